@@ -1,5 +1,8 @@
 // use std::env::current_dir;
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use cmake::Config;
 
 fn main() {
@@ -9,24 +12,34 @@ fn main() {
 
     // let directory = current_dir().unwrap();
 
+    // the stub changes the layout of the CPU classes, so the core and the
+    // bindings have to agree on whether it's there
+    let gdbstub = std::env::var_os("CARGO_FEATURE_GDBSTUB").is_some();
+
+    let core = patch_core();
+
     // build melonDS
-    let dst = Config::new("melonDS")
+    let dst = Config::new(&core)
         .define("BUILD_QT_SDL", "OFF")
         .define("ENABLE_JIT", "OFF")
-        .define("ENABLE_GDBSTUB", "OFF")
+        .define("ENABLE_GDBSTUB", if gdbstub { "ON" } else { "OFF" })
         .define("ENABLE_OGLRENDERER", "OFF")
         .build_target("all")
         .build();
 
-    cxx_build::bridge("src/melon/sys.rs")
-        .include("melonDS/src")
+    let mut bindings = cxx_build::bridge("src/melon/sys.rs");
+    if gdbstub {
+        bindings.define("GDBSTUB_ENABLED", None);
+    }
+
+    bindings
+        .include(core.join("src"))
         .include("src/melon/cpp")
-        .include("melonDS/src/frontend/glad")
+        .include(core.join("src/frontend/glad"))
         .file("src/melon/cpp/Platform.cpp")
         .file("src/melon/cpp/Shims.cpp")
         .file("src/melon/cpp/Util.cpp")
-        .file("melonDS/src/frontend/glad/glad.c")
-        // .define("GDBSTUB_ENABLED", None)
+        .file(core.join("src/frontend/glad/glad.c"))
         .flag_if_supported("-std=c++17")
         .flag_if_supported("-Wno-unused-parameter")
         .compile("melon-bindings"); // arbitrary library name, pick anything
@@ -42,4 +55,297 @@ fn main() {
     );
     println!("cargo:rustc-link-lib=static=core");
     println!("cargo:rustc-link-lib=static=teakra");
+    if gdbstub && std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+        println!("cargo:rustc-link-lib=ws2_32");
+    }
+
+    println!("cargo:rustc-env=MELONDS_VERSION={}", melonds_version());
+}
+
+/// Where a piece of code goes in one of melonDS's sources.
+enum Place {
+    /// At the top of the file.
+    Start,
+    /// At the bottom of the file.
+    End,
+    /// After the first line containing the text.
+    AfterFirst(&'static str),
+    /// After every line containing the text.
+    AfterEach(&'static str),
+    /// At the top of the body of every function defined with a signature
+    /// containing the text.
+    BodyOf(String),
+}
+
+/// The hooks melon-rs adds to the core's CPUs, which call back into it
+/// through the shims. Each goes in by finding where it belongs rather than by
+/// line number, so they keep applying as melonDS moves its code around.
+fn core_edits() -> Vec<(&'static str, Place, String)> {
+    let mut edits = vec![
+        (
+            "src/ARM.h",
+            Place::Start,
+            "#include <unordered_set>\n#include <vector>\n".to_owned(),
+        ),
+        (
+            "src/ARM.h",
+            Place::AfterFirst("virtual void Reset();"),
+            ARM_MEMBERS.to_owned(),
+        ),
+        (
+            "src/ARM.cpp",
+            Place::AfterEach("GdbCheckC();"),
+            "            if (InstrHook) CheckInstrHook(); // melon-rs\n".to_owned(),
+        ),
+        (
+            "src/debug/GdbStub.h",
+            Place::BodyOf("class StubCallbacks".to_owned()),
+            STUB_CALLBACK_MEMBERS.to_owned(),
+        ),
+        (
+            "src/debug/GdbStub.h",
+            Place::BodyOf("class GdbStub".to_owned()),
+            STUB_MEMBERS.to_owned(),
+        ),
+        (
+            "src/debug/GdbStub.cpp",
+            Place::BodyOf("StubState GdbStub::Enter(".to_owned()),
+            STUB_HELD_NOTICE.to_owned(),
+        ),
+        ("src/debug/GdbStub.cpp", Place::End, STUB_DETACH.to_owned()),
+    ];
+
+    let accesses = [
+        ("DataRead8", 1, false),
+        ("DataRead16", 2, false),
+        ("DataRead32", 4, false),
+        ("DataRead32S", 4, false),
+        ("DataWrite8", 1, true),
+        ("DataWrite16", 2, true),
+        ("DataWrite32", 4, true),
+        ("DataWrite32S", 4, true),
+    ];
+    for (function, width, write) in accesses {
+        let value = if write { "val" } else { "0" };
+        let code = format!(
+            "    if (MemHook) CheckMemHook(addr, {width}, {write}, {value}); // melon-rs\n"
+        );
+        // The ARM7's are defined in the header, and the ARM9's alongside its
+        // TCMs.
+        for file in ["src/ARM.h", "src/CP15.cpp"] {
+            edits.push((
+                file,
+                Place::BodyOf(format!("{function}(u32 addr")),
+                code.clone(),
+            ));
+        }
+    }
+
+    edits
+}
+
+/// Added to the ARM class, which both CPUs derive from.
+const ARM_MEMBERS: &str = "
+    // melon-rs: called before the instructions at HookAddrs, or before every
+    // instruction with HookAll set. Only checked while InstrHook is set
+    void (*InstrHook)(ARM& cpu, u32 addr) = nullptr;
+    std::unordered_set<u32> HookAddrs;
+    bool HookAll = false;
+
+    void CheckInstrHook()
+    {
+        u32 addr = R[15] - ((CPSR & 0x20) ? 2 : 4);
+        if (HookAll || HookAddrs.count(addr))
+            InstrHook(*this, addr);
+    }
+
+    // melon-rs: called before each data access to the 16 KB pages marked in
+    // WatchPages, with the value about to be written, if it's a write. Only
+    // checked while MemHook is set
+    void (*MemHook)(ARM& cpu, u32 addr, u32 width, bool write, u32 value) = nullptr;
+    std::vector<bool> WatchPages;
+
+    void CheckMemHook(u32 addr, u32 width, bool write, u32 value)
+    {
+        if (WatchPages[addr >> 14])
+            MemHook(*this, addr, width, write, value);
+    }
+
+#ifdef GDBSTUB_ENABLED
+    // melon-rs: called when the GDB stub starts holding the CPU, and when it
+    // lets go
+    void (*StubHook)(ARM& cpu, bool stopped) = nullptr;
+
+    void StateChanged(bool stopped) override
+    {
+        if (StubHook)
+            StubHook(*this, stopped);
+    }
+
+    void DetachStub() { GdbStub.Detach(); }
+#endif
+";
+
+/// Added to the interface the GDB stub drives each CPU through.
+const STUB_CALLBACK_MEMBERS: &str = "
+public:
+    // melon-rs: told when the stub starts holding the CPU, and when it lets go
+    virtual void StateChanged(bool stopped) {}
+";
+
+/// Added to the GDB stub itself.
+const STUB_MEMBERS: &str = "
+public:
+    // melon-rs: drops the debugger and stops listening for another, letting go
+    // of a CPU held in Enter. Safe from any thread, as it only shuts sockets
+    void Detach();
+";
+
+/// At the top of `GdbStub::Enter`, which holds the CPU until the debugger
+/// lets it go when called to stay.
+const STUB_HELD_NOTICE: &str = "
+    // melon-rs: the CPU hears it's held for as long as this call stays
+    struct HeldNotice
+    {
+        StubCallbacks *Callbacks;
+        bool Held;
+
+        HeldNotice(StubCallbacks *callbacks, bool held) : Callbacks(callbacks), Held(held)
+        {
+            if (Held) Callbacks->StateChanged(true);
+        }
+        ~HeldNotice()
+        {
+            if (Held) Callbacks->StateChanged(false);
+        }
+    } heldNotice(Cb, stay);
+";
+
+const STUB_DETACH: &str = "
+// melon-rs: shutting the sockets down wakes Enter from its wait with the
+// connection gone, and leaves nothing to accept another. 2 is SHUT_RDWR, and
+// SD_BOTH on Windows
+void Gdb::GdbStub::Detach()
+{
+    if (ConnFd > 0) shutdown(ConnFd, 2);
+    if (SockFd > 0) shutdown(SockFd, 2);
+}
+";
+
+/// Copies melonDS's sources into the build directory with melon-rs's hooks
+/// added, returning where they are. The submodule itself is left as it is,
+/// and every build patches it afresh, so edits added since the last build
+/// always make it in.
+fn patch_core() -> PathBuf {
+    let core = PathBuf::from(std::env::var_os("OUT_DIR").unwrap()).join("melonDS");
+
+    let mut files: Vec<(&str, Vec<(Place, String)>)> = Vec::new();
+    for (file, place, code) in core_edits() {
+        match files.iter_mut().find(|(name, _)| *name == file) {
+            Some((_, edits)) => edits.push((place, code)),
+            None => files.push((file, vec![(place, code)])),
+        }
+    }
+
+    let mut patched = Vec::new();
+    for (file, edits) in files {
+        let path = Path::new("melonDS").join(file);
+        let source = fs::read_to_string(&path).unwrap();
+        assert!(
+            !source.contains("melon-rs"),
+            "melonDS/{file} was patched in place by an older build; \
+            restore it with `git -C melonDS checkout -- {file}`"
+        );
+
+        let mut lines: Vec<String> = source.lines().map(str::to_owned).collect();
+        for (place, code) in edits {
+            let at = insertion_points(&lines, &place);
+            assert!(
+                !at.is_empty(),
+                "melonDS has changed: nowhere to hook {file}"
+            );
+            for &index in at.iter().rev() {
+                lines.insert(index, code.trim_end_matches('\n').to_owned());
+            }
+        }
+
+        patched.push((path, lines.join("\n") + "\n"));
+    }
+
+    copy_tree(Path::new("melonDS"), &core, &patched);
+    core
+}
+
+/// Mirrors the files under `from` at `to`, leaving out git's own, and with
+/// the contents given in `patched` for those it names.
+fn copy_tree(from: &Path, to: &Path, patched: &[(PathBuf, String)]) {
+    fs::create_dir_all(to).unwrap();
+
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        if path.is_dir() {
+            copy_tree(&path, &target, patched);
+        } else if let Some((_, source)) = patched.iter().find(|(file, _)| *file == path) {
+            write_if_changed(&target, source.as_bytes());
+        } else {
+            write_if_changed(&target, &fs::read(&path).unwrap());
+        }
+    }
+}
+
+/// Writes `contents` to `path` unless they're there already, so that CMake
+/// only rebuilds what has changed.
+fn write_if_changed(path: &Path, contents: &[u8]) {
+    if fs::read(path).is_ok_and(|existing| existing == contents) {
+        return;
+    }
+    fs::write(path, contents).unwrap();
+}
+
+/// The line indices `place` puts code before.
+fn insertion_points(lines: &[String], place: &Place) -> Vec<usize> {
+    let containing = |text: &str| -> Vec<usize> {
+        (0..lines.len())
+            .filter(|&i| lines[i].contains(text))
+            .collect()
+    };
+
+    match place {
+        Place::Start => vec![0],
+        Place::End => vec![lines.len()],
+        Place::AfterFirst(text) => containing(text)
+            .into_iter()
+            .take(1)
+            .map(|i| i + 1)
+            .collect(),
+        Place::AfterEach(text) => containing(text).into_iter().map(|i| i + 1).collect(),
+        Place::BodyOf(signature) => containing(signature)
+            .into_iter()
+            // Declarations have no body.
+            .filter(|&i| !lines[i].trim_end().ends_with(';'))
+            .filter_map(|i| (i..lines.len()).find(|&j| lines[j].contains('{')))
+            .map(|i| i + 1)
+            .collect(),
+    }
+}
+
+/// The version melonDS declares in its top-level `project(...)` call, which
+/// replays record so that a mismatched core can be pointed out.
+fn melonds_version() -> String {
+    let cmake = fs::read_to_string("melonDS/CMakeLists.txt").unwrap();
+    let project = &cmake[cmake.find("project(melonDS").unwrap()..];
+    let project = &project[..project.find(')').unwrap()];
+
+    let mut words = project.split_whitespace();
+    words
+        .find(|&word| word == "VERSION")
+        .and_then(|_| words.next())
+        .unwrap_or("unknown")
+        .to_owned()
 }
@@ -1,36 +1,33 @@
-use std::sync::{Arc, OnceLock};
+use std::sync::Arc;
 
 use egui::{
-    Color32, ColorImage, Context, Pos2, Rect, TextureHandle, TextureOptions, Ui, Vec2,
-    ViewportBuilder,
+    Color32, ColorImage, Context, Id, Pos2, Rect, Sense, TextureHandle, TextureOptions, Ui, Vec2,
+    ViewportBuilder, ViewportCommand, ViewportId,
 };
 use tokio::sync::watch;
 
 use crate::frontend::Frames;
 use crate::input::{InputBridge, InputEvent, Modifiers, TouchPoint};
-use crate::render::{draw_screen, RenderContext, RenderHook, RenderStatus, ScreenRect};
-use crate::EmuStateChange;
-
-pub const SCREEN_WIDTH: usize = 256;
-pub const SCREEN_HEIGHT: usize = 192;
-
-/// Handle the emulator uses to wake the UI once a frame is ready.
-///
-/// eframe creates the [`Context`], so the emulator task cannot be handed one
-/// when it is spawned; it takes this and waits for the first repaint to fill it.
-pub type RepaintHandle = Arc<OnceLock<Context>>;
+use crate::panels::Panel;
+use crate::render::{
+    draw_screen, RenderContext, RenderHook, RenderStatus, RepaintHandle, ScreenRect, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+use crate::{EmuState, EmuStateChange};
 
 pub fn native_options() -> eframe::NativeOptions {
     eframe::NativeOptions {
-        viewport: ViewportBuilder::default().with_inner_size([
-            SCREEN_WIDTH as f32,
-            (2 * SCREEN_HEIGHT) as f32,
-        ]),
+        viewport: window(),
         renderer: eframe::Renderer::Glow,
         ..Default::default()
     }
 }
 
+/// A window the size of the two screens.
+fn window() -> ViewportBuilder {
+    ViewportBuilder::default().with_inner_size([SCREEN_WIDTH as f32, (2 * SCREEN_HEIGHT) as f32])
+}
+
 /// Draws the two screens and forwards host input.
 ///
 /// Deliberately an observer: it reads the latest frame and pushes events into
@@ -43,6 +40,11 @@ pub struct App {
     render_hooks: Vec<Box<dyn RenderHook>>,
     bridge: InputBridge,
     state_tx: watch::Sender<Option<EmuStateChange>>,
+    /// Debugging windows, and whether each is open.
+    panels: Vec<(Box<dyn Panel>, bool)>,
+    /// The other consoles being played, each drawn in a window of its own
+    /// with this title.
+    players: Vec<(String, App)>,
     top: TextureHandle,
     bottom: TextureHandle,
 }
@@ -57,7 +59,29 @@ impl App {
         state_tx: watch::Sender<Option<EmuStateChange>>,
         repaint: &RepaintHandle,
     ) -> Self {
-        let _ = repaint.set(cc.egui_ctx.clone());
+        Self::for_context(
+            &cc.egui_ctx,
+            frames,
+            status,
+            render_hooks,
+            bridge,
+            state_tx,
+            repaint,
+        )
+    }
+
+    /// An app drawing into `ctx`, which may belong to another app: the other
+    /// consoles given to [`App::with_players`] share the main one's.
+    pub fn for_context(
+        ctx: &Context,
+        frames: watch::Receiver<Arc<Frames>>,
+        status: watch::Receiver<RenderStatus>,
+        render_hooks: Vec<Box<dyn RenderHook>>,
+        bridge: InputBridge,
+        state_tx: watch::Sender<Option<EmuStateChange>>,
+        repaint: &RepaintHandle,
+    ) -> Self {
+        let _ = repaint.set(ctx.clone());
 
         let blank = ColorImage::new(
             [SCREEN_WIDTH, SCREEN_HEIGHT],
@@ -70,15 +94,24 @@ impl App {
             render_hooks,
             bridge,
             state_tx,
-            top: cc
-                .egui_ctx
-                .load_texture("top_screen", blank.clone(), TextureOptions::NEAREST),
-            bottom: cc
-                .egui_ctx
-                .load_texture("bottom_screen", blank, TextureOptions::NEAREST),
+            panels: Vec::new(),
+            players: Vec::new(),
+            top: ctx.load_texture("top_screen", blank.clone(), TextureOptions::NEAREST),
+            bottom: ctx.load_texture("bottom_screen", blank, TextureOptions::NEAREST),
         }
     }
 
+    pub fn with_panels(mut self, panels: impl IntoIterator<Item = Box<dyn Panel>>) -> Self {
+        self.panels
+            .extend(panels.into_iter().map(|panel| (panel, false)));
+        self
+    }
+
+    pub fn with_players(mut self, players: impl IntoIterator<Item = (String, App)>) -> Self {
+        self.players.extend(players);
+        self
+    }
+
     /// Uploads the latest pair of screens, if the emulator has drawn any since
     /// the last repaint. Resizing the window should not cost two conversions.
     fn upload_frames(&mut self) {
@@ -100,7 +133,8 @@ impl App {
         let status = *self.status.borrow();
         let ctx = RenderContext {
             frame: status.frame,
-            paused: status.paused,
+            paused: status.state != EmuState::Running,
+            speed: status.speed,
             top_screen: screen_rect(top_screen),
             bottom_screen: screen_rect(bottom_screen),
         };
@@ -137,8 +171,64 @@ impl App {
         (top_screen, bottom_screen)
     }
 
+    /// Lists the panels in a menu opened by right-clicking the screens, and
+    /// draws the open ones.
+    fn show_panels(&mut self, ui: &Ui, screens: Rect) {
+        if self.panels.is_empty() {
+            return;
+        }
+
+        // Only the primary button touches the screen, so the secondary one is
+        // free for the menu.
+        ui.interact(screens, Id::new("screens"), Sense::click())
+            .context_menu(|ui| {
+                for (panel, open) in &mut self.panels {
+                    ui.checkbox(open, panel.title());
+                }
+            });
+
+        for (panel, open) in &mut self.panels {
+            egui::Window::new(panel.title().to_owned())
+                .open(open)
+                .show(ui.ctx(), |ui| panel.ui(ui));
+        }
+    }
+
     fn request_stop(&self) {
         let _ = self.state_tx.send(Some(EmuStateChange::Stop));
+        for (_, player) in &self.players {
+            player.request_stop();
+        }
+    }
+
+    /// Everything drawn for the console in its window.
+    fn show(&mut self, ui: &mut Ui) {
+        self.upload_frames();
+        let (top_screen, bottom_screen) = self.draw_screens(ui);
+        self.invoke_render_hooks(ui, top_screen, bottom_screen);
+        self.show_panels(ui, top_screen.union(bottom_screen));
+        *self.bridge.bottom_screen.lock().unwrap() = Some(bottom_screen);
+    }
+
+    /// Draws each of the other consoles in its window. Their input arrives
+    /// with their window rather than through [`eframe::App::raw_input_hook`],
+    /// and closing any of them closes the lot.
+    fn show_players(&mut self, ctx: &Context) {
+        for (i, (title, player)) in self.players.iter_mut().enumerate() {
+            let id = ViewportId::from_hash_of(("player", i));
+            ctx.show_viewport_immediate(id, window().with_title(title.as_str()), |ui, _| {
+                let raw = ui.input(|input| input.raw.clone());
+                let screen = player.touch_screen(&raw);
+                player.forward_input(ui.ctx(), screen, &raw.events);
+
+                player.show(ui);
+
+                if ui.input(|input| input.viewport().close_requested()) {
+                    ui.ctx()
+                        .send_viewport_cmd_to(ViewportId::ROOT, ViewportCommand::Close);
+                }
+            });
+        }
     }
 
     fn touch_screen(&self, raw: &egui::RawInput) -> Rect {
@@ -185,10 +275,8 @@ impl eframe::App for App {
     }
 
     fn ui(&mut self, ui: &mut Ui, _frame: &mut eframe::Frame) {
-        self.upload_frames();
-        let (top_screen, bottom_screen) = self.draw_screens(ui);
-        self.invoke_render_hooks(ui, top_screen, bottom_screen);
-        *self.bridge.bottom_screen.lock().unwrap() = Some(bottom_screen);
+        self.show(ui);
+        self.show_players(&ui.ctx().clone());
     }
 
     /// Letterboxing around the screens, rather than egui's window colour.
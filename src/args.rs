@@ -8,6 +8,30 @@ pub struct Args {
     #[arg(short, long)]
     pub game: Option<PathBuf>,
 
+    /// Encode video and audio to `<ENCODE>.y4m` and `<ENCODE>.wav`, starting
+    /// from the first frame
+    #[arg(long)]
+    pub encode: Option<PathBuf>,
+
+    /// Serve the ARM9 to gdb on this port. Needs a build with the `gdbstub`
+    /// feature
+    #[arg(long)]
+    pub gdb_arm9: Option<u16>,
+
+    /// Serve the ARM7 to gdb on this port. Needs a build with the `gdbstub`
+    /// feature
+    #[arg(long)]
+    pub gdb_arm7: Option<u16>,
+
+    /// Stop both CPUs before the first instruction, until gdb continues them
+    #[arg(long)]
+    pub gdb_break: bool,
+
+    /// Run this Lua script alongside the game. Needs a build with the `lua`
+    /// feature
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -20,6 +44,10 @@ pub enum Commands {
     Replay(ReplayArgs),
     /// Record a new replay
     Record(RecordArgs),
+    /// Encode an existing replay to video and audio, without opening a window
+    Render(RenderArgs),
+    /// Print the disassembly of a range of memory, without opening a window
+    Disasm(DisasmArgs),
 }
 
 #[derive(Debug, Parser)]
@@ -31,12 +59,31 @@ pub struct PlayArgs {
     /// Disable loading a save file, even if a default is provided by the config
     #[arg(long)]
     pub no_save: bool,
+
+    /// Play the game on this many consoles, each in its own window and all
+    /// in wireless range of each other. Only the first gets the save file
+    #[arg(long, default_value_t = 1)]
+    pub players: usize,
+
+    /// A YAML file holding a key map for the second player onwards, in the
+    /// config's `key_map` format. Give it once per player, in order, to take
+    /// the place of the config's `player_key_maps`
+    #[arg(long = "key-map")]
+    pub key_maps: Vec<PathBuf>,
 }
 
 #[derive(Debug, Parser)]
 pub struct ReplayArgs {
     /// The path that the replay will be loaded from
     pub name: PathBuf,
+
+    /// Play the replay even if it was recorded on a different game or save
+    #[arg(long)]
+    pub force: bool,
+
+    /// Pause at the first frame that doesn't match the recording
+    #[arg(long)]
+    pub pause_on_desync: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -45,9 +92,21 @@ pub struct RecordArgs {
     pub name: PathBuf,
 
     /// The path of the save file to load. Defaults to no save
-    #[arg(long, short)]
+    #[arg(long, short, conflicts_with_all = ["savestate", "clean"])]
     pub save: Option<PathBuf>,
 
+    /// Start the recording from a savestate instead of from power-on
+    #[arg(long, conflicts_with = "clean")]
+    pub savestate: Option<PathBuf>,
+
+    /// Copy the savestate into the replay, rather than referring to its path
+    #[arg(long, requires = "savestate")]
+    pub embed: bool,
+
+    /// Start from power-on with no save file at all, keeping only the timestamp
+    #[arg(long)]
+    pub clean: bool,
+
     /// The timestamp to begin emulation at. Defaults to current time.
     /// Datetimes should be written in the format `YYYY-MM-DDT12:34:56+0000`
     #[arg(long, short)]
@@ -57,3 +116,52 @@ pub struct RecordArgs {
     #[arg(long, short)]
     pub author: Option<String>,
 }
+
+#[derive(Debug, Parser)]
+pub struct RenderArgs {
+    /// The path that the replay will be loaded from
+    pub name: PathBuf,
+
+    /// Where to write the encoding: `<OUTPUT>.y4m` and `<OUTPUT>.wav`
+    #[arg(long, short)]
+    pub output: PathBuf,
+
+    /// Render the replay even if it was recorded on a different game or save
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct DisasmArgs {
+    /// The address to start from, in hex
+    #[arg(value_parser = parse_address)]
+    pub start: u32,
+
+    /// How many instructions to print
+    #[arg(long, short, default_value_t = 32)]
+    pub count: usize,
+
+    /// Read memory as the ARM7 sees it, rather than the ARM9
+    #[arg(long)]
+    pub arm7: bool,
+
+    /// Decode Thumb instructions rather than ARM ones
+    #[arg(long)]
+    pub thumb: bool,
+
+    /// How many frames to run first, so that the game has loaded its code
+    #[arg(long, short, default_value_t = 0)]
+    pub frames: u64,
+
+    /// The path of the save file to load. Defaults to no save
+    #[arg(long, short)]
+    pub save: Option<PathBuf>,
+}
+
+fn parse_address(text: &str) -> Result<u32, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16).map_err(|err| err.to_string())
+}
@@ -1,18 +1,26 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+// Without the `gui` feature there is no device, and only the pacing is used.
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+#[cfg(feature = "gui")]
 use std::time::Duration;
 
+#[cfg(feature = "gui")]
 use rodio::{OutputStream, Sink, Source};
 use rtrb::{Consumer, Producer, RingBuffer};
 
 use crate::melon::nds::Nds;
+use crate::pacing::Pacing;
 
 /// An open output device. Playback stops when this is dropped.
+#[cfg(feature = "gui")]
 pub struct Playback {
     _output_stream: OutputStream,
     _sink: Sink,
 }
 
+#[cfg(feature = "gui")]
 impl Playback {
     /// Opens the default device, returning it alongside the producer handle.
     pub fn start() -> (Self, Audio) {
@@ -48,13 +56,23 @@ impl Audio {
 
         let audio = Audio {
             pairs,
-            pace: Pace::new(),
+            pace: Pace::new(Pacing::default()),
         };
         let stream = Stream::new(consumer, dry);
 
         (audio, stream)
     }
 
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pace = Pace::new(pacing);
+    }
+
+    /// How much audio is waiting for the device, as a fraction of the backlog
+    /// the ring aims to hold.
+    pub fn backlog(&self) -> f64 {
+        (Self::CAPACITY - self.pairs.slots()) as f64 / Pace::TARGET as f64
+    }
+
     /// Queues one emulated frame of audio, reporting the skew to resample with
     pub fn submit(&mut self, frame: &[[i16; 2]]) -> f64 {
         let _reserve = Self::CAPACITY - self.pairs.slots();
@@ -72,6 +90,32 @@ impl Audio {
     }
 }
 
+/// A console's audio backlog, as of the tick it last scheduled, for the
+/// consoles it plays alongside to follow. Only one console is heard, so the
+/// others have no backlog of their own to keep pace with.
+#[derive(Debug, Clone)]
+pub struct SharedBacklog(Arc<AtomicU64>);
+
+impl Default for SharedBacklog {
+    /// A backlog with nothing being played yet.
+    fn default() -> Self {
+        SharedBacklog(Arc::new(AtomicU64::new(f64::NAN.to_bits())))
+    }
+}
+
+impl SharedBacklog {
+    /// Records `backlog`, or that nothing is being played.
+    pub fn set(&self, backlog: Option<f64>) {
+        let bits = backlog.unwrap_or(f64::NAN).to_bits();
+        self.0.store(bits, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Option<f64> {
+        let backlog = f64::from_bits(self.0.load(Ordering::Relaxed));
+        (!backlog.is_nan()).then_some(backlog)
+    }
+}
+
 /// The device's end of the ring, handing out one channel per call.
 struct Stream {
     pairs: Consumer<[i16; 2]>,
@@ -113,6 +157,7 @@ impl Iterator for Stream {
     }
 }
 
+#[cfg(feature = "gui")]
 impl Source for Stream {
     fn current_frame_len(&self) -> Option<usize> {
         None
@@ -136,9 +181,15 @@ impl Source for Stream {
 /// The backlog is the integral of the difference between the two rates, so
 /// stepping the skew while it sits outside the target range cancels a constant
 /// clock offset and then holds still.
+///
+/// Under [`Pacing::Audio`] the frames are scheduled to match the device
+/// instead, and the skew stays where it started.
 #[derive(Debug)]
 struct Pace {
     skew: f64,
+    /// Where one emulated frame of audio lasts exactly one scheduled frame.
+    calibrated: f64,
+    fixed: bool,
 }
 
 impl Pace {
@@ -149,31 +200,42 @@ impl Pace {
     /// after this is the margin that absorbs a late frame.
     const SLACK: usize = Self::TARGET / 8;
 
-    /// Where one emulated frame is worth exactly a sixtieth of a second.
-    const CALIBRATED: f64 = 60.0 / Nds::NATIVE_FRAME_RATE;
-
     /// One step is 0.01%, or 0.17 cents of pitch, and cancels 100ppm of error.
-    const STEP: f64 = Self::CALIBRATED * 0.0001;
+    const STEP: f64 = 0.0001;
 
-    const MIN: f64 = Self::CALIBRATED * 0.995;
-    const MAX: f64 = Self::CALIBRATED * 1.005;
+    /// How far the skew may stray from calibrated, either way.
+    const RANGE: f64 = 0.005;
 
-    fn new() -> Self {
+    fn new(pacing: Pacing) -> Self {
         Pace {
-            skew: Self::CALIBRATED,
+            skew: pacing.calibrated_skew(),
+            calibrated: pacing.calibrated_skew(),
+            fixed: !pacing.adjusts_skew(),
         }
     }
 
+    fn min(&self) -> f64 {
+        self.calibrated * (1.0 - Self::RANGE)
+    }
+
+    fn max(&self) -> f64 {
+        self.calibrated * (1.0 + Self::RANGE)
+    }
+
     /// Reports the skew to resample with, for a ring holding `backlog` pairs.
     fn adjust(&mut self, backlog: usize) -> f64 {
+        if self.fixed {
+            return self.skew;
+        }
+
         // Pairs per frame scale with the reciprocal of the skew.
         if backlog < Self::TARGET - Self::SLACK {
-            self.skew -= Self::STEP;
+            self.skew -= self.calibrated * Self::STEP;
         } else if backlog > Self::TARGET + Self::SLACK {
-            self.skew += Self::STEP;
+            self.skew += self.calibrated * Self::STEP;
         }
 
-        self.skew = self.skew.clamp(Self::MIN, Self::MAX);
+        self.skew = self.skew.clamp(self.min(), self.max());
 
         self.skew
     }
@@ -183,6 +245,8 @@ impl Pace {
 mod tests {
     use super::*;
 
+    const CALIBRATED: f64 = 60.0 / Nds::NATIVE_FRAME_RATE;
+
     /// Both ends of a ring holding `pairs`, with no device behind it.
     fn ring(pairs: &[[i16; 2]]) -> (Audio, Stream) {
         let (mut producer, consumer) = RingBuffer::new(Audio::CAPACITY);
@@ -191,7 +255,7 @@ mod tests {
         let dry = Arc::new(AtomicUsize::new(0));
         let audio = Audio {
             pairs: producer,
-            pace: Pace::new(),
+            pace: Pace::new(Pacing::Sixty),
         };
 
         (audio, Stream::new(consumer, dry))
@@ -226,7 +290,7 @@ mod tests {
     fn submitting_into_a_thin_ring_asks_for_more_audio() {
         let (mut audio, _stream) = ring(&[]);
 
-        assert!(audio.submit(&[]) < Pace::CALIBRATED);
+        assert!(audio.submit(&[]) < CALIBRATED);
     }
 
     #[test]
@@ -243,24 +307,24 @@ mod tests {
 
     #[test]
     fn a_thin_backlog_steps_the_skew_down() {
-        assert!(Pace::new().adjust(0) < Pace::CALIBRATED);
+        assert!(Pace::new(Pacing::Sixty).adjust(0) < CALIBRATED);
     }
 
     #[test]
     fn a_deep_backlog_steps_the_skew_up() {
-        assert!(Pace::new().adjust(Audio::CAPACITY) > Pace::CALIBRATED);
+        assert!(Pace::new(Pacing::Sixty).adjust(Audio::CAPACITY) > CALIBRATED);
     }
 
     #[test]
     fn a_backlog_in_range_is_left_alone() {
-        assert_eq!(Pace::new().adjust(Pace::TARGET), Pace::CALIBRATED);
+        assert_eq!(Pace::new(Pacing::Sixty).adjust(Pace::TARGET), CALIBRATED);
     }
 
     /// The correction has to survive the backlog recovering, or the drift it
     /// cancels would simply resume.
     #[test]
     fn a_correction_outlives_the_backlog_that_caused_it() {
-        let mut pace = Pace::new();
+        let mut pace = Pace::new(Pacing::Sixty);
         let corrected = pace.adjust(0);
 
         assert_eq!(pace.adjust(Pace::TARGET), corrected);
@@ -268,15 +332,50 @@ mod tests {
 
     #[test]
     fn corrections_are_bounded_either_way() {
-        let mut thin = Pace::new();
-        let mut deep = Pace::new();
+        let mut thin = Pace::new(Pacing::Sixty);
+        let mut deep = Pace::new(Pacing::Sixty);
 
         for _ in 0..1000 {
             thin.adjust(0);
             deep.adjust(Audio::CAPACITY);
         }
 
-        assert_eq!(thin.adjust(0), Pace::MIN);
-        assert_eq!(deep.adjust(Audio::CAPACITY), Pace::MAX);
+        assert_eq!(thin.adjust(0), thin.min());
+        assert_eq!(deep.adjust(Audio::CAPACITY), deep.max());
+    }
+
+    #[test]
+    fn native_pacing_calibrates_to_no_skew() {
+        assert_eq!(Pace::new(Pacing::Native).adjust(Pace::TARGET), 1.0);
+    }
+
+    #[test]
+    fn audio_pacing_never_moves_the_skew() {
+        let mut pace = Pace::new(Pacing::Audio);
+
+        assert_eq!(pace.adjust(0), 1.0);
+        assert_eq!(pace.adjust(Audio::CAPACITY), 1.0);
+    }
+
+    #[test]
+    fn the_backlog_is_measured_against_the_target() {
+        let (mut audio, _stream) = ring(&[]);
+        assert_eq!(audio.backlog(), 0.0);
+
+        audio.submit(&vec![[0, 0]; Pace::TARGET]);
+        assert_eq!(audio.backlog(), 1.0);
+    }
+
+    #[test]
+    fn a_shared_backlog_reads_back_on_another_handle() {
+        let backlog = SharedBacklog::default();
+        let follower = backlog.clone();
+        assert_eq!(follower.get(), None);
+
+        backlog.set(Some(1.25));
+        assert_eq!(follower.get(), Some(1.25));
+
+        backlog.set(None);
+        assert_eq!(follower.get(), None);
     }
 }
@@ -4,11 +4,15 @@ use chrono::{DateTime, Utc};
 use egui::Key;
 use serde::{Deserialize, Serialize};
 
+use crate::debug::{GdbSettings, TraceSettings, WatchSettings};
 use crate::frontend::ReplayState;
 use crate::input::{
-    Binding, ConsoleBinding, ConsoleButton, FrontendCommand, KeyCombination, Modifiers,
+    Binding, ConsoleBinding, ConsoleButton, FrontendCommand, HeldCommand, KeyCombination, Modifiers,
 };
-use crate::replay::Replay;
+use crate::pacing::Pacing;
+use crate::replay::{CheckpointSettings, Replay};
+use crate::rewind::RewindSettings;
+use crate::speed::Speed;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Config {
@@ -16,6 +20,18 @@ pub struct Config {
     pub default_save_path: Option<PathBuf>,
     pub timestamp: Option<DateTime<Utc>>,
     pub key_map: HashMap<KeyCombination, Binding>,
+    /// The key maps of the second player onwards, when playing with several.
+    /// Players without one use `key_map`.
+    pub player_key_maps: Vec<HashMap<KeyCombination, Binding>>,
+    pub checkpoints: CheckpointSettings,
+    pub rewind: RewindSettings,
+    /// The speed the turbo bindings switch to.
+    pub turbo_speed: Speed,
+    pub pacing: Pacing,
+    pub gdb: Option<GdbSettings>,
+    pub watch: WatchSettings,
+    /// Traces can only be taken if this is set, as it needs the debugger.
+    pub trace: Option<TraceSettings>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -68,6 +84,14 @@ impl Default for Config {
                 Binding::Command(FrontendCommand::WriteSavedata(String::from("save.bin"))),
             )])
             .collect(),
+            player_key_maps: Vec::new(),
+            checkpoints: CheckpointSettings::default(),
+            rewind: RewindSettings::default(),
+            turbo_speed: Speed::Quadruple,
+            pacing: Pacing::default(),
+            gdb: None,
+            watch: WatchSettings::default(),
+            trace: None,
         }
     }
 }
@@ -131,6 +155,12 @@ pub enum ConfigBinding {
     WriteMainRam(String),
     ToggleReplayMode,
     SaveReplay,
+    ToggleEncoding(String),
+    ToggleTrace(String),
+    Rewind,
+    SetSpeed(Speed),
+    ToggleTurbo,
+    Turbo,
 }
 
 impl From<ConfigBinding> for Binding {
@@ -155,6 +185,16 @@ impl From<ConfigBinding> for Binding {
             }
             ConfigBinding::ToggleReplayMode => Binding::Command(FrontendCommand::ToggleReplayMode),
             ConfigBinding::SaveReplay => Binding::Command(FrontendCommand::SaveReplay),
+            ConfigBinding::ToggleEncoding(path) => {
+                Binding::Command(FrontendCommand::ToggleEncoding(path))
+            }
+            ConfigBinding::ToggleTrace(path) => {
+                Binding::Command(FrontendCommand::ToggleTrace(path))
+            }
+            ConfigBinding::Rewind => Binding::Held(HeldCommand::Rewind),
+            ConfigBinding::SetSpeed(speed) => Binding::Command(FrontendCommand::SetSpeed(speed)),
+            ConfigBinding::ToggleTurbo => Binding::Command(FrontendCommand::ToggleTurbo),
+            ConfigBinding::Turbo => Binding::Held(HeldCommand::Turbo),
         }
     }
 }
@@ -181,6 +221,16 @@ impl From<Binding> for ConfigBinding {
             }
             Binding::Command(FrontendCommand::ToggleReplayMode) => ConfigBinding::ToggleReplayMode,
             Binding::Command(FrontendCommand::SaveReplay) => ConfigBinding::SaveReplay,
+            Binding::Command(FrontendCommand::ToggleEncoding(path)) => {
+                ConfigBinding::ToggleEncoding(path)
+            }
+            Binding::Command(FrontendCommand::ToggleTrace(path)) => {
+                ConfigBinding::ToggleTrace(path)
+            }
+            Binding::Held(HeldCommand::Rewind) => ConfigBinding::Rewind,
+            Binding::Command(FrontendCommand::SetSpeed(speed)) => ConfigBinding::SetSpeed(speed),
+            Binding::Command(FrontendCommand::ToggleTurbo) => ConfigBinding::ToggleTurbo,
+            Binding::Held(HeldCommand::Turbo) => ConfigBinding::Turbo,
         }
     }
 }
@@ -191,6 +241,26 @@ pub struct ConfigFile {
     pub default_save_path: Option<PathBuf>,
     pub timestamp: Option<DateTime<Utc>>,
     pub key_map: Vec<ConfigKeyMapEntry>,
+    #[serde(default)]
+    pub player_key_maps: Vec<Vec<ConfigKeyMapEntry>>,
+    #[serde(default)]
+    pub checkpoints: CheckpointSettings,
+    #[serde(default)]
+    pub rewind: RewindSettings,
+    #[serde(default = "default_turbo_speed")]
+    pub turbo_speed: Speed,
+    #[serde(default)]
+    pub pacing: Pacing,
+    #[serde(default)]
+    pub gdb: Option<GdbSettings>,
+    #[serde(default)]
+    pub watch: WatchSettings,
+    #[serde(default)]
+    pub trace: Option<TraceSettings>,
+}
+
+fn default_turbo_speed() -> Speed {
+    Config::default().turbo_speed
 }
 
 impl From<KeyEntry> for KeyCombination {
@@ -208,11 +278,19 @@ impl From<ConfigFile> for Config {
             default_game_path: value.default_game_path,
             default_save_path: value.default_save_path,
             timestamp: value.timestamp,
-            key_map: value
-                .key_map
+            key_map: key_map_from_entries(value.key_map),
+            player_key_maps: value
+                .player_key_maps
                 .into_iter()
-                .map(|entry| (entry.key.into(), entry.binding.into()))
+                .map(key_map_from_entries)
                 .collect(),
+            checkpoints: value.checkpoints,
+            rewind: value.rewind,
+            turbo_speed: value.turbo_speed,
+            pacing: value.pacing,
+            gdb: value.gdb,
+            watch: value.watch,
+            trace: value.trace,
         }
     }
 }
@@ -223,18 +301,41 @@ impl From<Config> for ConfigFile {
             default_game_path: value.default_game_path,
             default_save_path: value.default_save_path,
             timestamp: value.timestamp,
-            key_map: value
-                .key_map
+            key_map: key_map_entries(value.key_map),
+            player_key_maps: value
+                .player_key_maps
                 .into_iter()
-                .map(|(key, binding)| ConfigKeyMapEntry {
-                    key: key.into(),
-                    binding: binding.into(),
-                })
+                .map(key_map_entries)
                 .collect(),
+            checkpoints: value.checkpoints,
+            rewind: value.rewind,
+            turbo_speed: value.turbo_speed,
+            pacing: value.pacing,
+            gdb: value.gdb,
+            watch: value.watch,
+            trace: value.trace,
         }
     }
 }
 
+/// A key map as it is spelled in a file.
+pub fn key_map_from_entries(entries: Vec<ConfigKeyMapEntry>) -> HashMap<KeyCombination, Binding> {
+    entries
+        .into_iter()
+        .map(|entry| (entry.key.into(), entry.binding.into()))
+        .collect()
+}
+
+fn key_map_entries(key_map: HashMap<KeyCombination, Binding>) -> Vec<ConfigKeyMapEntry> {
+    key_map
+        .into_iter()
+        .map(|(key, binding)| ConfigKeyMapEntry {
+            key: key.into(),
+            binding: binding.into(),
+        })
+        .collect()
+}
+
 impl From<KeyCombination> for KeyEntry {
     fn from(value: KeyCombination) -> Self {
         KeyEntry {
@@ -0,0 +1,680 @@
+//! A disassembler for the ARMv5TE instruction set the ARM9 runs, and the
+//! ARMv4T subset of it the ARM7 runs, in both ARM and Thumb state.
+//!
+//! The output follows the usual assembler spelling, lower case, with branch
+//! targets and PC-relative loads resolved to absolute addresses.
+
+use std::fmt;
+
+/// One decoded instruction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Instruction {
+    pub addr: u32,
+    /// The instruction word. Thumb's two-halfword BL is kept as the first
+    /// halfword in the low bits and the second in the high bits, the way it
+    /// sits in memory.
+    pub opcode: u32,
+    /// In bytes: 4 in ARM state, and 2 in Thumb state except for BL.
+    pub len: u32,
+    pub text: String,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opcode = match self.len {
+            2 => format!("{:04x}", self.opcode),
+            _ => format!("{:08x}", self.opcode),
+        };
+        write!(f, "{:08x}: {opcode:<8}  {}", self.addr, self.text)
+    }
+}
+
+/// Disassembles `count` instructions from `addr`, reading code with
+/// `fetch(addr, width)`, where `width` is 2 or 4 bytes.
+pub fn disassemble(
+    addr: u32,
+    count: usize,
+    thumb: bool,
+    mut fetch: impl FnMut(u32, u32) -> u32,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut addr = addr;
+
+    for _ in 0..count {
+        let instruction = if thumb {
+            let opcode = fetch(addr, 2) as u16;
+            let next = fetch(addr.wrapping_add(2), 2) as u16;
+            disassemble_thumb(addr, opcode, next)
+        } else {
+            disassemble_arm(addr, fetch(addr, 4))
+        };
+        addr = addr.wrapping_add(instruction.len);
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+const CONDITIONS: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "", "nv",
+];
+
+fn reg(index: u32) -> String {
+    match index & 0xF {
+        13 => String::from("sp"),
+        14 => String::from("lr"),
+        15 => String::from("pc"),
+        index => format!("r{index}"),
+    }
+}
+
+fn bits(opcode: u32, low: u32, count: u32) -> u32 {
+    (opcode >> low) & ((1 << count) - 1)
+}
+
+fn bit(opcode: u32, index: u32) -> bool {
+    opcode & (1 << index) != 0
+}
+
+fn sign_extend(value: u32, width: u32) -> i32 {
+    let shift = 32 - width;
+    ((value << shift) as i32) >> shift
+}
+
+fn hex(value: u32) -> String {
+    if value < 10 {
+        format!("#{value}")
+    } else {
+        format!("#0x{value:x}")
+    }
+}
+
+fn signed_hex(value: u32, up: bool) -> String {
+    let sign = if up { "" } else { "-" };
+    if value < 10 {
+        format!("#{sign}{value}")
+    } else {
+        format!("#{sign}0x{value:x}")
+    }
+}
+
+/// `{r0-r3, lr}`, from a register bitmask.
+fn register_list(mask: u32) -> String {
+    let mut parts = Vec::new();
+    let mut index = 0;
+    while index < 16 {
+        if !bit(mask, index) {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < 16 && bit(mask, index) {
+            index += 1;
+        }
+        parts.push(match index - start {
+            1 => reg(start),
+            2 => format!("{}, {}", reg(start), reg(start + 1)),
+            _ => format!("{}-{}", reg(start), reg(index - 1)),
+        });
+    }
+    format!("{{{}}}", parts.join(", "))
+}
+
+fn instruction(addr: u32, opcode: u32, len: u32, text: String) -> Instruction {
+    Instruction {
+        addr,
+        opcode,
+        len,
+        text,
+    }
+}
+
+pub fn disassemble_arm(addr: u32, opcode: u32) -> Instruction {
+    instruction(addr, opcode, 4, arm_text(addr, opcode))
+}
+
+fn arm_text(addr: u32, op: u32) -> String {
+    let cond_index = bits(op, 28, 4);
+    let cond = CONDITIONS[cond_index as usize];
+    let rn = bits(op, 16, 4);
+    let rd = bits(op, 12, 4);
+    let rs = bits(op, 8, 4);
+    let rm = bits(op, 0, 4);
+
+    if cond_index == 0xF {
+        return if op & 0x0E00_0000 == 0x0A00_0000 {
+            let offset = (sign_extend(bits(op, 0, 24), 24) << 2) | ((bit(op, 24) as i32) << 1);
+            format!(
+                "blx 0x{:08x}",
+                addr.wrapping_add(8).wrapping_add_signed(offset)
+            )
+        } else if op & 0x0D70_F000 == 0x0550_F000 {
+            format!("pld {}", address_mode(op))
+        } else {
+            String::from("undefined")
+        };
+    }
+
+    if op & 0x0FFF_FFF0 == 0x012F_FF10 {
+        return format!("bx{cond} {}", reg(rm));
+    }
+    if op & 0x0FFF_FFF0 == 0x012F_FF30 {
+        return format!("blx{cond} {}", reg(rm));
+    }
+    if op & 0x0FFF_0FF0 == 0x016F_0F10 {
+        return format!("clz{cond} {}, {}", reg(rd), reg(rm));
+    }
+    if op & 0x0F90_0FF0 == 0x0100_0050 {
+        let name = ["qadd", "qsub", "qdadd", "qdsub"][bits(op, 21, 2) as usize];
+        return format!("{name}{cond} {}, {}, {}", reg(rd), reg(rm), reg(rn));
+    }
+    if op & 0x0FF0_00F0 == 0x0120_0070 {
+        return format!("bkpt {}", hex((bits(op, 8, 12) << 4) | rm));
+    }
+    if op & 0x0F90_0090 == 0x0100_0080 {
+        return signed_multiply(op, cond);
+    }
+    if op & 0x0FB0_0FF0 == 0x0100_0090 {
+        let byte = if bit(op, 22) { "b" } else { "" };
+        return format!("swp{cond}{byte} {}, {}, [{}]", reg(rd), reg(rm), reg(rn));
+    }
+    if op & 0x0FC0_00F0 == 0x0000_0090 {
+        let s = if bit(op, 20) { "s" } else { "" };
+        // The destination is in the Rn field for multiplies.
+        return if bit(op, 21) {
+            format!(
+                "mla{cond}{s} {}, {}, {}, {}",
+                reg(rn),
+                reg(rm),
+                reg(rs),
+                reg(rd)
+            )
+        } else {
+            format!("mul{cond}{s} {}, {}, {}", reg(rn), reg(rm), reg(rs))
+        };
+    }
+    if op & 0x0F80_00F0 == 0x0080_0090 {
+        let sign = if bit(op, 22) { "s" } else { "u" };
+        let name = if bit(op, 21) { "mlal" } else { "mull" };
+        let s = if bit(op, 20) { "s" } else { "" };
+        return format!(
+            "{sign}{name}{cond}{s} {}, {}, {}, {}",
+            reg(rd),
+            reg(rn),
+            reg(rm),
+            reg(rs)
+        );
+    }
+    if op & 0x0E00_0090 == 0x0000_0090 && op & 0x60 != 0 {
+        return halfword_transfer(op, cond);
+    }
+    if op & 0x0FBF_0FFF == 0x010F_0000 {
+        let psr = if bit(op, 22) { "spsr" } else { "cpsr" };
+        return format!("mrs{cond} {}, {psr}", reg(rd));
+    }
+    if op & 0x0DB0_F000 == 0x0120_F000 {
+        let psr = if bit(op, 22) { "spsr" } else { "cpsr" };
+        let fields: String = ["c", "x", "s", "f"]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| bit(op, 16 + *i as u32))
+            .map(|(_, field)| *field)
+            .collect();
+        let source = if bit(op, 25) {
+            hex(bits(op, 0, 8).rotate_right(bits(op, 8, 4) * 2))
+        } else {
+            reg(rm)
+        };
+        return format!("msr{cond} {psr}_{fields}, {source}");
+    }
+    if op & 0x0C00_0000 == 0x0000_0000 {
+        return data_processing(op, cond);
+    }
+    if op & 0x0E00_0010 == 0x0600_0010 {
+        return String::from("undefined");
+    }
+    if op & 0x0C00_0000 == 0x0400_0000 {
+        let name = if bit(op, 20) { "ldr" } else { "str" };
+        let byte = if bit(op, 22) { "b" } else { "" };
+        // Post-indexed with writeback is the user-mode access.
+        let user = if !bit(op, 24) && bit(op, 21) { "t" } else { "" };
+
+        // Loads relative to the PC are spelled as the address they load from.
+        if rn == 15 && !bit(op, 25) && bit(op, 24) {
+            let offset = bits(op, 0, 12);
+            let target = if bit(op, 23) {
+                addr.wrapping_add(8).wrapping_add(offset)
+            } else {
+                addr.wrapping_add(8).wrapping_sub(offset)
+            };
+            return format!(
+                "{name}{cond}{byte} {}, [pc, {}] ; 0x{target:08x}",
+                reg(rd),
+                signed_hex(offset, bit(op, 23))
+            );
+        }
+        return format!("{name}{cond}{byte}{user} {}, {}", reg(rd), address_mode(op));
+    }
+    if op & 0x0E00_0000 == 0x0800_0000 {
+        let name = if bit(op, 20) { "ldm" } else { "stm" };
+        let mode = ["da", "ia", "db", "ib"][bits(op, 23, 2) as usize];
+        let writeback = if bit(op, 21) { "!" } else { "" };
+        let user = if bit(op, 22) { "^" } else { "" };
+        return format!(
+            "{name}{cond}{mode} {}{writeback}, {}{user}",
+            reg(rn),
+            register_list(bits(op, 0, 16))
+        );
+    }
+    if op & 0x0E00_0000 == 0x0A00_0000 {
+        let name = if bit(op, 24) { "bl" } else { "b" };
+        let offset = sign_extend(bits(op, 0, 24), 24) << 2;
+        return format!(
+            "{name}{cond} 0x{:08x}",
+            addr.wrapping_add(8).wrapping_add_signed(offset)
+        );
+    }
+    if op & 0x0FE0_0000 == 0x0C40_0000 {
+        let name = if bit(op, 20) { "mrrc" } else { "mcrr" };
+        return format!(
+            "{name}{cond} p{rs}, {}, {}, {}, c{rm}",
+            bits(op, 4, 4),
+            reg(rd),
+            reg(rn)
+        );
+    }
+    if op & 0x0E00_0000 == 0x0C00_0000 {
+        let name = if bit(op, 20) { "ldc" } else { "stc" };
+        let long = if bit(op, 22) { "l" } else { "" };
+        let offset = signed_hex(bits(op, 0, 8) * 4, bit(op, 23));
+        let writeback = if bit(op, 21) { "!" } else { "" };
+        let address = if bit(op, 24) {
+            format!("[{}, {offset}]{writeback}", reg(rn))
+        } else {
+            format!("[{}], {offset}", reg(rn))
+        };
+        return format!("{name}{cond}{long} p{rs}, c{rd}, {address}");
+    }
+    if op & 0x0F00_0010 == 0x0E00_0000 {
+        return format!(
+            "cdp{cond} p{rs}, {}, c{rd}, c{rn}, c{rm}, {}",
+            bits(op, 20, 4),
+            bits(op, 5, 3)
+        );
+    }
+    if op & 0x0F00_0010 == 0x0E00_0010 {
+        let name = if bit(op, 20) { "mrc" } else { "mcr" };
+        return format!(
+            "{name}{cond} p{rs}, {}, {}, c{rn}, c{rm}, {}",
+            bits(op, 21, 3),
+            reg(rd),
+            bits(op, 5, 3)
+        );
+    }
+
+    format!("swi{cond} {}", hex(bits(op, 0, 24)))
+}
+
+/// The second operand of a data processing instruction.
+fn shifter_operand(op: u32) -> String {
+    if bit(op, 25) {
+        return hex(bits(op, 0, 8).rotate_right(bits(op, 8, 4) * 2));
+    }
+
+    let rm = reg(bits(op, 0, 4));
+    let kind = bits(op, 5, 2);
+    let name = ["lsl", "lsr", "asr", "ror"][kind as usize];
+
+    if bit(op, 4) {
+        return format!("{rm}, {name} {}", reg(bits(op, 8, 4)));
+    }
+    match (kind, bits(op, 7, 5)) {
+        (0, 0) => rm,
+        (3, 0) => format!("{rm}, rrx"),
+        // An amount of 0 means 32 for the right shifts.
+        (_, 0) => format!("{rm}, {name} #32"),
+        (_, amount) => format!("{rm}, {name} #{amount}"),
+    }
+}
+
+fn data_processing(op: u32, cond: &str) -> String {
+    const NAMES: [&str; 16] = [
+        "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc", "tst", "teq", "cmp", "cmn", "orr",
+        "mov", "bic", "mvn",
+    ];
+    let opcode = bits(op, 21, 4);
+    let name = NAMES[opcode as usize];
+    let rn = reg(bits(op, 16, 4));
+    let rd = reg(bits(op, 12, 4));
+    let operand = shifter_operand(op);
+
+    match opcode {
+        // The compares always set the flags, so don't say so.
+        8..=11 => format!("{name}{cond} {rn}, {operand}"),
+        _ => {
+            let s = if bit(op, 20) { "s" } else { "" };
+            match opcode {
+                13 | 15 => format!("{name}{cond}{s} {rd}, {operand}"),
+                _ => format!("{name}{cond}{s} {rd}, {rn}, {operand}"),
+            }
+        }
+    }
+}
+
+/// The address of a word or byte load or store, or a preload.
+fn address_mode(op: u32) -> String {
+    let rn = reg(bits(op, 16, 4));
+    let up = bit(op, 23);
+    let offset = if bit(op, 25) {
+        let sign = if up { "" } else { "-" };
+        let shifted = shifter_operand(op & !(1 << 25) & !(1 << 4));
+        format!("{sign}{shifted}")
+    } else {
+        match bits(op, 0, 12) {
+            0 => String::new(),
+            offset => signed_hex(offset, up),
+        }
+    };
+
+    match (bit(op, 24), offset.is_empty()) {
+        (true, true) => format!("[{rn}]"),
+        (true, false) => {
+            let writeback = if bit(op, 21) { "!" } else { "" };
+            format!("[{rn}, {offset}]{writeback}")
+        }
+        (false, true) => format!("[{rn}]"),
+        (false, false) => format!("[{rn}], {offset}"),
+    }
+}
+
+fn halfword_transfer(op: u32, cond: &str) -> String {
+    let name = match (bit(op, 20), bits(op, 5, 2)) {
+        (true, 1) => "ldrh",
+        (true, 2) => "ldrsb",
+        (true, _) => "ldrsh",
+        (false, 1) => "strh",
+        (false, 2) => "ldrd",
+        (false, _) => "strd",
+    };
+    let rn = reg(bits(op, 16, 4));
+    let rd = reg(bits(op, 12, 4));
+    let up = bit(op, 23);
+
+    let offset = if bit(op, 22) {
+        match (bits(op, 8, 4) << 4) | bits(op, 0, 4) {
+            0 => String::new(),
+            offset => signed_hex(offset, up),
+        }
+    } else {
+        let sign = if up { "" } else { "-" };
+        format!("{sign}{}", reg(bits(op, 0, 4)))
+    };
+
+    let address = match (bit(op, 24), offset.is_empty()) {
+        (_, true) => format!("[{rn}]"),
+        (true, false) => {
+            let writeback = if bit(op, 21) { "!" } else { "" };
+            format!("[{rn}, {offset}]{writeback}")
+        }
+        (false, false) => format!("[{rn}], {offset}"),
+    };
+    format!("{name}{cond} {rd}, {address}")
+}
+
+/// The ARMv5TE halfword multiplies.
+fn signed_multiply(op: u32, cond: &str) -> String {
+    let rd = reg(bits(op, 16, 4));
+    let rn = reg(bits(op, 12, 4));
+    let rs = reg(bits(op, 8, 4));
+    let rm = reg(bits(op, 0, 4));
+    let x = if bit(op, 5) { "t" } else { "b" };
+    let y = if bit(op, 6) { "t" } else { "b" };
+
+    match bits(op, 21, 2) {
+        0 => format!("smla{x}{y}{cond} {rd}, {rm}, {rs}, {rn}"),
+        1 if bit(op, 5) => format!("smulw{y}{cond} {rd}, {rm}, {rs}"),
+        1 => format!("smlaw{y}{cond} {rd}, {rm}, {rs}, {rn}"),
+        2 => format!("smlal{x}{y}{cond} {rn}, {rd}, {rm}, {rs}"),
+        _ => format!("smul{x}{y}{cond} {rd}, {rm}, {rs}"),
+    }
+}
+
+/// `next` is the halfword after `opcode`, which a BL takes as its second half.
+pub fn disassemble_thumb(addr: u32, opcode: u16, next: u16) -> Instruction {
+    let op = opcode as u32;
+    let next = next as u32;
+
+    // BL and BLX take two halfwords: the high part of the offset, then the
+    // low part.
+    if op & 0xF800 == 0xF000 && next & 0xE800 == 0xE800 {
+        let offset = (sign_extend(bits(op, 0, 11), 11) << 12) | (bits(next, 0, 11) << 1) as i32;
+        let target = addr.wrapping_add(4).wrapping_add_signed(offset);
+        let text = if next & 0xF800 == 0xF800 {
+            format!("bl 0x{target:08x}")
+        } else {
+            format!("blx 0x{:08x}", target & !3)
+        };
+        return instruction(addr, op | (next << 16), 4, text);
+    }
+
+    instruction(addr, op, 2, thumb_text(addr, op))
+}
+
+fn thumb_text(addr: u32, op: u32) -> String {
+    let rd = reg(bits(op, 0, 3));
+    let rs = reg(bits(op, 3, 3));
+    let high = reg(bits(op, 8, 3));
+
+    match op >> 11 {
+        0..=2 => {
+            let name = ["lsl", "lsr", "asr"][(op >> 11) as usize];
+            let amount = match bits(op, 6, 5) {
+                0 if op >> 11 != 0 => 32,
+                amount => amount,
+            };
+            format!("{name}s {rd}, {rs}, #{amount}")
+        }
+        3 => {
+            let name = if bit(op, 9) { "sub" } else { "add" };
+            let operand = if bit(op, 10) {
+                format!("#{}", bits(op, 6, 3))
+            } else {
+                reg(bits(op, 6, 3))
+            };
+            format!("{name}s {rd}, {rs}, {operand}")
+        }
+        4..=7 => {
+            let name = ["movs", "cmp", "adds", "subs"][bits(op, 11, 2) as usize];
+            format!("{name} {high}, {}", hex(bits(op, 0, 8)))
+        }
+        8 if bit(op, 10) => {
+            let rd = reg(bits(op, 0, 3) | ((bit(op, 7) as u32) << 3));
+            let rs = reg(bits(op, 3, 4));
+            match bits(op, 8, 2) {
+                0 => format!("add {rd}, {rs}"),
+                1 => format!("cmp {rd}, {rs}"),
+                2 => format!("mov {rd}, {rs}"),
+                _ if bit(op, 7) => format!("blx {rs}"),
+                _ => format!("bx {rs}"),
+            }
+        }
+        8 => {
+            const NAMES: [&str; 16] = [
+                "ands", "eors", "lsls", "lsrs", "asrs", "adcs", "sbcs", "rors", "tst", "negs",
+                "cmp", "cmn", "orrs", "muls", "bics", "mvns",
+            ];
+            format!("{} {rd}, {rs}", NAMES[bits(op, 6, 4) as usize])
+        }
+        9 => {
+            let offset = bits(op, 0, 8) * 4;
+            let target = (addr.wrapping_add(4) & !3).wrapping_add(offset);
+            format!("ldr {high}, [pc, {}] ; 0x{target:08x}", hex(offset))
+        }
+        10 | 11 => {
+            let name = match bits(op, 9, 3) {
+                0 => "str",
+                1 => "strh",
+                2 => "strb",
+                3 => "ldrsb",
+                4 => "ldr",
+                5 => "ldrh",
+                6 => "ldrb",
+                _ => "ldrsh",
+            };
+            format!("{name} {rd}, [{rs}, {}]", reg(bits(op, 6, 3)))
+        }
+        12..=17 => {
+            let (name, scale) = match op >> 11 {
+                12 => ("str", 4),
+                13 => ("ldr", 4),
+                14 => ("strb", 1),
+                15 => ("ldrb", 1),
+                16 => ("strh", 2),
+                _ => ("ldrh", 2),
+            };
+            match bits(op, 6, 5) * scale {
+                0 => format!("{name} {rd}, [{rs}]"),
+                offset => format!("{name} {rd}, [{rs}, {}]", hex(offset)),
+            }
+        }
+        18 | 19 => {
+            let name = if bit(op, 11) { "ldr" } else { "str" };
+            format!("{name} {high}, [sp, {}]", hex(bits(op, 0, 8) * 4))
+        }
+        20 => {
+            let target = (addr.wrapping_add(4) & !3).wrapping_add(bits(op, 0, 8) * 4);
+            format!(
+                "add {high}, pc, {} ; 0x{target:08x}",
+                hex(bits(op, 0, 8) * 4)
+            )
+        }
+        21 => format!("add {high}, sp, {}", hex(bits(op, 0, 8) * 4)),
+        22 | 23 => match bits(op, 8, 4) {
+            0 => {
+                let name = if bit(op, 7) { "sub" } else { "add" };
+                format!("{name} sp, {}", hex(bits(op, 0, 7) * 4))
+            }
+            4 | 5 => format!(
+                "push {}",
+                register_list(bits(op, 0, 8) | ((bit(op, 8) as u32) << 14))
+            ),
+            12 | 13 => format!(
+                "pop {}",
+                register_list(bits(op, 0, 8) | ((bit(op, 8) as u32) << 15))
+            ),
+            14 => format!("bkpt {}", hex(bits(op, 0, 8))),
+            _ => String::from("undefined"),
+        },
+        24 | 25 => {
+            let name = if bit(op, 11) { "ldmia" } else { "stmia" };
+            format!("{name} {high}!, {}", register_list(bits(op, 0, 8)))
+        }
+        26 | 27 => match bits(op, 8, 4) {
+            14 => String::from("undefined"),
+            15 => format!("swi {}", hex(bits(op, 0, 8))),
+            cond => {
+                let offset = sign_extend(bits(op, 0, 8), 8) << 1;
+                format!(
+                    "b{} 0x{:08x}",
+                    CONDITIONS[cond as usize],
+                    addr.wrapping_add(4).wrapping_add_signed(offset)
+                )
+            }
+        },
+        28 => {
+            let offset = sign_extend(bits(op, 0, 11), 11) << 1;
+            format!(
+                "b 0x{:08x}",
+                addr.wrapping_add(4).wrapping_add_signed(offset)
+            )
+        }
+        // Halves of a BL without the other half.
+        _ => format!("bl (half) {}", hex(bits(op, 0, 11))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arm(addr: u32, opcode: u32) -> String {
+        disassemble_arm(addr, opcode).text
+    }
+
+    fn thumb(addr: u32, opcode: u16) -> String {
+        disassemble_thumb(addr, opcode, 0).text
+    }
+
+    #[test]
+    fn arm_data_processing() {
+        assert_eq!(arm(0, 0xE3A0_0001), "mov r0, #1");
+        assert_eq!(arm(0, 0xE092_1003), "adds r1, r2, r3");
+        assert_eq!(arm(0, 0xE1A0_1102), "mov r1, r2, lsl #2");
+        assert_eq!(arm(0, 0x0350_0000), "cmpeq r0, #0");
+        assert_eq!(arm(0, 0xE3C0_04FF), "bic r0, r0, #0xff000000");
+    }
+
+    #[test]
+    fn arm_loads_and_stores() {
+        assert_eq!(arm(0, 0xE591_0004), "ldr r0, [r1, #4]");
+        assert_eq!(arm(0, 0xE5A1_0004), "str r0, [r1, #4]!");
+        assert_eq!(arm(0, 0xE4D1_0001), "ldrb r0, [r1], #1");
+        assert_eq!(arm(0, 0xE1D1_00B2), "ldrh r0, [r1, #2]");
+        assert_eq!(arm(0, 0xE92D_4010), "stmdb sp!, {r4, lr}");
+        assert_eq!(arm(0, 0xE8BD_800F), "ldmia sp!, {r0-r3, pc}");
+        assert_eq!(
+            arm(0x0200_0000, 0xE59F_0010),
+            "ldr r0, [pc, #0x10] ; 0x02000018"
+        );
+    }
+
+    #[test]
+    fn arm_branches_resolve_their_targets() {
+        assert_eq!(arm(0x0200_0000, 0xEA00_0000), "b 0x02000008");
+        assert_eq!(arm(0x0200_0000, 0xEBFF_FFFE), "bl 0x02000000");
+        assert_eq!(arm(0x0200_0000, 0xFB00_0000), "blx 0x0200000a");
+        assert_eq!(arm(0, 0xE12F_FF1E), "bx lr");
+    }
+
+    #[test]
+    fn arm_system_instructions() {
+        assert_eq!(arm(0, 0xE10F_0000), "mrs r0, cpsr");
+        assert_eq!(arm(0, 0xE129_F000), "msr cpsr_cf, r0");
+        assert_eq!(arm(0, 0xEE11_0F10), "mrc p15, 0, r0, c1, c0, 0");
+        assert_eq!(arm(0, 0xEF00_0005), "swi #5");
+        assert_eq!(arm(0, 0xE16F_0F11), "clz r0, r1");
+        assert_eq!(arm(0, 0xE000_0291), "mul r0, r1, r2");
+    }
+
+    #[test]
+    fn thumb_instructions() {
+        assert_eq!(thumb(0, 0x2001), "movs r0, #1");
+        assert_eq!(thumb(0, 0x1888), "adds r0, r1, r2");
+        assert_eq!(thumb(0, 0x6848), "ldr r0, [r1, #4]");
+        assert_eq!(thumb(0, 0xB510), "push {r4, lr}");
+        assert_eq!(thumb(0, 0xBD10), "pop {r4, pc}");
+        assert_eq!(thumb(0, 0x4770), "bx lr");
+        assert_eq!(thumb(0x0200_0000, 0xD0FE), "beq 0x02000000");
+        assert_eq!(thumb(0x0200_0002, 0x4801), "ldr r0, [pc, #4] ; 0x02000008");
+    }
+
+    #[test]
+    fn thumb_bl_takes_two_halfwords() {
+        let bl = disassemble_thumb(0x0200_0000, 0xF000, 0xF802);
+
+        assert_eq!(bl.text, "bl 0x02000008");
+        assert_eq!(bl.len, 4);
+        assert_eq!(bl.opcode, 0xF802_F000);
+    }
+
+    #[test]
+    fn ranges_advance_by_each_instruction() {
+        let code: [u16; 4] = [0x2001, 0xF000, 0xF802, 0x4770];
+        let listing = disassemble(0, 3, true, |addr, _| {
+            code.get(addr as usize / 2).copied().unwrap_or_default() as u32
+        });
+
+        let addrs: Vec<u32> = listing.iter().map(|instruction| instruction.addr).collect();
+        assert_eq!(addrs, [0, 2, 6]);
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Where melonDS's GDB stub listens for each CPU.
+///
+/// Only takes effect in builds with the `gdbstub` feature. Attach with
+/// `gdb-multiarch`, then `target remote localhost:<port>`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GdbSettings {
+    pub arm9_port: u16,
+    pub arm7_port: u16,
+    /// Stop both CPUs before the first instruction, so a debugger can attach
+    /// before anything runs.
+    pub break_on_startup: bool,
+}
+
+impl Default for GdbSettings {
+    fn default() -> Self {
+        GdbSettings {
+            arm9_port: 3333,
+            arm7_port: 3334,
+            break_on_startup: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_default_to_the_usual_ports() {
+        let settings: GdbSettings = serde_yaml::from_str("arm7_port: 4444").unwrap();
+
+        assert_eq!(
+            settings,
+            GdbSettings {
+                arm7_port: 4444,
+                ..Default::default()
+            }
+        );
+    }
+}
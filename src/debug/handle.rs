@@ -0,0 +1,53 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+use crate::melon::nds::Nds;
+use crate::render::RepaintHandle;
+
+/// Work for the emulator thread to do on the console, between frames.
+pub type DebugCommand = Box<dyn FnOnce(&mut Nds) + Send>;
+
+/// Lets the window reach the console, which only the emulator thread may
+/// touch.
+#[derive(Clone)]
+pub struct DebugHandle {
+    commands: mpsc::Sender<DebugCommand>,
+    repaint: RepaintHandle,
+}
+
+impl DebugHandle {
+    pub fn new(repaint: RepaintHandle) -> (Self, mpsc::Receiver<DebugCommand>) {
+        let (commands, receiver) = mpsc::channel(64);
+        (DebugHandle { commands, repaint }, receiver)
+    }
+
+    /// Runs `command` on the console once the current frame is over. It is
+    /// dropped if the emulator is too far behind to take it.
+    pub fn run(&self, command: impl FnOnce(&mut Nds) + Send + 'static) {
+        let _ = self.commands.try_send(Box::new(command));
+    }
+
+    /// Reads something from the console into `into`, and repaints the window
+    /// if it changed, so a panel can draw the last value while the next one is
+    /// on its way.
+    pub fn fetch<T: PartialEq + Send + 'static>(
+        &self,
+        into: &Arc<Mutex<T>>,
+        read: impl FnOnce(&mut Nds) -> T + Send + 'static,
+    ) {
+        let into = into.clone();
+        let repaint = self.repaint.clone();
+
+        self.run(move |nds| {
+            let value = read(nds);
+            let mut current = into.lock().unwrap();
+            if *current != value {
+                *current = value;
+                if let Some(ctx) = repaint.get() {
+                    ctx.request_repaint();
+                }
+            }
+        });
+    }
+}
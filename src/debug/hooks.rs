@@ -0,0 +1,820 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::{GdbStub, Nds};
+
+use super::trace::Step;
+use super::{Cpu, Instruction, Register, Registers, TraceLog, WatchHit, WatchKind, Watchpoint};
+
+pub type HookFn = Box<dyn FnMut(&mut HookContext<'_>) + Send>;
+pub type WatchFn = Box<dyn FnMut(&WatchHit) + Send>;
+
+/// A closure to call whenever a CPU is about to run the instruction at an
+/// address. It's called from inside the core, and through its [`HookContext`]
+/// can read and patch registers and memory, or ask for a pause.
+pub struct ExecutionHook {
+    pub cpu: Cpu,
+    pub addr: u32,
+    pub callback: HookFn,
+}
+
+impl ExecutionHook {
+    pub fn new(
+        cpu: Cpu,
+        addr: u32,
+        callback: impl FnMut(&mut HookContext<'_>) + Send + 'static,
+    ) -> Self {
+        ExecutionHook {
+            cpu,
+            addr,
+            callback: Box::new(callback),
+        }
+    }
+}
+
+/// Identifies a hook or watchpoint, so it can be removed.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct HookId(u64);
+
+impl HookId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        HookId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The console as a hook sees it: stopped before the hooked instruction, with
+/// the CPU that reached it open for reading and writing.
+///
+/// Memory is read and written through that CPU's address map. The console is
+/// in the middle of a frame, so it mustn't be made to run another, or be
+/// reset or have a savestate loaded.
+pub struct HookContext<'a> {
+    cpu: Cpu,
+    nds: &'a mut Nds,
+    registers: Registers,
+    pause: bool,
+}
+
+impl HookContext<'_> {
+    pub fn cpu(&self) -> Cpu {
+        self.cpu
+    }
+
+    /// The registers as they were when the hook was reached.
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u32) {
+        self.nds.set_register(self.cpu, register, value);
+    }
+
+    pub fn read_bytes(&mut self, addr: u32, len: u32) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.nds.read8(self.cpu, addr.wrapping_add(i)))
+            .collect()
+    }
+
+    pub fn read8(&mut self, addr: u32) -> u8 {
+        self.nds.read8(self.cpu, addr)
+    }
+
+    pub fn read16(&mut self, addr: u32) -> u16 {
+        self.nds.read16(self.cpu, addr)
+    }
+
+    pub fn read32(&mut self, addr: u32) -> u32 {
+        self.nds.read32(self.cpu, addr)
+    }
+
+    pub fn write_bytes(&mut self, addr: u32, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.nds.write8(self.cpu, addr.wrapping_add(i as u32), byte);
+        }
+    }
+
+    pub fn write8(&mut self, addr: u32, value: u8) {
+        self.nds.write8(self.cpu, addr, value);
+    }
+
+    pub fn write16(&mut self, addr: u32, value: u16) {
+        self.nds.write16(self.cpu, addr, value);
+    }
+
+    pub fn write32(&mut self, addr: u32, value: u32) {
+        self.nds.write32(self.cpu, addr, value);
+    }
+
+    /// The whole console, for whatever the methods above don't cover.
+    pub fn nds(&mut self) -> &mut Nds {
+        self.nds
+    }
+
+    /// Pauses emulation once the hook returns. The frame being emulated still
+    /// runs to its end.
+    pub fn pause(&mut self) {
+        self.pause = true;
+    }
+}
+
+/// A CPU held at a breakpoint, or after a step, as of when it stopped.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Halt {
+    pub registers: Registers,
+    /// The code around the PC.
+    pub listing: Vec<Instruction>,
+}
+
+/// How many instructions a [`Halt`] lists before and after the PC.
+pub const LISTING_BEFORE: u32 = 8;
+pub const LISTING_AFTER: u32 = 16;
+
+/// A trace running, or waiting to, on one CPU.
+struct Trace {
+    log: Arc<Mutex<TraceLog>>,
+    /// Where to start, if not straight away.
+    from: Option<u32>,
+    until: Option<u32>,
+    active: bool,
+    /// Steps not yet written to the log.
+    pending: Vec<Step>,
+}
+
+/// How many steps a trace holds before writing them out, if a frame doesn't
+/// end first.
+const TRACE_BUFFER: usize = 1 << 16;
+
+impl Trace {
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            self.log.lock().unwrap().record_steps(&self.pending);
+            self.pending.clear();
+        }
+    }
+}
+
+/// A watchpoint, and what to call when it's hit.
+struct Watch {
+    id: HookId,
+    point: Watchpoint,
+    on_hit: WatchFn,
+    /// What the range held when last looked at, for a domain behind a serial
+    /// port, whose changes are caught by comparing it.
+    contents: Option<Vec<u8>>,
+}
+
+impl Watch {
+    /// The hit `access` makes, if this catches it.
+    fn check(&mut self, nds: &mut Nds, pc: u32, access: Access) -> Option<WatchHit> {
+        let point = self.point;
+        let caught = match point.kind {
+            WatchKind::Write => access.write,
+            WatchKind::Read => !access.write,
+            WatchKind::Access => true,
+        };
+        let (addr, domain, old, new) = match point.domain {
+            // Any access to the I/O registers may have been the one that
+            // finished a write through the port.
+            Some(domain) if domain.is_serial() => {
+                if point.kind == WatchKind::Read {
+                    return None;
+                }
+                let (offset, old, new) = self.compare(nds, domain)?;
+                (access.addr, Some((domain, offset)), old, new)
+            }
+            _ if !caught => return None,
+            Some(domain) => {
+                let (addr, offset, old, new) = self.check_domain(nds, domain, access)?;
+                (addr, Some((domain, offset)), old, new)
+            }
+            None => {
+                let (addr, old, new) = self.check_bus(nds, access)?;
+                (addr, None, old, new)
+            }
+        };
+
+        Some(WatchHit {
+            cpu: point.cpu,
+            pc,
+            addr,
+            domain,
+            kind: if point.domain.is_some_and(MemoryDomain::is_serial) {
+                WatchKind::Write
+            } else {
+                point.kind
+            },
+            old,
+            new,
+        })
+    }
+
+    /// Where on the bus the value `access` touches is, and what it is before
+    /// and after.
+    fn check_bus(&self, nds: &mut Nds, access: Access) -> Option<(u32, u32, u32)> {
+        let width = self.point.width();
+        let first = (0..access.width)
+            .map(|i| access.addr.wrapping_add(i))
+            .find(|&addr| self.point.contains(addr))?;
+
+        let addr = first & !(width - 1);
+        let old = match width {
+            1 => nds.read8(self.point.cpu, addr) as u32,
+            2 => nds.read16(self.point.cpu, addr) as u32,
+            _ => nds.read32(self.point.cpu, addr),
+        };
+        let new = if access.write {
+            overlay(old, addr, width, access)
+        } else {
+            old
+        };
+        Some((addr, old, new))
+    }
+
+    /// As [`Watch::check_bus`], following the access to wherever it reaches
+    /// the domain, with the offset into it too.
+    fn check_domain(
+        &self,
+        nds: &Nds,
+        domain: MemoryDomain,
+        access: Access,
+    ) -> Option<(u32, u32, u32, u32)> {
+        let (byte, offset) = (0..access.width).find_map(|i| {
+            nds.domains_at(self.point.cpu, access.addr.wrapping_add(i))
+                .into_iter()
+                .find(|&(found, offset)| found == domain && self.point.contains(offset as u32))
+                .map(|(_, offset)| (i, offset as u32))
+        })?;
+
+        let width = self.point.width();
+        let at = offset & !(width - 1);
+        let old = little_endian(
+            nds.memory(domain).get(at as usize..).unwrap_or_default(),
+            width,
+        );
+        // Where the access starts, in the domain's terms.
+        let start = offset.wrapping_sub(byte);
+        let new = if access.write {
+            let access = Access {
+                addr: start,
+                ..access
+            };
+            overlay(old, at, width, access)
+        } else {
+            old
+        };
+
+        let addr = access.addr.wrapping_add(at.wrapping_sub(start));
+        Some((addr, at, old, new))
+    }
+
+    /// The first value in the range that has changed since it was last looked
+    /// at, as its offset into the domain, and what it was and is.
+    fn compare(&mut self, nds: &Nds, domain: MemoryDomain) -> Option<(u32, u32, u32)> {
+        let start = self.point.addr as usize;
+        let now = nds
+            .memory(domain)
+            .get(start..start + self.point.len as usize)?;
+        let Some(before) = &mut self.contents else {
+            self.contents = Some(now.to_vec());
+            return None;
+        };
+
+        let changed = before.iter().zip(now).position(|(old, new)| old != new)?;
+        let width = self.point.width() as usize;
+        let at = changed & !(width - 1);
+        let old = little_endian(&before[at..], width as u32);
+        let new = little_endian(&now[at..], width as u32);
+        before.copy_from_slice(now);
+
+        Some(((start + at) as u32, old, new))
+    }
+}
+
+/// Everything set on one CPU.
+#[derive(Default)]
+struct CpuHooks {
+    hooks: HashMap<u32, Vec<(HookId, HookFn)>>,
+    watchpoints: Vec<Watch>,
+    breakpoints: HashSet<u32>,
+    trace: Option<Trace>,
+    /// Hold the CPU at the next instruction it runs, after a step.
+    hold_next: bool,
+    /// Where the CPU is held, while it is.
+    halt: Option<Halt>,
+}
+
+impl CpuHooks {
+    /// The addresses the core has to call back at.
+    fn addresses(&self) -> Vec<u32> {
+        let trace_start = self
+            .trace
+            .as_ref()
+            .filter(|trace| !trace.active)
+            .and_then(|trace| trace.from);
+
+        let mut addrs: Vec<u32> = self.hooks.keys().copied().collect();
+        addrs.extend(&self.breakpoints);
+        addrs.extend(trace_start);
+        addrs
+    }
+
+    /// Whether the core has to call back at every instruction, rather than
+    /// just at [`CpuHooks::addresses`].
+    fn every_instruction(&self) -> bool {
+        self.hold_next || self.trace.as_ref().is_some_and(|trace| trace.active)
+    }
+
+    /// The 16 KB pages the watchpoints could be reached through as things
+    /// are mapped now, numbered by address >> 14.
+    fn watched_pages(&self, nds: &Nds) -> Vec<u32> {
+        pages(
+            self.watchpoints
+                .iter()
+                .flat_map(|watch| reachable_through(&watch.point, nds)),
+        )
+    }
+}
+
+/// Where either CPU's I/O registers are, the first page of which has the
+/// serial ports'.
+const IO_REGISTERS: Range<u64> = 0x0400_0000..0x0400_4000;
+
+/// Where on its CPU's bus a watchpoint could be reached.
+fn reachable_through(point: &Watchpoint, nds: &Nds) -> Vec<Range<u64>> {
+    let len = point.len.max(1) as u64;
+    let at = |base: u64| base + point.addr as u64..base + point.addr as u64 + len;
+
+    match point.domain {
+        None => vec![at(0)],
+        // Through the ports' I/O registers.
+        Some(domain) if domain.is_serial() => vec![IO_REGISTERS],
+        Some(MemoryDomain::Dtcm) => nds
+            .bus_address(point.cpu, MemoryDomain::Dtcm)
+            .map(|base| at(base as u64))
+            .into_iter()
+            .collect(),
+        Some(domain) => {
+            let Some(region) = domain.bus_region(point.cpu) else {
+                return Vec::new();
+            };
+            let size = nds.memory(domain).len();
+            // The rest move around with VRAMCNT and WRAMCNT, so the whole
+            // region is watched, and each access followed to where it lands.
+            if !domain.mirrors_evenly() || size == 0 {
+                let whole = region.start as u64..region.end as u64;
+                return vec![whole];
+            }
+            (region.start as u64..region.end as u64)
+                .step_by(size)
+                .map(at)
+                .collect()
+        }
+    }
+}
+
+/// The 16 KB pages that `ranges` of the bus cover, numbered by address >> 14.
+fn pages(ranges: impl Iterator<Item = Range<u64>>) -> Vec<u32> {
+    let mut pages: Vec<u32> = ranges
+        .filter(|range| !range.is_empty())
+        .flat_map(|range| {
+            let last = (range.end - 1).min(u32::MAX as u64);
+            (range.start >> 14..=last >> 14).map(|page| page as u32)
+        })
+        .collect();
+    pages.sort_unstable();
+    pages.dedup();
+    pages
+}
+
+/// One data access a CPU is about to make, as the core reports it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Access {
+    pub addr: u32,
+    pub width: u32,
+    pub write: bool,
+    /// What's being written, for a write.
+    pub value: u32,
+}
+
+/// Runs execution hooks, watchpoints, and breakpoints from inside the core,
+/// which calls back before each instruction that has one, and before each
+/// data access to a page under a watchpoint. Nothing is checked for a CPU with
+/// nothing set on it.
+///
+/// A CPU held at a breakpoint holds the emulator thread inside the frame with
+/// it, until it is stepped or continued from another thread.
+pub struct Hooks {
+    on_pause: Arc<dyn Fn() + Send + Sync>,
+    cpus: HashMap<Cpu, CpuHooks>,
+    /// Which CPU each hook belongs to.
+    owners: HashMap<HookId, Cpu>,
+    /// Set whenever the core has to be told again where to call back.
+    changed: bool,
+    /// Hold nothing, once the emulator is shutting down.
+    detached: bool,
+    /// Signalled whenever a held CPU may have been let go.
+    released: Arc<Condvar>,
+    /// Set when a trace reaches its stop address, for the [`Tracer`] to see.
+    ///
+    /// [`Tracer`]: super::Tracer
+    trace_ended: bool,
+    /// Called with each CPU the GDB stub starts holding, and lets go of.
+    on_debugger: Option<Arc<dyn Fn(Cpu, bool) + Send + Sync>>,
+    /// The console's stub, for letting go of the console once it's stopping.
+    stub: Option<GdbStub>,
+}
+
+impl Hooks {
+    /// `on_pause` is called whenever a hook asks for a pause, or a watchpoint
+    /// that pauses is hit.
+    pub fn new(on_pause: impl Fn() + Send + Sync + 'static) -> Self {
+        Hooks {
+            on_pause: Arc::new(on_pause),
+            cpus: HashMap::new(),
+            owners: HashMap::new(),
+            changed: true,
+            detached: false,
+            released: Arc::new(Condvar::new()),
+            trace_ended: false,
+            on_debugger: None,
+            stub: None,
+        }
+    }
+
+    /// Has `on_debugger` called with `true` whenever a debugger attached to
+    /// the GDB stub stops a CPU, and with `false` once it lets it go. The
+    /// emulator thread is held inside the frame in between.
+    pub fn set_on_debugger(&mut self, on_debugger: impl Fn(Cpu, bool) + Send + Sync + 'static) {
+        self.on_debugger = Some(Arc::new(on_debugger));
+    }
+
+    pub(crate) fn debugger_stopped(&mut self, cpu: Cpu, stopped: bool) {
+        if let Some(on_debugger) = &self.on_debugger {
+            on_debugger(cpu, stopped);
+        }
+    }
+
+    pub(crate) fn set_stub(&mut self, stub: GdbStub) {
+        self.stub = Some(stub);
+    }
+
+    /// For a console about to be freed.
+    pub(crate) fn forget_stub(&mut self) {
+        self.stub = None;
+    }
+
+    pub fn add(&mut self, hook: ExecutionHook) -> HookId {
+        let id = HookId::next();
+        self.owners.insert(id, hook.cpu);
+        self.cpu_mut(hook.cpu)
+            .hooks
+            .entry(hook.addr)
+            .or_default()
+            .push((id, hook.callback));
+        id
+    }
+
+    /// Calls `on_hit` for every access the watchpoint catches, as the access
+    /// is made.
+    pub fn add_watchpoint(
+        &mut self,
+        watchpoint: Watchpoint,
+        on_hit: impl FnMut(&WatchHit) + Send + 'static,
+    ) -> HookId {
+        let id = HookId::next();
+        self.owners.insert(id, watchpoint.cpu);
+        self.cpu_mut(watchpoint.cpu).watchpoints.push(Watch {
+            id,
+            point: watchpoint,
+            on_hit: Box::new(on_hit),
+            contents: None,
+        });
+        id
+    }
+
+    /// Removes a hook or a watchpoint.
+    pub fn remove(&mut self, id: HookId) {
+        let Some(cpu) = self.owners.remove(&id) else {
+            return;
+        };
+
+        let hooks = self.cpu_mut(cpu);
+        for callbacks in hooks.hooks.values_mut() {
+            callbacks.retain(|(hook, _)| *hook != id);
+        }
+        hooks.hooks.retain(|_, callbacks| !callbacks.is_empty());
+        hooks.watchpoints.retain(|watch| watch.id != id);
+    }
+
+    /// Holds `cpu` whenever it reaches `addr`, until it is stepped or
+    /// continued.
+    pub fn set_breakpoint(&mut self, cpu: Cpu, addr: u32, set: bool) {
+        let breakpoints = &mut self.cpu_mut(cpu).breakpoints;
+        if set {
+            breakpoints.insert(addr);
+        } else {
+            breakpoints.remove(&addr);
+        }
+    }
+
+    pub fn has_breakpoint(&self, cpu: Cpu, addr: u32) -> bool {
+        self.cpus
+            .get(&cpu)
+            .is_some_and(|hooks| hooks.breakpoints.contains(&addr))
+    }
+
+    /// Runs one instruction on a held CPU. On one that is running, holds it
+    /// wherever it gets to next, which for a paused emulator is the start of
+    /// the next frame.
+    pub fn step(&mut self, cpu: Cpu) {
+        let hooks = self.cpu_mut(cpu);
+        hooks.hold_next = true;
+        hooks.halt = None;
+        self.released.notify_all();
+    }
+
+    /// Lets a held CPU go.
+    pub fn resume(&mut self, cpu: Cpu) {
+        let hooks = self.cpu_mut(cpu);
+        hooks.hold_next = false;
+        hooks.halt = None;
+        self.released.notify_all();
+    }
+
+    /// Lets every CPU go for good, so the emulator thread can finish its
+    /// frame and stop. A debugger attached to the GDB stub is dropped too.
+    pub fn detach(&mut self) {
+        if let Some(stub) = &self.stub {
+            stub.detach();
+        }
+        self.detached = true;
+        for hooks in self.cpus.values_mut() {
+            hooks.hold_next = false;
+            hooks.halt = None;
+        }
+        self.changed = true;
+        self.released.notify_all();
+    }
+
+    /// Records every instruction `cpu` runs to `log`, starting when it reaches
+    /// `from` if that is given, and stopping when it reaches `until`.
+    pub fn start_trace(
+        &mut self,
+        cpu: Cpu,
+        log: Arc<Mutex<TraceLog>>,
+        from: Option<u32>,
+        until: Option<u32>,
+    ) {
+        self.cpu_mut(cpu).trace = Some(Trace {
+            log,
+            from,
+            until,
+            active: from.is_none(),
+            pending: Vec::new(),
+        });
+    }
+
+    pub fn stop_trace(&mut self, cpu: Cpu) {
+        if let Some(mut trace) = self.cpu_mut(cpu).trace.take() {
+            trace.flush();
+        }
+    }
+
+    /// Writes out the steps each trace has buffered.
+    pub fn flush_traces(&mut self) {
+        for hooks in self.cpus.values_mut() {
+            if let Some(trace) = &mut hooks.trace {
+                trace.flush();
+            }
+        }
+    }
+
+    /// Whether a trace has reached its stop address since this was last asked.
+    pub fn take_trace_ended(&mut self) -> bool {
+        std::mem::take(&mut self.trace_ended)
+    }
+
+    /// Where `cpu` is held, if it is.
+    pub fn halt(&self, cpu: Cpu) -> Option<Halt> {
+        self.cpus.get(&cpu)?.halt.clone()
+    }
+
+    /// Tells the core where to call back, if that has changed since it was
+    /// last told, or may have with the DTCM moving.
+    pub(crate) fn apply(&mut self, nds: &mut Nds) {
+        let follows_dtcm = self.cpus.values().any(|hooks| {
+            hooks
+                .watchpoints
+                .iter()
+                .any(|watch| watch.point.domain == Some(MemoryDomain::Dtcm))
+        });
+        if !self.changed && !follows_dtcm {
+            return;
+        }
+        self.changed = false;
+
+        for cpu in Cpu::ALL {
+            let hooks = self.cpus.entry(cpu).or_default();
+            nds.set_instr_hooks(cpu, &hooks.addresses(), hooks.every_instruction());
+            nds.set_watch_pages(cpu, &hooks.watched_pages(nds));
+        }
+    }
+
+    /// Anything that goes through here may change where the core has to call
+    /// back.
+    fn cpu_mut(&mut self, cpu: Cpu) -> &mut CpuHooks {
+        self.changed = true;
+        self.cpus.entry(cpu).or_default()
+    }
+}
+
+/// Called by the core before `cpu` runs the instruction at `addr`, wherever
+/// [`Hooks`] has something to do.
+pub(crate) fn on_instruction(hooks: &Mutex<Hooks>, nds: &mut Nds, cpu: Cpu, addr: u32) {
+    let mut guard = hooks.lock().unwrap();
+    let registers = nds.registers(cpu);
+    let Hooks {
+        on_pause,
+        cpus,
+        changed,
+        detached,
+        trace_ended,
+        ..
+    } = &mut *guard;
+    let hooks = cpus.entry(cpu).or_default();
+
+    if let Some(callbacks) = hooks.hooks.get_mut(&addr) {
+        let mut context = HookContext {
+            cpu,
+            nds: &mut *nds,
+            registers,
+            pause: false,
+        };
+        for (_, hook) in callbacks {
+            hook(&mut context);
+        }
+        if context.pause {
+            on_pause();
+        }
+
+        // The hook jumped, so the instruction here never runs.
+        if nds.registers(cpu).pc() != addr {
+            return;
+        }
+    }
+
+    if let Some(trace) = &mut hooks.trace {
+        if !trace.active && trace.from == Some(addr) {
+            trace.active = true;
+            *changed = true;
+        }
+        if trace.active && trace.until == Some(addr) {
+            println!("{cpu} reached {addr:#010x}, so the trace is over");
+            trace.flush();
+            hooks.trace = None;
+            *trace_ended = true;
+            *changed = true;
+        } else if trace.active {
+            let code = if registers.is_thumb() {
+                let next = nds.read16(cpu, addr.wrapping_add(2));
+                u32::from(nds.read16(cpu, addr)) | u32::from(next) << 16
+            } else {
+                nds.read32(cpu, addr)
+            };
+            trace.pending.push(Step {
+                cpu,
+                registers,
+                code,
+            });
+            if trace.pending.len() >= TRACE_BUFFER {
+                trace.flush();
+            }
+        }
+    }
+
+    if (hooks.hold_next || hooks.breakpoints.contains(&addr)) && !*detached {
+        hooks.hold_next = false;
+        hooks.halt = Some(halt(nds, cpu, registers));
+        *changed = true;
+        guard = hold(guard, cpu);
+    }
+
+    guard.apply(nds);
+}
+
+/// Waits for a held CPU to be stepped or continued. The hooks are free to use
+/// meanwhile.
+fn hold(mut guard: MutexGuard<'_, Hooks>, cpu: Cpu) -> MutexGuard<'_, Hooks> {
+    let released = guard.released.clone();
+    while !guard.detached && guard.cpus[&cpu].halt.is_some() {
+        guard = released.wait(guard).unwrap();
+    }
+    guard
+}
+
+/// Lists the code around where `cpu` is.
+fn halt(nds: &mut Nds, cpu: Cpu, registers: Registers) -> Halt {
+    let size = if registers.is_thumb() { 2 } else { 4 };
+    let start = registers.pc().wrapping_sub(LISTING_BEFORE * size);
+    let count = LISTING_BEFORE + LISTING_AFTER;
+    let listing = nds.disassemble(cpu, start, count as usize, registers.is_thumb());
+
+    Halt { registers, listing }
+}
+
+/// Called by the core before `cpu` makes an access to a page under a
+/// watchpoint.
+pub(crate) fn on_access(hooks: &Mutex<Hooks>, nds: &mut Nds, cpu: Cpu, access: Access) {
+    let mut guard = hooks.lock().unwrap();
+    let Hooks { on_pause, cpus, .. } = &mut *guard;
+    let Some(hooks) = cpus.get_mut(&cpu) else {
+        return;
+    };
+
+    // By the time the instruction makes its access, the core has moved its PC
+    // on to the next.
+    let registers = nds.registers(cpu);
+    let size = if registers.is_thumb() { 2 } else { 4 };
+    let pc = registers.pc().wrapping_sub(size);
+
+    let mut pause = false;
+    for watch in &mut hooks.watchpoints {
+        if let Some(hit) = watch.check(nds, pc, access) {
+            (watch.on_hit)(&hit);
+            pause |= watch.point.pause;
+        }
+    }
+
+    if pause {
+        on_pause();
+    }
+}
+
+/// `old`, the `width` bytes at `addr`, as they'll be once the write `access`
+/// lays its bytes over them.
+fn overlay(old: u32, addr: u32, width: u32, access: Access) -> u32 {
+    let mut bytes = old.to_le_bytes();
+    for i in 0..access.width {
+        let offset = access.addr.wrapping_add(i).wrapping_sub(addr);
+        if offset < width {
+            bytes[offset as usize] = (access.value >> (8 * i)) as u8;
+        }
+    }
+    u32::from_le_bytes(bytes)
+}
+
+/// Up to `width` bytes from the start of `bytes`, as a little-endian value.
+fn little_endian(bytes: &[u8], width: u32) -> u32 {
+    bytes
+        .iter()
+        .take(width as usize)
+        .rev()
+        .fold(0, |value, &byte| (value << 8) | byte as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(addr: u32, width: u32, value: u32) -> Access {
+        Access {
+            addr,
+            width,
+            write: true,
+            value,
+        }
+    }
+
+    #[test]
+    fn a_write_only_changes_the_bytes_it_covers() {
+        // A byte written into the middle of a watched word.
+        assert_eq!(
+            overlay(0x4433_2211, 0x100, 4, write(0x102, 1, 0xAA)),
+            0x44AA_2211
+        );
+        // A word written over a watched halfword, which only sees its half.
+        assert_eq!(
+            overlay(0x2211, 0x102, 2, write(0x100, 4, 0xDDCC_BBAA)),
+            0xDDCC
+        );
+    }
+
+    #[test]
+    fn pages_cover_the_whole_of_each_range() {
+        let ranges = [0x0200_3FFE..0x0200_4002, 0x0200_0000..0x0200_0001];
+        assert_eq!(pages(ranges.into_iter()), [0x0800, 0x0801]);
+        // A range running off the end of the bus stops there.
+        let end = 0xFFFF_FFFF..0x1_0000_0003;
+        assert_eq!(pages(std::iter::once(end)), [0x3FFFF]);
+    }
+
+    #[test]
+    fn values_are_read_little_endian_as_far_as_there_are_bytes() {
+        assert_eq!(little_endian(&[0x11, 0x22, 0x33, 0x44], 2), 0x2211);
+        assert_eq!(little_endian(&[0x11], 4), 0x11);
+    }
+}
@@ -0,0 +1,18 @@
+mod disasm;
+mod gdb;
+mod handle;
+mod hooks;
+mod registers;
+mod trace;
+mod watch;
+
+pub use disasm::{disassemble, disassemble_arm, disassemble_thumb, Instruction};
+pub use gdb::GdbSettings;
+pub use handle::{DebugCommand, DebugHandle};
+pub(crate) use hooks::{on_access, on_instruction, Access};
+pub use hooks::{
+    ExecutionHook, Halt, HookContext, HookFn, HookId, Hooks, WatchFn, LISTING_AFTER, LISTING_BEFORE,
+};
+pub use registers::{Cpu, Mode, Register, Registers};
+pub use trace::{TraceBound, TraceLog, TraceSettings, Tracer};
+pub use watch::{WatchHit, WatchKind, WatchLog, WatchSettings, Watchpoint, WatchpointSettings};
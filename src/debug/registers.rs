@@ -0,0 +1,224 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// One of the console's two processors.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Cpu {
+    /// The ARM946E-S, which runs the game.
+    Arm9,
+    /// The ARM7TDMI, which runs sound, touch, and wireless.
+    Arm7,
+}
+
+impl Cpu {
+    pub const ALL: [Cpu; 2] = [Cpu::Arm9, Cpu::Arm7];
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cpu::Arm9 => write!(f, "ARM9"),
+            Cpu::Arm7 => write!(f, "ARM7"),
+        }
+    }
+}
+
+/// A register that can be written.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Register {
+    /// R0 to R14, in the current mode's bank.
+    Gpr(u8),
+    /// Where execution continues. Writing it jumps there, staying in the
+    /// current instruction set.
+    Pc,
+    Cpsr,
+    /// Ignored in User and System mode, which have none.
+    Spsr,
+}
+
+impl Register {
+    /// The index the shims take.
+    pub(crate) fn index(self) -> u32 {
+        match self {
+            Register::Gpr(index) => {
+                assert!(index < 15, "R{index} is not a general-purpose register");
+                index as u32
+            }
+            Register::Pc => 15,
+            Register::Cpsr => 16,
+            Register::Spsr => 17,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::Gpr(13) => write!(f, "SP"),
+            Register::Gpr(14) => write!(f, "LR"),
+            Register::Gpr(index) => write!(f, "R{index}"),
+            Register::Pc => write!(f, "PC"),
+            Register::Cpsr => write!(f, "CPSR"),
+            Register::Spsr => write!(f, "SPSR"),
+        }
+    }
+}
+
+/// One CPU's registers, as they stand between frames.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Registers {
+    /// R0 to R15 in the current mode's bank. R15 is as melonDS keeps it, ahead
+    /// of the next instruction by the depth of the pipeline; see
+    /// [`Registers::pc`].
+    pub gpr: [u32; 16],
+    pub cpsr: u32,
+    /// The current mode's saved status, if it has one.
+    pub spsr: Option<u32>,
+}
+
+impl Registers {
+    const THUMB: u32 = 1 << 5;
+
+    /// Builds them from what the shims write out.
+    pub(crate) fn from_raw(raw: [u32; 19]) -> Self {
+        let mut gpr = [0; 16];
+        gpr.copy_from_slice(&raw[..16]);
+
+        Registers {
+            gpr,
+            cpsr: raw[16],
+            spsr: (raw[18] != 0).then_some(raw[17]),
+        }
+    }
+
+    /// The address of the next instruction to run.
+    pub fn pc(&self) -> u32 {
+        // melonDS keeps R15 where the next fetch is, which is two instructions
+        // past the one about to run.
+        self.gpr[15].wrapping_sub(if self.is_thumb() { 2 } else { 4 })
+    }
+
+    pub fn is_thumb(&self) -> bool {
+        self.cpsr & Self::THUMB != 0
+    }
+
+    /// The current mode, from the low bits of the CPSR.
+    pub fn mode(&self) -> Mode {
+        Mode::from_bits(self.cpsr)
+    }
+
+    /// The N, Z, C, and V condition flags, as `NZCV` with `-` for each clear
+    /// one.
+    pub fn flags(&self) -> String {
+        "NZCV"
+            .chars()
+            .enumerate()
+            .map(|(i, flag)| {
+                if self.cpsr & (1 << (31 - i)) != 0 {
+                    flag
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+
+    /// Reads a register the way [`Register`] names it.
+    pub fn get(&self, register: Register) -> Option<u32> {
+        match register {
+            Register::Gpr(index) => self.gpr.get(index as usize).copied(),
+            Register::Pc => Some(self.pc()),
+            Register::Cpsr => Some(self.cpsr),
+            Register::Spsr => self.spsr,
+        }
+    }
+}
+
+/// The processor mode, which decides which registers are banked in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    User,
+    Fiq,
+    Irq,
+    Supervisor,
+    Abort,
+    Undefined,
+    System,
+    /// Bits that don't name a mode.
+    Invalid(u32),
+}
+
+impl Mode {
+    pub fn from_bits(cpsr: u32) -> Self {
+        match cpsr & 0x1F {
+            0x10 => Mode::User,
+            0x11 => Mode::Fiq,
+            0x12 => Mode::Irq,
+            0x13 => Mode::Supervisor,
+            0x17 => Mode::Abort,
+            0x1B => Mode::Undefined,
+            0x1F => Mode::System,
+            bits => Mode::Invalid(bits),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::User => write!(f, "usr"),
+            Mode::Fiq => write!(f, "fiq"),
+            Mode::Irq => write!(f, "irq"),
+            Mode::Supervisor => write!(f, "svc"),
+            Mode::Abort => write!(f, "abt"),
+            Mode::Undefined => write!(f, "und"),
+            Mode::System => write!(f, "sys"),
+            Mode::Invalid(bits) => write!(f, "{bits:#04x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(r15: u32, cpsr: u32) -> Registers {
+        let mut raw = [0; 19];
+        raw[15] = r15;
+        raw[16] = cpsr;
+        Registers::from_raw(raw)
+    }
+
+    #[test]
+    fn the_pc_accounts_for_the_pipeline() {
+        assert_eq!(registers(0x0200_0008, 0x1F).pc(), 0x0200_0004);
+        assert_eq!(registers(0x0200_0008, 0x3F).pc(), 0x0200_0006);
+    }
+
+    #[test]
+    fn the_spsr_is_only_there_in_modes_that_have_one() {
+        let mut raw = [0; 19];
+        raw[17] = 0x1234;
+        assert_eq!(Registers::from_raw(raw).spsr, None);
+
+        raw[18] = 1;
+        assert_eq!(Registers::from_raw(raw).spsr, Some(0x1234));
+    }
+
+    #[test]
+    fn the_cpsr_reads_as_flags_and_mode() {
+        let registers = registers(0, 0xA000_0013);
+
+        assert_eq!(registers.flags(), "N-C-");
+        assert_eq!(registers.mode(), Mode::Supervisor);
+        assert!(!registers.is_thumb());
+    }
+
+    #[test]
+    fn banked_registers_have_their_usual_names() {
+        assert_eq!(Register::Gpr(13).to_string(), "SP");
+        assert_eq!(Register::Gpr(14).to_string(), "LR");
+        assert_eq!(Register::Gpr(3).to_string(), "R3");
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use super::{disassemble_arm, disassemble_thumb, Cpu, Hooks, Instruction, Registers};
+
+/// Where a trace starts or stops.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum TraceBound {
+    /// The frame with this number, counting the first as 1.
+    Frame(u64),
+    /// The first time a traced CPU reaches this address.
+    Address(u32),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraceSettings {
+    pub cpus: Vec<Cpu>,
+    /// Write each CPU's registers alongside every instruction.
+    pub registers: bool,
+    /// Start straight away if unset.
+    pub start: Option<TraceBound>,
+    /// Run until toggled off if unset.
+    pub stop: Option<TraceBound>,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        TraceSettings {
+            cpus: vec![Cpu::Arm9],
+            registers: false,
+            start: None,
+            stop: None,
+        }
+    }
+}
+
+/// A gzipped text file with a line for every instruction run, so that two
+/// runs can be compared with `zdiff`.
+pub struct TraceLog {
+    out: GzEncoder<BufWriter<File>>,
+    registers: bool,
+    failed: bool,
+}
+
+impl TraceLog {
+    pub fn create(path: &Path, registers: bool) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(TraceLog {
+            out: GzEncoder::new(file, Compression::fast()),
+            registers,
+            failed: false,
+        })
+    }
+
+    /// `registers` are as they were just before `instruction` ran.
+    pub fn record(&mut self, cpu: Cpu, registers: &Registers, instruction: &Instruction) {
+        if self.failed {
+            return;
+        }
+
+        let line = entry(cpu, registers, instruction, self.registers);
+        if let Err(err) = writeln!(self.out, "{line}") {
+            println!("WARNING: stopped writing the trace: {err}");
+            self.failed = true;
+        }
+    }
+}
+
+/// An instruction as a traced CPU was about to run it, kept as read until the
+/// log is next written to, so that the core isn't held up disassembling and
+/// compressing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Step {
+    pub cpu: Cpu,
+    pub registers: Registers,
+    /// Four bytes from the PC, which for Thumb may be both halves of a BL.
+    pub code: u32,
+}
+
+impl Step {
+    fn instruction(&self) -> Instruction {
+        let pc = self.registers.pc();
+        if self.registers.is_thumb() {
+            disassemble_thumb(pc, self.code as u16, (self.code >> 16) as u16)
+        } else {
+            disassemble_arm(pc, self.code)
+        }
+    }
+}
+
+impl TraceLog {
+    pub(crate) fn record_steps(&mut self, steps: &[Step]) {
+        for step in steps {
+            self.record(step.cpu, &step.registers, &step.instruction());
+        }
+    }
+}
+
+fn entry(
+    cpu: Cpu,
+    registers: &Registers,
+    instruction: &Instruction,
+    with_registers: bool,
+) -> String {
+    let opcode = match instruction.len {
+        2 => format!("{:04x}", instruction.opcode),
+        _ => format!("{:08x}", instruction.opcode),
+    };
+    let mut line = format!(
+        "{cpu} {:08x} {opcode:<8} {:<32}",
+        instruction.addr, instruction.text
+    );
+
+    if with_registers {
+        for (i, value) in registers.gpr[..15].iter().enumerate() {
+            line.push_str(&format!(" r{i}={value:08x}"));
+        }
+        line.push_str(&format!(" cpsr={:08x}", registers.cpsr));
+    }
+
+    line.trim_end().to_owned()
+}
+
+enum State {
+    Off,
+    /// Waiting for the start frame.
+    Armed(Arc<Mutex<TraceLog>>),
+    Running,
+}
+
+/// Starts and stops traces on the CPUs the settings name, at the bounds they
+/// give.
+///
+/// A traced CPU has the core call back before every instruction it runs, so
+/// emulation slows down while a trace is running. The first traced CPU to
+/// reach the stop address ends the trace on all of them.
+pub struct Tracer {
+    hooks: Arc<Mutex<Hooks>>,
+    settings: TraceSettings,
+    state: State,
+}
+
+impl Tracer {
+    pub fn new(hooks: Arc<Mutex<Hooks>>, settings: TraceSettings) -> Self {
+        Tracer {
+            hooks,
+            settings,
+            state: State::Off,
+        }
+    }
+
+    /// Starts a trace to `path`, or stops the one running.
+    pub fn toggle(&mut self, path: &Path) {
+        if !matches!(self.state, State::Off) {
+            self.stop();
+            return;
+        }
+
+        match TraceLog::create(path, self.settings.registers) {
+            Ok(log) => {
+                self.state = State::Armed(Arc::new(Mutex::new(log)));
+                println!("tracing to {}", path.display());
+            }
+            Err(err) => println!("WARNING: couldn't create {}: {err}", path.display()),
+        }
+    }
+
+    /// Called before each frame, with the number of the frame about to run.
+    pub fn before_frame(&mut self, frame: u64) {
+        let State::Armed(log) = &self.state else {
+            return;
+        };
+        let (from, waiting) = match self.settings.start {
+            Some(TraceBound::Frame(start)) => (None, frame < start),
+            Some(TraceBound::Address(addr)) => (Some(addr), false),
+            None => (None, false),
+        };
+        if waiting {
+            return;
+        }
+
+        let until = match self.settings.stop {
+            Some(TraceBound::Address(addr)) => Some(addr),
+            _ => None,
+        };
+        let mut hooks = self.hooks.lock().unwrap();
+        for &cpu in &self.settings.cpus {
+            hooks.start_trace(cpu, log.clone(), from, until);
+        }
+        // The CPUs hold the log from here, and it is finished once they have
+        // all let it go.
+        self.state = State::Running;
+    }
+
+    /// Called after each frame, with the number of the frame that ran.
+    pub fn after_frame(&mut self, frame: u64) {
+        let State::Running = self.state else {
+            return;
+        };
+
+        let until_reached = {
+            let mut hooks = self.hooks.lock().unwrap();
+            hooks.flush_traces();
+            hooks.take_trace_ended()
+        };
+        let frame_reached =
+            matches!(self.settings.stop, Some(TraceBound::Frame(stop)) if frame >= stop);
+        if until_reached || frame_reached {
+            self.stop();
+        }
+    }
+
+    fn stop(&mut self) {
+        if let State::Running = self.state {
+            let mut hooks = self.hooks.lock().unwrap();
+            for &cpu in &self.settings.cpus {
+                hooks.stop_trace(cpu);
+            }
+        }
+        self.state = State::Off;
+        println!("stopped tracing");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_hold_the_instruction_and_optionally_the_registers() {
+        let mut registers = Registers::default();
+        registers.gpr[1] = 0x1234;
+        registers.cpsr = 0x1F;
+        let instruction = disassemble_arm(0x0200_0000, 0xE3A0_0001);
+
+        assert_eq!(
+            entry(Cpu::Arm9, &registers, &instruction, false),
+            "ARM9 02000000 e3a00001 mov r0, #1"
+        );
+
+        let line = entry(Cpu::Arm9, &registers, &instruction, true);
+        assert!(line.contains(" r1=00001234 "));
+        assert!(line.ends_with(" cpsr=0000001f"));
+    }
+
+    #[test]
+    fn steps_are_disassembled_in_the_mode_they_ran_in() {
+        let mut registers = Registers::default();
+        // A Thumb BL at 0x02000000, as the core sees it before running it.
+        registers.gpr[15] = 0x0200_0002;
+        registers.cpsr = 0x3F;
+        let step = Step {
+            cpu: Cpu::Arm7,
+            registers,
+            code: 0xF800_F000,
+        };
+
+        let instruction = step.instruction();
+        assert_eq!(instruction, disassemble_thumb(0x0200_0000, 0xF000, 0xF800));
+        assert_eq!(instruction.len, 4);
+
+        registers.cpsr = 0x1F;
+        registers.gpr[15] = 0x0200_0004;
+        let step = Step {
+            cpu: Cpu::Arm9,
+            registers,
+            code: 0xE3A0_0001,
+        };
+        assert_eq!(step.instruction().text, "mov r0, #1");
+    }
+
+    #[test]
+    fn settings_take_either_kind_of_bound() {
+        let settings: TraceSettings = serde_yaml::from_str(
+            "cpus: [Arm9, Arm7]\nstart: !Frame 100\nstop: !Address 0x02000800\n",
+        )
+        .unwrap();
+
+        assert_eq!(settings.start, Some(TraceBound::Frame(100)));
+        assert_eq!(settings.stop, Some(TraceBound::Address(0x0200_0800)));
+        assert!(!settings.registers);
+    }
+}
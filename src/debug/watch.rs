@@ -0,0 +1,204 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::Nds;
+
+use super::Cpu;
+
+/// Which accesses a watchpoint catches.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum WatchKind {
+    Write,
+    Read,
+    /// Reads and writes both.
+    Access,
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+/// A range of memory to report one CPU's accesses to. Only the CPU's own
+/// loads and stores are caught, not DMA transfers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Watchpoint {
+    pub cpu: Cpu,
+    /// What `addr` is in: the CPU's bus if unset. A domain is watched wherever
+    /// the CPU reaches it at the time of each access, through any mirror and
+    /// however VRAMCNT, WRAMCNT, and the DTCM's base have it mapped.
+    ///
+    /// Cart save and firmware aren't on either bus, but behind serial ports,
+    /// so only changes to them are caught, and only when the CPU next touches
+    /// an I/O register after the write that made them.
+    pub domain: Option<MemoryDomain>,
+    pub addr: u32,
+    pub len: u32,
+    pub kind: WatchKind,
+    /// Pause emulation after each hit.
+    pub pause: bool,
+}
+
+impl Watchpoint {
+    pub fn contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(self.addr) < self.len
+    }
+
+    /// How wide the values reported for a hit are: the whole range if it is a
+    /// single value, and a word otherwise.
+    pub fn width(&self) -> u32 {
+        match self.len {
+            1 => 1,
+            2 | 3 => 2,
+            _ => 4,
+        }
+    }
+}
+
+/// One access caught by a watchpoint.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WatchHit {
+    pub cpu: Cpu,
+    /// Where the CPU was when it made the access.
+    pub pc: u32,
+    /// Where the value is on the CPU's bus. For cart save and firmware, the
+    /// I/O register the change was caught at instead.
+    pub addr: u32,
+    /// The domain and offset, for a watchpoint on a domain.
+    pub domain: Option<(MemoryDomain, u32)>,
+    pub kind: WatchKind,
+    /// The value at `addr` before the access, [`Watchpoint::width`] bytes
+    /// wide.
+    pub old: u32,
+    /// The value at `addr` after it, which only differs from `old` for a write.
+    pub new: u32,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} of {:#010x}", self.cpu, self.kind, self.addr)?;
+        if let Some((domain, offset)) = self.domain {
+            write!(f, " ({domain} + {offset:#x})")?;
+        }
+        write!(
+            f,
+            " at pc {:#010x}: {:#x} -> {:#x}",
+            self.pc, self.old, self.new
+        )
+    }
+}
+
+/// Appends each hit to a text file, a line each.
+pub struct WatchLog(BufWriter<File>);
+
+impl WatchLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(WatchLog(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&mut self, hit: &WatchHit) {
+        // Flushed every time, so the log is complete even if the emulator is
+        // killed while stopped somewhere.
+        let result = writeln!(self.0, "{hit}").and_then(|_| self.0.flush());
+        if let Err(err) = result {
+            println!("WARNING: couldn't log a watchpoint hit: {err}");
+        }
+    }
+}
+
+/// A watchpoint as the config file gives it, by domain rather than address.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WatchpointSettings {
+    pub cpu: Cpu,
+    pub domain: MemoryDomain,
+    /// From the start of the domain.
+    pub offset: u32,
+    pub len: u32,
+    pub kind: WatchKind,
+}
+
+impl WatchpointSettings {
+    /// The watchpoint, if the range is inside the domain and the CPU can
+    /// reach the domain at all.
+    pub fn resolve(&self, nds: &Nds, pause: bool) -> Option<Watchpoint> {
+        let end = self.offset.checked_add(self.len)?;
+        let inside = self.len > 0 && end as usize <= nds.memory(self.domain).len();
+        if !inside || !self.domain.is_reachable_by(self.cpu) {
+            return None;
+        }
+
+        Some(Watchpoint {
+            cpu: self.cpu,
+            domain: Some(self.domain),
+            addr: self.offset,
+            len: self.len,
+            kind: self.kind,
+            pause,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    pub points: Vec<WatchpointSettings>,
+    /// Pause emulation whenever one is hit.
+    pub pause: bool,
+    /// A file to log every hit to.
+    pub log: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watchpoint(addr: u32, len: u32) -> Watchpoint {
+        Watchpoint {
+            cpu: Cpu::Arm9,
+            domain: None,
+            addr,
+            len,
+            kind: WatchKind::Write,
+            pause: false,
+        }
+    }
+
+    #[test]
+    fn a_watchpoint_covers_its_range_only() {
+        let watchpoint = watchpoint(0x0200_0010, 4);
+
+        assert!(!watchpoint.contains(0x0200_000F));
+        assert!(watchpoint.contains(0x0200_0010));
+        assert!(watchpoint.contains(0x0200_0013));
+        assert!(!watchpoint.contains(0x0200_0014));
+    }
+
+    #[test]
+    fn values_are_as_wide_as_a_single_watched_value() {
+        assert_eq!(watchpoint(0, 1).width(), 1);
+        assert_eq!(watchpoint(0, 2).width(), 2);
+        assert_eq!(watchpoint(0, 4).width(), 4);
+        assert_eq!(watchpoint(0, 0x1000).width(), 4);
+    }
+
+    #[test]
+    fn settings_take_hex_offsets() {
+        let settings: WatchSettings = serde_yaml::from_str(
+            "points:\n  - cpu: Arm9\n    domain: MainRam\n    offset: 0x1234\n    len: 2\n    kind: Write\n",
+        )
+        .unwrap();
+
+        assert_eq!(settings.points[0].offset, 0x1234);
+        assert!(!settings.pause);
+    }
+}
@@ -0,0 +1,443 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::frontend::Frames;
+use crate::melon::nds::Nds;
+use crate::pacing::Pacing;
+use crate::render::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// One emulated frame's worth of output.
+struct Chunk {
+    frames: Arc<Frames>,
+    samples: Vec<[i16; 2]>,
+}
+
+/// Writes every emulated frame to disk, as a Y4M video of both screens stacked
+/// top over bottom and a WAV of the audio produced alongside it.
+///
+/// Frames are handed over from the emulator thread as they are emulated, so a
+/// slow repaint cannot drop or repeat one. The conversion and the writing
+/// happen on a thread of their own, behind a short queue: a disk that falls
+/// behind holds the emulator up rather than costing frames or memory.
+pub struct Encoder {
+    chunks: Option<mpsc::SyncSender<Chunk>>,
+    writer: Option<JoinHandle<()>>,
+    /// The frame that the next chunk should belong to.
+    next_frame: Option<u64>,
+}
+
+impl Encoder {
+    /// How many frames may wait to be written before the emulator waits too.
+    const QUEUE_LEN: usize = 8;
+
+    /// Starts writing `<stem>.y4m` and `<stem>.wav`, at the frame rate of
+    /// `pacing`.
+    pub fn start(stem: &Path, pacing: Pacing) -> io::Result<Self> {
+        let frame_rate = pacing.frame_rate();
+        let video = Y4mWriter::new(
+            BufWriter::new(File::create(with_extension(stem, "y4m"))?),
+            frame_rate,
+        )?;
+        let audio = WavWriter::new(BufWriter::new(File::create(with_extension(stem, "wav"))?))?;
+        let fit = FrameAudio::new(frame_rate);
+
+        let (chunks, rx) = mpsc::sync_channel::<Chunk>(Self::QUEUE_LEN);
+
+        let writer = thread::Builder::new()
+            .name("encoder".to_owned())
+            .spawn(move || write_chunks(rx, video, audio, fit))?;
+
+        println!("encoding to {}", stem.display());
+
+        Ok(Encoder {
+            chunks: Some(chunks),
+            writer: Some(writer),
+            next_frame: None,
+        })
+    }
+
+    /// Queues the output of emulated frame `frame`, returning false once the
+    /// writer has stopped and the encoder is no use any more.
+    pub fn push(&mut self, frame: u64, frames: Arc<Frames>, samples: Vec<[i16; 2]>) -> bool {
+        // A savestate can move the console to any frame it likes. The output is
+        // still written in the order it was emulated, but the jump is worth
+        // knowing about when lining the video up with a replay.
+        if let Some(expected) = self.next_frame.filter(|&expected| expected != frame) {
+            println!("WARNING: the encoding skipped from frame {expected} to frame {frame}");
+        }
+        self.next_frame = Some(frame + 1);
+
+        let sent = self
+            .chunks
+            .as_ref()
+            .is_some_and(|chunks| chunks.send(Chunk { frames, samples }).is_ok());
+        if !sent {
+            println!("WARNING: the encoder stopped early; frame {frame} and those after it were not written");
+        }
+
+        sent
+    }
+}
+
+impl Drop for Encoder {
+    /// Waits for everything queued to be written, so the files are complete once
+    /// the encoder is gone.
+    fn drop(&mut self) {
+        self.chunks.take();
+
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn with_extension(stem: &Path, extension: &str) -> PathBuf {
+    let mut path = stem.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn write_chunks(
+    chunks: mpsc::Receiver<Chunk>,
+    mut video: Y4mWriter<BufWriter<File>>,
+    mut audio: WavWriter<BufWriter<File>>,
+    mut fit: FrameAudio,
+) {
+    let mut written = 0u64;
+
+    for chunk in chunks {
+        let result = video
+            .write_frame(&chunk.frames)
+            .and_then(|_| audio.write_samples(&fit.stretch(&chunk.samples)));
+
+        if let Err(err) = result {
+            println!("WARNING: encoding stopped after {written} frames: {err}");
+            return;
+        }
+        written += 1;
+    }
+
+    match video.finish().and_then(|_| audio.finish()) {
+        Ok(()) => println!("encoded {written} frames"),
+        Err(err) => println!("WARNING: couldn't finish the encoding: {err}"),
+    }
+}
+
+/// Stretches each frame's audio to the same share of a second.
+///
+/// How much audio melonDS hands out for a frame depends on the skew it was
+/// resampled with, which the audio device nudges back and forth to keep its
+/// backlog steady. Stretching it back keeps the WAV in step with the frame
+/// count instead, so it lines up with the video however long the encoding runs.
+struct FrameAudio {
+    per_frame: f64,
+    /// The part of a sample pair the frames so far have been short.
+    owed: f64,
+}
+
+impl FrameAudio {
+    fn new((numerator, denominator): (u32, u32)) -> Self {
+        FrameAudio {
+            per_frame: Nds::AUDIO_SAMPLE_RATE as f64 * denominator as f64 / numerator as f64,
+            owed: 0.0,
+        }
+    }
+
+    fn stretch(&mut self, samples: &[[i16; 2]]) -> Vec<[i16; 2]> {
+        self.owed += self.per_frame;
+        let len = self.owed as usize;
+        self.owed -= len as f64;
+
+        if samples.is_empty() {
+            return vec![[0, 0]; len];
+        }
+
+        let step = samples.len() as f64 / len as f64;
+        (0..len)
+            .map(|index| {
+                let position = index as f64 * step;
+                let before = samples[position as usize];
+                let after = samples[(position as usize + 1).min(samples.len() - 1)];
+                let t = position.fract();
+
+                [0, 1].map(|channel| {
+                    let (before, after) = (before[channel] as f64, after[channel] as f64);
+                    (before + (after - before) * t).round() as i16
+                })
+            })
+            .collect()
+    }
+}
+
+/// A raw YUV 4:4:4 stream, which keeps the screens' hard pixel edges where
+/// subsampled chroma would smear them.
+struct Y4mWriter<W: Write> {
+    out: W,
+    plane: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    const WIDTH: usize = SCREEN_WIDTH;
+    const HEIGHT: usize = 2 * SCREEN_HEIGHT;
+
+    /// Frames are labelled with the rate they were paced at, which the audio
+    /// is stretched to match, so that the two line up however long they run.
+    fn new(mut out: W, (numerator, denominator): (u32, u32)) -> io::Result<Self> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{numerator}:{denominator} Ip A1:1 C444",
+            Self::WIDTH,
+            Self::HEIGHT
+        )?;
+
+        Ok(Y4mWriter {
+            out,
+            plane: vec![0; 3 * Self::WIDTH * Self::HEIGHT],
+        })
+    }
+
+    fn write_frame(&mut self, frames: &Frames) -> io::Result<()> {
+        let pixels = Self::WIDTH * Self::HEIGHT;
+        let (y, chroma) = self.plane.split_at_mut(pixels);
+        let (u, v) = chroma.split_at_mut(pixels);
+
+        let bgra = frames
+            .top
+            .chunks_exact(4)
+            .chain(frames.bottom.chunks_exact(4));
+        for (index, pixel) in bgra.enumerate() {
+            [y[index], u[index], v[index]] = yuv(pixel[2], pixel[1], pixel[0]);
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.plane)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// BT.601 at studio range, which is what a Y4M reader assumes it is given.
+fn yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+
+    [y as u8, u as u8, v as u8]
+}
+
+/// 16-bit stereo PCM. The header is written with empty sizes up front and
+/// patched once the length is known.
+struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_len: u32,
+    /// The most audio the file can hold, past which the rest is left out.
+    max_data_len: u32,
+    /// Whether any audio has been left out.
+    full: bool,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    const HEADER_LEN: u32 = 44;
+    const BYTES_PER_PAIR: u32 = 2 * Nds::AUDIO_CHANNELS as u32;
+    /// RIFF sizes are 32-bit and count everything after the first eight bytes,
+    /// which caps the data at a little under 4 GiB, or about six hours.
+    const MAX_DATA_LEN: u32 =
+        (u32::MAX - (Self::HEADER_LEN - 8)) / Self::BYTES_PER_PAIR * Self::BYTES_PER_PAIR;
+
+    fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"RIFF")?;
+        out.write_u32::<LittleEndian>(Self::HEADER_LEN - 8)?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_u32::<LittleEndian>(16)?;
+        // PCM
+        out.write_u16::<LittleEndian>(1)?;
+        out.write_u16::<LittleEndian>(Nds::AUDIO_CHANNELS)?;
+        out.write_u32::<LittleEndian>(Nds::AUDIO_SAMPLE_RATE)?;
+        out.write_u32::<LittleEndian>(Nds::AUDIO_SAMPLE_RATE * Self::BYTES_PER_PAIR)?;
+        out.write_u16::<LittleEndian>(Self::BYTES_PER_PAIR as u16)?;
+        out.write_u16::<LittleEndian>(16)?;
+
+        out.write_all(b"data")?;
+        out.write_u32::<LittleEndian>(0)?;
+
+        Ok(WavWriter {
+            out,
+            data_len: 0,
+            max_data_len: Self::MAX_DATA_LEN,
+            full: false,
+        })
+    }
+
+    /// Appends `samples`, as far as the file has room for them. The video
+    /// carries on without audio once it is full.
+    fn write_samples(&mut self, samples: &[[i16; 2]]) -> io::Result<()> {
+        let room = ((self.max_data_len - self.data_len) / Self::BYTES_PER_PAIR) as usize;
+        if samples.len() > room && !self.full {
+            println!("WARNING: the WAV is full, so the rest of the audio was not written");
+            self.full = true;
+        }
+
+        let samples = &samples[..samples.len().min(room)];
+        for [left, right] in samples {
+            self.out.write_i16::<LittleEndian>(*left)?;
+            self.out.write_i16::<LittleEndian>(*right)?;
+        }
+        self.data_len += samples.len() as u32 * Self::BYTES_PER_PAIR;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_u32::<LittleEndian>(Self::HEADER_LEN - 8 + self.data_len)?;
+        self.out
+            .seek(SeekFrom::Start(Self::HEADER_LEN as u64 - 4))?;
+        self.out.write_u32::<LittleEndian>(self.data_len)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::ReadBytesExt;
+
+    use super::*;
+
+    #[test]
+    fn the_y4m_header_describes_both_screens_stacked() {
+        let mut out = Vec::new();
+        Y4mWriter::new(&mut out, (60, 1)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "YUV4MPEG2 W256 H384 F60:1 Ip A1:1 C444\n"
+        );
+    }
+
+    #[test]
+    fn native_frames_are_labelled_with_their_exact_rate() {
+        let mut out = Vec::new();
+        Y4mWriter::new(&mut out, Pacing::Native.frame_rate()).unwrap();
+
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains(" F33513982:560190 "));
+    }
+
+    #[test]
+    fn a_frame_is_one_full_resolution_plane_per_component() {
+        let mut out = Vec::new();
+        let mut video = Y4mWriter::new(&mut out, (60, 1)).unwrap();
+        video.write_frame(&Frames::blank()).unwrap();
+        drop(video);
+
+        let header = "YUV4MPEG2 W256 H384 F60:1 Ip A1:1 C444\n".len();
+        assert_eq!(out.len(), header + "FRAME\n".len() + 3 * 256 * 384);
+    }
+
+    #[test]
+    fn the_top_screen_comes_first() {
+        let mut frames = Frames::blank();
+        frames.top.fill(0xff);
+
+        let mut out = Vec::new();
+        let mut video = Y4mWriter::new(&mut out, (60, 1)).unwrap();
+        video.write_frame(&frames).unwrap();
+
+        let luma = &video.plane[..256 * 384];
+        assert_eq!(luma[0], 235);
+        assert_eq!(luma[256 * 384 - 1], 16);
+    }
+
+    #[test]
+    fn primaries_land_at_studio_range() {
+        assert_eq!(yuv(0, 0, 0), [16, 128, 128]);
+        assert_eq!(yuv(255, 255, 255), [235, 128, 128]);
+        assert_eq!(yuv(255, 0, 0), [82, 90, 240]);
+    }
+
+    #[test]
+    fn audio_keeps_pace_with_the_frames_whatever_its_skew() {
+        let mut fit = FrameAudio::new((60, 1));
+
+        assert_eq!(fit.stretch(&vec![[1, -1]; 796]).len(), 800);
+        assert_eq!(fit.stretch(&vec![[1, -1]; 804]).len(), 800);
+        assert_eq!(fit.stretch(&[]), vec![[0, 0]; 800]);
+
+        let mut fit = FrameAudio::new((48_000, 5));
+        let lens: Vec<usize> = (0..4).map(|_| fit.stretch(&[[0, 0]; 3]).len()).collect();
+        assert_eq!(lens, [5, 5, 5, 5]);
+
+        let mut fit = FrameAudio::new((96_000, 5));
+        let lens: Vec<usize> = (0..4).map(|_| fit.stretch(&[[0, 0]; 3]).len()).collect();
+        assert_eq!(lens, [2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn stretching_interpolates_between_pairs() {
+        let mut fit = FrameAudio::new((12_000, 1));
+        assert_eq!(
+            fit.stretch(&[[0, 0], [10, -10]]),
+            [[0, 0], [5, -5], [10, -10], [10, -10]]
+        );
+    }
+
+    #[test]
+    fn the_wav_header_is_patched_with_the_final_length() {
+        let mut audio = WavWriter::new(Cursor::new(Vec::new())).unwrap();
+        audio.write_samples(&[[1, -1], [2, -2]]).unwrap();
+        audio.write_samples(&[[3, -3]]).unwrap();
+        audio.finish().unwrap();
+
+        let bytes = audio.out.into_inner();
+        let mut header = Cursor::new(&bytes);
+
+        header.set_position(4);
+        assert_eq!(header.read_u32::<LittleEndian>().unwrap(), 36 + 12);
+        header.set_position(40);
+        assert_eq!(header.read_u32::<LittleEndian>().unwrap(), 12);
+        assert_eq!(bytes.len(), 44 + 12);
+        assert_eq!(&bytes[44..48], &[1, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn a_full_wav_keeps_what_fits_and_a_valid_header() {
+        let mut audio = WavWriter::new(Cursor::new(Vec::new())).unwrap();
+        audio.max_data_len = 8;
+        audio.write_samples(&[[1, -1], [2, -2], [3, -3]]).unwrap();
+        audio.write_samples(&[[4, -4]]).unwrap();
+        audio.finish().unwrap();
+        assert!(audio.full);
+
+        let bytes = audio.out.into_inner();
+        let mut header = Cursor::new(&bytes);
+
+        header.set_position(40);
+        assert_eq!(header.read_u32::<LittleEndian>().unwrap(), 8);
+        assert_eq!(bytes.len(), 44 + 8);
+    }
+
+    #[test]
+    fn the_largest_wav_still_has_a_representable_riff_size() {
+        type Wav = WavWriter<Cursor<Vec<u8>>>;
+
+        assert!(Wav::MAX_DATA_LEN.checked_add(Wav::HEADER_LEN - 8).is_some());
+        assert_eq!(Wav::MAX_DATA_LEN % Wav::BYTES_PER_PAIR, 0);
+    }
+}
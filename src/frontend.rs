@@ -1,18 +1,27 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
 use tokio::sync::{mpsc, watch};
 
 use crate::audio::Audio;
+use crate::debug::{Cpu, Tracer};
+use crate::encode::Encoder;
 use crate::input::{
     Binding, BindingOutcome, Bindings, BoundaryIndex, BoundaryInput, ConsoleInputState,
-    FrontendCommand, InputAccumulator, InputEvent, KeyCombination, SystemAction,
+    FrontendCommand, HeldCommand, InputAccumulator, InputChange, InputEvent, InputProvider,
+    KeyCombination, SystemAction,
 };
 use crate::melon::nds::Nds;
+use crate::pacing::Pacing;
 use crate::replay::SavestateContextReplay;
-use crate::replay::{Replay, SavestateContext};
+use crate::replay::{
+    Checkpoint, CheckpointSettings, Desync, Replay, ReplaySource, SavestateContext,
+};
+use crate::rewind::{Rewind, RewindSettings};
+#[cfg(feature = "lua")]
+use crate::script::{ScriptOverlay, Scripting};
+use crate::speed::{Speed, SpeedControl};
 use crate::observe::{FrameObserver, FrameView};
 use crate::utils::localize_pathbuf;
 use crate::EmuStateChange;
@@ -30,6 +39,8 @@ pub enum Request {
     WriteRam(PathBuf),
     WriteReplay,
     WriteSavedata(PathBuf),
+    LoadScript(PathBuf),
+    StopScript,
 }
 
 /// A file the emulator wants written, handed off so a multi-megabyte write never
@@ -65,43 +76,94 @@ pub struct Frontend {
     /// Finished frames, for whoever is drawing. Sent behind an [`Arc`] so that
     /// publishing one cannot wait on the reader.
     frames: watch::Sender<Arc<Frames>>,
-    audio: Audio,
+    /// The output device's end of the audio path, if there is a device at all.
+    audio: Option<Audio>,
+    /// Whatever melonDS produced during the last frame.
+    samples: Vec<[i16; 2]>,
     bindings: Bindings,
     inputs: InputAccumulator,
     observers: Vec<Box<dyn FrameObserver>>,
+    input_providers: Vec<Box<dyn InputProvider>>,
+    encoder: Option<Encoder>,
+    /// The rate encodings are labelled with.
+    pacing: Pacing,
+    checkpoints: CheckpointSettings,
+    /// The first checkpoint the replay being played back failed to reproduce.
+    desync: Option<Desync>,
+    rewind: Option<Rewind>,
+    /// Whether the rewind binding is held, and how many frames it has been held
+    /// since the console last stepped back.
+    rewinding: Option<u64>,
+    speed: SpeedControl,
+    tracer: Option<Tracer>,
+    #[cfg(feature = "lua")]
+    scripting: Scripting,
 }
 
 impl Frontend {
+    /// Takes over a console that has already been booted.
     pub fn new(
-        cart: Vec<u8>,
-        save: Option<Vec<u8>>,
-        time: DateTime<Utc>,
-        audio: Audio,
+        nds: Nds,
+        audio: Option<Audio>,
         key_map: HashMap<KeyCombination, Binding>,
         replay: Option<(Replay, ReplayState)>,
         frames: watch::Sender<Arc<Frames>>,
     ) -> Self {
-        let mut nds = Nds::new();
-
-        nds.set_nds_cart(&cart, save.as_deref());
-        nds.set_time(time);
-
-        println!("Needs direct boot? {:?}", nds.needs_direct_boot());
-
-        if nds.needs_direct_boot() {
-            nds.setup_direct_boot(String::from("TEMP"));
-        }
-
-        nds.start();
-
-        Frontend {
+        let mut frontend = Frontend {
             nds,
             audio,
+            samples: Vec::new(),
             bindings: Bindings::new(key_map),
             inputs: InputAccumulator::new(),
             replay,
             frames,
             observers: Vec::new(),
+            input_providers: Vec::new(),
+            encoder: None,
+            pacing: Pacing::default(),
+            checkpoints: CheckpointSettings::default(),
+            desync: None,
+            rewind: None,
+            rewinding: None,
+            speed: SpeedControl::default(),
+            tracer: None,
+            #[cfg(feature = "lua")]
+            scripting: Scripting::new(),
+        };
+        frontend.restore_replay_source();
+
+        frontend
+    }
+
+    /// Moves the console to where a replay that starts from a savestate
+    /// begins.
+    ///
+    /// A new recording has no inputs yet, so it takes its starting frame from
+    /// the savestate rather than the other way around.
+    fn restore_replay_source(&mut self) {
+        let Some((replay, replay_state)) = self.replay.as_mut() else {
+            return;
+        };
+        let ReplaySource::Savestate { state, start_frame } = &mut replay.source else {
+            return;
+        };
+
+        let contents = state
+            .read()
+            .unwrap_or_else(|err| panic!("Couldn't read the replay's savestate: {err}"));
+        assert!(
+            self.nds.load_savestate(&contents),
+            "The replay's savestate could not be loaded"
+        );
+
+        let frame = self.nds.current_frame();
+        if *replay_state == ReplayState::Recording && replay.inputs.is_empty() {
+            *start_frame = frame;
+        } else if frame != *start_frame {
+            println!(
+                "WARNING: the replay expects to start on frame {start_frame}, \
+                but its savestate was taken on frame {frame}"
+            );
         }
     }
 
@@ -113,6 +175,93 @@ impl Frontend {
         self
     }
 
+    /// See [`InputProvider`] for where providers stand among the other
+    /// sources of input.
+    pub fn with_input_providers(
+        mut self,
+        providers: impl IntoIterator<Item = Box<dyn InputProvider>>,
+    ) -> Self {
+        self.input_providers = providers.into_iter().collect();
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoints: CheckpointSettings) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    pub fn with_rewind(mut self, settings: RewindSettings) -> Self {
+        self.rewind = settings.enabled.then(|| Rewind::new(settings));
+        self
+    }
+
+    /// Calibrates the audio for frames scheduled by `pacing`.
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.nds.set_audio_output_skew(pacing.calibrated_skew());
+        self.pacing = pacing;
+        if let Some(audio) = self.audio.as_mut() {
+            audio.set_pacing(pacing);
+        }
+        self
+    }
+
+    pub fn with_tracer(mut self, tracer: Option<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    pub fn with_turbo_speed(mut self, speed: Speed) -> Self {
+        self.speed.turbo = speed;
+        self
+    }
+
+    /// Queues a console input change directly, for callers with no host
+    /// bindings to go through.
+    pub fn queue_input(&mut self, change: InputChange) {
+        self.inputs.apply(change);
+    }
+
+    pub fn replay(&self) -> Option<&(Replay, ReplayState)> {
+        self.replay.as_ref()
+    }
+
+    /// Where the replay being played back first stopped matching its
+    /// checkpoints, if it has.
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+
+    /// How fast the bindings have asked for emulation to run.
+    pub fn speed(&self) -> Speed {
+        self.speed.current()
+    }
+
+    /// The selected speed and the state of the turbo bindings, for consoles
+    /// that keep the same speed as each other.
+    pub fn speed_control(&self) -> SpeedControl {
+        self.speed
+    }
+
+    pub fn set_speed_control(&mut self, control: SpeedControl) {
+        self.speed = control;
+    }
+
+    /// How far ahead of the device the audio is, as a fraction of the backlog
+    /// it aims for, while there is audio being played.
+    pub fn audio_backlog(&self) -> Option<f64> {
+        let playing = self.speed.current() == Speed::Normal && self.rewinding.is_none();
+
+        self.audio
+            .as_ref()
+            .filter(|_| playing)
+            .map(|audio| audio.backlog())
+    }
+
+    /// The audio melonDS produced during the last frame.
+    pub fn samples(&self) -> &[[i16; 2]] {
+        &self.samples
+    }
+
     pub fn handle_input_event(
         &mut self,
         event: InputEvent,
@@ -124,6 +273,7 @@ impl Frontend {
             Some(BindingOutcome::Command(command)) => {
                 self.run_command(command, state_tx, request_tx)
             }
+            Some(BindingOutcome::Held(command, held)) => self.hold(command, held),
             None => {}
         }
     }
@@ -176,27 +326,214 @@ impl Frontend {
             FrontendCommand::SaveReplay => {
                 request_tx.try_send(Request::WriteReplay).unwrap();
             }
+            FrontendCommand::ToggleEncoding(path) => match self.encoder {
+                Some(_) => self.stop_encoding(),
+                None => self.start_encoding(path.as_ref()),
+            },
+            FrontendCommand::ToggleTrace(path) => self.toggle_trace(path.as_ref()),
+            FrontendCommand::SetSpeed(speed) => {
+                self.speed.selected = speed;
+                println!("Speed set to {speed}");
+            }
+            FrontendCommand::ToggleTurbo => {
+                self.speed.turbo_toggled = !self.speed.turbo_toggled;
+            }
+        }
+    }
+
+    fn hold(&mut self, command: HeldCommand, held: bool) {
+        match command {
+            HeldCommand::Rewind => self.rewinding = held.then_some(0),
+            HeldCommand::Turbo => self.speed.turbo_held = held,
+        }
+    }
+
+    /// Encodes every frame from the next one on, replacing any encoding already
+    /// running.
+    pub fn start_encoding(&mut self, stem: &Path) {
+        self.stop_encoding();
+
+        match Encoder::start(stem, self.pacing) {
+            Ok(encoder) => self.encoder = Some(encoder),
+            Err(err) => println!(
+                "WARNING: couldn't start encoding to {}: {err}",
+                stem.display()
+            ),
+        }
+    }
+
+    /// Waits for the frames encoded so far to be written.
+    pub fn stop_encoding(&mut self) {
+        if self.encoder.take().is_some() {
+            println!("stopped encoding");
+        }
+    }
+
+    /// Starts tracing to `path`, at the start bound of the trace settings, or
+    /// stops the trace running.
+    pub fn toggle_trace(&mut self, path: &Path) {
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer.toggle(path),
+            None => println!("WARNING: tracing needs the `trace` section of config.yml"),
         }
     }
 
+    /// Runs the Lua script at `path` alongside the game, in place of any
+    /// already running.
+    pub fn load_script(&mut self, path: &Path) {
+        #[cfg(feature = "lua")]
+        self.scripting.load(path, &mut self.nds);
+        #[cfg(not(feature = "lua"))]
+        println!(
+            "WARNING: built without the lua feature, so {} can't run",
+            path.display()
+        );
+    }
+
+    pub fn stop_script(&mut self) {
+        #[cfg(feature = "lua")]
+        self.scripting.stop();
+    }
+
+    /// Draws what scripts draw, in the window.
+    #[cfg(feature = "lua")]
+    pub fn script_overlay(&self) -> ScriptOverlay {
+        self.scripting.render_hook()
+    }
+
     pub fn run_frame(&mut self) {
+        if self.rewinding.is_some() && self.rewind.is_some() {
+            self.rewind_frame();
+            return;
+        }
+
+        // Before the input is sampled, so it's sampled and recorded for the
+        // boundary the savestate lands on.
+        #[cfg(feature = "lua")]
+        self.load_script_savestate();
+
         let input = self.select_input();
         self.record(&input);
         self.apply_input(&input);
 
+        let frame = self.nds.current_frame() as u64;
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.before_frame(frame + 1);
+        }
+
         self.nds.run_frame();
 
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.after_frame(frame + 1);
+        }
+
         self.notify_observers(&input.state);
+        #[cfg(feature = "lua")]
+        self.scripting.after_frame(&mut self.nds);
 
         self.update_audio();
+        let frames = self.publish_frames();
+        self.checkpoint(&frames);
+        self.keep_rewind_state();
+
+        if let Some(encoder) = self.encoder.as_mut() {
+            let frame = self.nds.current_frame() as u64;
+            if !encoder.push(frame, frames, self.samples.clone()) {
+                self.encoder = None;
+            }
+        }
+    }
+
+    fn keep_rewind_state(&mut self) {
+        let frame = self.nds.current_frame() as u64;
+
+        if let Some(rewind) = self.rewind.as_mut().filter(|rewind| rewind.is_due(frame)) {
+            rewind.push(frame, self.nds.savestate());
+        }
+    }
+
+    /// Stands in for a frame while the rewind binding is held.
+    ///
+    /// Savestates are only kept every so often, so the console steps back one
+    /// of them each time that many frames have passed, keeping the rewind to
+    /// roughly the speed the game was played at. Rewinding a recording drops
+    /// the inputs from the point it lands on, just as recording over them would.
+    fn rewind_frame(&mut self) {
+        let (Some(rewind), Some(held)) = (self.rewind.as_mut(), self.rewinding.as_mut()) else {
+            return;
+        };
+
+        *held += 1;
+        if *held < rewind.interval() {
+            return;
+        }
+        *held = 0;
+
+        let current = self.nds.current_frame() as u64;
+        let earliest = match &self.replay {
+            Some((replay, _)) => replay.source.start_frame() as u64,
+            None => 0,
+        };
+        let Some((frame, state)) = rewind.rewind_before(current, earliest) else {
+            return;
+        };
+
+        if !self.nds.load_savestate(state) {
+            println!("WARNING: couldn't rewind to frame {frame}");
+            rewind.clear();
+            return;
+        }
+
+        self.record_over_from(frame);
+
+        self.samples.clear();
         self.publish_frames();
     }
 
+    /// Drops what a recording holds from `frame` on, for the console has gone
+    /// back there and will record it again.
+    fn record_over_from(&mut self, frame: u64) {
+        if let Some((replay, ReplayState::Recording)) = self.replay.as_mut() {
+            record_over(replay, frame);
+        }
+    }
+
+    /// Takes a checkpoint while recording, or checks the one recorded for this
+    /// frame while playing back.
+    fn checkpoint(&mut self, frames: &Frames) {
+        let frame = self.nds.current_frame() as u64;
+
+        match self.replay.as_mut() {
+            Some((replay, ReplayState::Recording)) => {
+                if self.checkpoints.is_due(frame) {
+                    replay.record_checkpoint(Checkpoint::new(frame, self.nds.main_ram(), frames));
+                } else {
+                    replay.discard_checkpoints_from(frame);
+                }
+            }
+            Some((replay, ReplayState::Playing)) if self.desync.is_none() => {
+                let Some(expected) = replay.checkpoint(frame) else {
+                    return;
+                };
+
+                let actual = Checkpoint::new(frame, self.nds.main_ram(), frames);
+                if let Some(desync) = expected.compare(&actual) {
+                    println!("WARNING: {desync}");
+                    self.desync = Some(desync);
+                }
+            }
+            _ => {}
+        }
+    }
+
     fn notify_observers(&mut self, input: &ConsoleInputState) {
         let view = FrameView {
             frame: self.nds.current_frame() as u64,
             main_ram: self.nds.main_ram(),
             input: *input,
+            arm9: self.nds.registers(Cpu::Arm9),
+            arm7: self.nds.registers(Cpu::Arm7),
+            nds: &self.nds,
         };
 
         for observer in &mut self.observers {
@@ -208,32 +545,32 @@ impl Frontend {
     ///
     /// The accumulator is sampled even during playback, so that host input
     /// accumulated while watching cannot leak into the first recorded window
-    /// after switching to recording.
+    /// after switching to recording. Input providers and scripts only see live
+    /// input, never a replay's.
     fn select_input(&mut self) -> BoundaryInput {
-        let boundary = self.nds.current_frame() as usize;
-        let live = self.inputs.sample(BoundaryIndex(boundary as u64));
+        let boundary = self.nds.current_frame() as u64;
+        let live = self.inputs.sample(BoundaryIndex(boundary));
+        let recorded = match &self.replay {
+            Some((replay, ReplayState::Playing)) => replay.input(boundary),
+            _ => None,
+        };
 
-        match &self.replay {
-            Some((replay, ReplayState::Playing)) if boundary < replay.inputs.len() => {
-                replay.inputs[boundary].clone()
-            }
-            _ => live,
-        }
+        let providers = self.input_providers.iter_mut().map(|provider| {
+            move |nds: &Nds, input: &mut BoundaryInput| provider.provide(nds, input)
+        });
+        #[cfg(feature = "lua")]
+        let script = |nds: &mut Nds, input| self.scripting.before_frame(nds, input);
+        #[cfg(not(feature = "lua"))]
+        let script = |_: &mut Nds, input| input;
+
+        settle_input(&mut self.nds, live, recorded, providers, script)
     }
 
     fn record(&mut self, input: &BoundaryInput) {
-        let boundary = self.nds.current_frame() as usize;
+        let boundary = self.nds.current_frame() as u64;
 
         if let Some((replay, ReplayState::Recording)) = self.replay.as_mut() {
-            if boundary <= replay.inputs.len() {
-                replay.inputs.splice(boundary.., [input.clone()]);
-            } else {
-                println!(
-                    "WARNING: the replay is in recording mode, but \
-                                cannot record new inputs, because the current \
-                                frame extends beyond the last recorded frame"
-                )
-            }
+            record_input(replay, boundary, input);
         }
     }
 
@@ -270,20 +607,36 @@ impl Frontend {
     }
 
     pub fn update_audio(&mut self) {
-        let skew = self.audio.submit(&self.nds.read_audio_output());
-
-        self.nds.set_audio_output_skew(skew);
+        self.samples = self.nds.read_audio_output();
+
+        // With no device there is no clock to keep pace with, so the skew melonDS
+        // started with is left alone. Away from normal speed the audio would
+        // arrive faster or slower than the device plays it, so it is left out
+        // and the device holds still until normal speed resumes.
+        if let Some(audio) = self
+            .audio
+            .as_mut()
+            .filter(|_| self.speed.current() == Speed::Normal)
+        {
+            let skew = audio.submit(&self.samples);
+
+            self.nds.set_audio_output_skew(skew);
+        }
     }
 
     /// Hands the finished screens to whoever is drawing.
-    fn publish_frames(&mut self) {
+    fn publish_frames(&mut self) -> Arc<Frames> {
         let mut frames = Frames::blank();
 
         self.nds.update_framebuffers(&mut frames.top, false);
         self.nds.update_framebuffers(&mut frames.bottom, true);
 
+        let frames = Arc::new(frames);
+
         // A closed window outliving the last frame is not worth reporting.
-        let _ = self.frames.send(Arc::new(frames));
+        let _ = self.frames.send(frames.clone());
+
+        frames
     }
 
     pub fn read_savestate(&mut self, file: String) {
@@ -306,20 +659,79 @@ impl Frontend {
         }
         let context: SavestateContext = context_result.unwrap();
 
-        match (&mut self.replay, context.replay) {
+        if self.adopt_savestate_context(context.replay) {
+            assert!(self.nds.read_savestate(localized));
+            self.loaded_savestate();
+        }
+    }
+
+    /// Loads a savestate held in memory, which has no replay context. A
+    /// recording carries on from the frame it lands on, as after a rewind, and
+    /// a replay being played back refuses it.
+    pub fn load_savestate(&mut self, state: &[u8]) -> bool {
+        if let Some((_, ReplayState::Playing)) = &self.replay {
+            println!("The savestate couldn't be loaded. A replay is playing back, and the savestate doesn't belong to it");
+            return false;
+        }
+        if !self.nds.load_savestate(state) {
+            println!("WARNING: the savestate couldn't be loaded");
+            return false;
+        }
+
+        self.record_over_from(self.nds.current_frame() as u64);
+        self.loaded_savestate();
+        true
+    }
+
+    /// Loads a savestate a script asked for during the last frame, as any
+    /// savestate held in memory is loaded.
+    #[cfg(feature = "lua")]
+    fn load_script_savestate(&mut self) {
+        let Some(state) = self.scripting.take_savestate() else {
+            return;
+        };
+
+        self.load_savestate(&state);
+    }
+
+    /// Whether a savestate with `context` may replace what the console is
+    /// doing. The replay running takes on the inputs and checkpoints of one
+    /// saved from it.
+    fn adopt_savestate_context(&mut self, context: Option<SavestateContextReplay>) -> bool {
+        match (&mut self.replay, context) {
             (Some(replay), Some(replay_context)) => {
                 if replay_context.name == replay.0.name {
                     replay.0.inputs = replay_context.inputs;
-                    assert!(self.nds.read_savestate(localized));
+                    replay.0.checkpoints = replay_context.checkpoints;
+                    true
                 } else {
-                    println!("The savestate couldn't be loaded. The savestate belongs to a different replay")
+                    println!("The savestate couldn't be loaded. The savestate belongs to a different replay");
+                    false
                 }
             }
-            (Some(_), None) => println!("The savestate couldn't be loaded. There is a replay running, but the savestate doesn't belong to one"),
-            (None, Some(_)) => println!("The savestate couldn't be loaded. There is no replay running, and the savestate belongs to a replay"),
-            (None, None) => {
-                assert!(self.nds.read_savestate(localized));
-            },
+            (Some(_), None) => {
+                println!("The savestate couldn't be loaded. There is a replay running, but the savestate doesn't belong to one");
+                false
+            }
+            (None, Some(_)) => {
+                println!("The savestate couldn't be loaded. There is no replay running, and the savestate belongs to a replay");
+                false
+            }
+            (None, None) => true,
+        }
+    }
+
+    fn loaded_savestate(&mut self) {
+        // Playback from here on may well agree with the replay again.
+        self.desync = None;
+        self.clear_rewind();
+    }
+
+    /// Forgets the rewind history, which belongs to whatever the console was
+    /// doing before a savestate replaced it.
+    fn clear_rewind(&mut self) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
         }
     }
 
@@ -334,6 +746,7 @@ impl Frontend {
             replay: self.replay.as_ref().map(|replay| SavestateContextReplay {
                 name: replay.0.name.clone(),
                 inputs: replay.0.inputs.clone(),
+                checkpoints: replay.0.checkpoints.clone(),
             }),
         };
 
@@ -355,8 +768,195 @@ impl Frontend {
 
         Some(Save {
             path: replay.name.clone(),
-            contents: serde_yaml::to_string(replay).unwrap().into_bytes(),
+            contents: replay.to_bytes(),
         })
     }
 }
 
+/// The input the console sees at a boundary: what a replay being played back
+/// has `recorded` for it, or else the `live` input as each of the `providers`
+/// in turn and then the `script` leave it.
+fn settle_input<N, P>(
+    nds: &mut N,
+    live: BoundaryInput,
+    recorded: Option<&BoundaryInput>,
+    providers: impl IntoIterator<Item = P>,
+    script: impl FnOnce(&mut N, BoundaryInput) -> BoundaryInput,
+) -> BoundaryInput
+where
+    P: FnMut(&N, &mut BoundaryInput),
+{
+    if let Some(recorded) = recorded {
+        return recorded.clone();
+    }
+
+    let mut input = live;
+    for mut provide in providers {
+        provide(nds, &mut input);
+    }
+    script(nds, input)
+}
+
+/// Records `input` for `boundary`, dropping whatever was recorded after it.
+/// Drops what `replay` recorded from `frame` on, for recording again.
+fn record_over(replay: &mut Replay, frame: u64) {
+    if let Some(index) = replay.index_of(frame) {
+        replay.inputs.truncate(index);
+    }
+    replay.discard_checkpoints_from(frame + 1);
+}
+
+fn record_input(replay: &mut Replay, boundary: u64, input: &BoundaryInput) {
+    match replay.index_of(boundary) {
+        Some(index) if index <= replay.inputs.len() => {
+            replay.inputs.splice(index.., [input.clone()]);
+        }
+        Some(_) => println!(
+            "WARNING: the replay is in recording mode, but \
+                        cannot record new inputs, because the current \
+                        frame extends beyond the last recorded frame"
+        ),
+        None => println!(
+            "WARNING: the replay is in recording mode, but \
+                        cannot record new inputs, because the current \
+                        frame is before the replay begins"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::ButtonMask;
+
+    fn input(boundary: u64, buttons: ButtonMask) -> BoundaryInput {
+        BoundaryInput {
+            boundary: BoundaryIndex(boundary),
+            state: ConsoleInputState {
+                buttons,
+                ..Default::default()
+            },
+            actions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn providers_change_the_input_in_order_and_the_script_goes_last() {
+        let mut seen = Vec::new();
+        let providers: [fn(&Vec<&str>, &mut BoundaryInput); 2] = [
+            |_, input| input.state.buttons |= ButtonMask::A,
+            // Only sees A if the first provider ran before it.
+            |_, input| {
+                if input.state.buttons.contains(ButtonMask::A) {
+                    input.state.buttons = ButtonMask::B;
+                }
+            },
+        ];
+
+        let settled = settle_input(
+            &mut seen,
+            input(3, ButtonMask::START),
+            None,
+            providers,
+            |seen, input| {
+                seen.push("script");
+                assert_eq!(input.state.buttons, ButtonMask::B);
+                BoundaryInput {
+                    state: ConsoleInputState {
+                        buttons: input.state.buttons | ButtonMask::X,
+                        ..input.state
+                    },
+                    ..input
+                }
+            },
+        );
+
+        assert_eq!(settled, input(3, ButtonMask::B | ButtonMask::X));
+        assert_eq!(seen, ["script"]);
+    }
+
+    #[test]
+    fn a_replay_being_played_back_skips_providers_and_scripts() {
+        let recorded = input(3, ButtonMask::L);
+        let providers: [fn(&(), &mut BoundaryInput); 1] =
+            [|_, _| panic!("providers don't run for recorded frames")];
+
+        let settled = settle_input(
+            &mut (),
+            input(3, ButtonMask::A),
+            Some(&recorded),
+            providers,
+            |_, _| panic!("scripts don't run for recorded frames"),
+        );
+
+        assert_eq!(settled, recorded);
+    }
+
+    #[test]
+    fn recording_keeps_what_the_providers_settled_on() {
+        let mut replay = Replay {
+            name: PathBuf::from("test.replay"),
+            author: String::new(),
+            source: ReplaySource::None {
+                timestamp: chrono::Utc::now(),
+            },
+            identity: None,
+            checkpoints: Vec::new(),
+            inputs: vec![input(0, ButtonMask::A), input(1, ButtonMask::A)],
+        };
+        let providers: [fn(&(), &mut BoundaryInput); 1] =
+            [|_, input| input.state.buttons = ButtonMask::R];
+
+        let settled = settle_input(
+            &mut (),
+            input(1, ButtonMask::A),
+            None,
+            providers,
+            |_, input| input,
+        );
+        record_input(&mut replay, 1, &settled);
+
+        assert_eq!(
+            replay.inputs,
+            [input(0, ButtonMask::A), input(1, ButtonMask::R)]
+        );
+    }
+
+    /// A script's savestate is loaded before the next input is sampled, so
+    /// that input is recorded where the savestate landed.
+    #[test]
+    fn a_recording_carries_on_from_where_a_script_load_lands() {
+        let mut replay = Replay {
+            name: PathBuf::from("test.replay"),
+            author: String::new(),
+            source: ReplaySource::None {
+                timestamp: chrono::Utc::now(),
+            },
+            identity: None,
+            checkpoints: Vec::new(),
+            inputs: (0..5)
+                .map(|boundary| input(boundary, ButtonMask::A))
+                .collect(),
+        };
+
+        record_over(&mut replay, 2);
+        let settled = settle_input(
+            &mut (),
+            input(2, ButtonMask::B),
+            None,
+            [] as [fn(&(), &mut BoundaryInput); 0],
+            |_, input| input,
+        );
+        record_input(&mut replay, 2, &settled);
+
+        assert_eq!(
+            replay.inputs,
+            [
+                input(0, ButtonMask::A),
+                input(1, ButtonMask::A),
+                input(2, ButtonMask::B)
+            ]
+        );
+    }
+}
+
@@ -0,0 +1,211 @@
+//! An environment for training agents against a game, in the style of Gym:
+//! [`Env::reset`] and [`Env::step`], with the console's state saved and
+//! restored in between.
+//!
+//! An environment opens no window and no audio device, so it builds without
+//! the `gui` feature and with neither eframe nor rodio. Each one holds its own
+//! console, with its own instance of melonDS, on whichever thread owns it.
+
+use std::sync::{Arc, Mutex};
+
+use crate::frontend::Frames;
+use crate::input::{BoundaryInput, ConsoleInputState};
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::Nds;
+use crate::observe::FrameObserver;
+use crate::run::RunParams;
+use crate::session::Session;
+
+/// A run of bytes in a memory domain, observed after every step.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RamRegion {
+    pub domain: MemoryDomain,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl RamRegion {
+    pub fn new(domain: MemoryDomain, offset: usize, len: usize) -> Self {
+        RamRegion {
+            domain,
+            offset,
+            len,
+        }
+    }
+
+    /// The region's bytes, cut short where the domain ends.
+    fn read(&self, nds: &Nds) -> Vec<u8> {
+        let memory = nds.memory(self.domain);
+        let start = self.offset.min(memory.len());
+        let end = (self.offset + self.len).min(memory.len());
+        memory[start..end].to_vec()
+    }
+}
+
+/// What an agent sees after a step.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Observation {
+    /// Both screens as of the last frame, in melonDS's BGRA order.
+    pub screens: Arc<Frames>,
+    /// Each of the environment's [`RamRegion`]s, in the order they were given.
+    pub ram: Vec<Vec<u8>>,
+}
+
+/// What else there is to know about a step.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Info {
+    /// The console's frame counter after the step.
+    pub frame: u64,
+    /// Steps taken since the last reset.
+    pub steps: u64,
+}
+
+/// A point to come back to with [`Env::restore_state`].
+#[derive(Debug, Clone)]
+pub struct EnvState {
+    savestate: Vec<u8>,
+    screens: Arc<Frames>,
+    steps: u64,
+}
+
+/// One console, driven a step at a time by an agent.
+///
+/// Each step holds one [`ConsoleInputState`] for a fixed number of frames.
+/// Observers given to [`Env::with_observers`] see every one of those frames,
+/// for working out rewards that need more than the last of them.
+pub struct Env {
+    session: Session,
+    /// What the console is held at, read by the session's input provider.
+    action: Arc<Mutex<ConsoleInputState>>,
+    frames_per_step: u32,
+    ram: Vec<RamRegion>,
+    /// Where [`Env::reset`] goes back to.
+    start: EnvState,
+    screens: Arc<Frames>,
+    steps: u64,
+}
+
+impl Env {
+    /// Boots the console in `params`, which starts each episode from the
+    /// moment of boot until [`Env::with_start_state`] says otherwise. Any
+    /// input providers in `params` run after the agent's action.
+    pub fn new(mut params: RunParams) -> Self {
+        let action = Arc::new(Mutex::new(ConsoleInputState::default()));
+        let held = action.clone();
+        let agent = Box::new(move |_: &Nds, input: &mut BoundaryInput| {
+            input.state = *held.lock().unwrap();
+        });
+        params.input_providers.insert(0, agent);
+
+        let mut session = Session::new(params);
+        let start = EnvState {
+            savestate: session.savestate(),
+            screens: Arc::new(Frames::blank()),
+            steps: 0,
+        };
+
+        Env {
+            screens: start.screens.clone(),
+            session,
+            action,
+            frames_per_step: 1,
+            ram: Vec::new(),
+            start,
+            steps: 0,
+        }
+    }
+
+    /// How many frames each action is held for. At least one.
+    pub fn with_frames_per_step(mut self, frames: u32) -> Self {
+        self.frames_per_step = frames.max(1);
+        self
+    }
+
+    /// The memory each observation carries.
+    pub fn with_ram(mut self, regions: impl IntoIterator<Item = RamRegion>) -> Self {
+        self.ram = regions.into_iter().collect();
+        self
+    }
+
+    /// Starts each episode from `state` instead, such as one taken past the
+    /// game's title screen.
+    pub fn with_start_state(mut self, state: EnvState) -> Self {
+        self.start = EnvState { steps: 0, ..state };
+        self
+    }
+
+    pub fn with_observers(
+        mut self,
+        observers: impl IntoIterator<Item = Box<dyn FrameObserver>>,
+    ) -> Self {
+        self.session = self.session.with_observers(observers);
+        self
+    }
+
+    /// Goes back to the start of an episode, with nothing held.
+    pub fn reset(&mut self) -> Observation {
+        let start = self.start.clone();
+        self.restore_state(&start);
+        *self.action.lock().unwrap() = ConsoleInputState::default();
+        self.observation()
+    }
+
+    /// Holds `action` for the environment's frames per step.
+    pub fn step(&mut self, action: ConsoleInputState) -> (Observation, Info) {
+        *self.action.lock().unwrap() = action;
+        self.session.run_frames(self.frames_per_step as u64);
+        self.screens = self.session.frames();
+        self.steps += 1;
+
+        (self.observation(), self.info())
+    }
+
+    pub fn clone_state(&mut self) -> EnvState {
+        EnvState {
+            savestate: self.session.savestate(),
+            screens: self.screens.clone(),
+            steps: self.steps,
+        }
+    }
+
+    pub fn restore_state(&mut self, state: &EnvState) {
+        assert!(
+            self.session.load_savestate(&state.savestate),
+            "The environment's state could not be loaded"
+        );
+        self.screens = state.screens.clone();
+        self.steps = state.steps;
+    }
+
+    pub fn observation(&self) -> Observation {
+        let nds = self.session.nds();
+        Observation {
+            screens: self.screens.clone(),
+            ram: self.ram.iter().map(|region| region.read(nds)).collect(),
+        }
+    }
+
+    pub fn info(&self) -> Info {
+        Info {
+            frame: self.session.frame(),
+            steps: self.steps,
+        }
+    }
+
+    /// The session underneath, for anything the environment doesn't cover.
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environments_can_each_run_on_their_own_thread() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Env>();
+        assert_send::<EnvState>();
+    }
+}
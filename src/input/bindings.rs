@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use super::accumulator::InputChange;
 use super::model::{ConsoleButton, TouchPoint};
 use super::primitives::{HoldChange, ValueChange};
+use crate::speed::Speed;
 
 bitflags! {
     /// The modifiers a binding can require.
@@ -58,6 +59,7 @@ pub struct KeyCombination {
 pub enum Binding {
     Console(ConsoleBinding),
     Command(FrontendCommand),
+    Held(HeldCommand),
 }
 
 /// A binding the emulated console sees.
@@ -80,6 +82,23 @@ pub enum FrontendCommand {
     WriteMainRam(String),
     ToggleReplayMode,
     SaveReplay,
+    /// Starts encoding video and audio to files with this stem, or stops the
+    /// encoding already running.
+    ToggleEncoding(String),
+    /// Starts tracing the instructions the CPUs run to this file, or stops
+    /// the trace already running.
+    ToggleTrace(String),
+    SetSpeed(Speed),
+    /// Switches to the turbo speed until toggled again.
+    ToggleTurbo,
+}
+
+/// A binding the emulator acts on for as long as its key is down, rather than
+/// once per press.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum HeldCommand {
+    Rewind,
+    Turbo,
 }
 
 /// What a host event turned out to mean.
@@ -87,6 +106,8 @@ pub enum FrontendCommand {
 pub enum BindingOutcome {
     Console(InputChange),
     Command(FrontendCommand),
+    /// A held command starting, or stopping when `false`.
+    Held(HeldCommand, bool),
 }
 
 /// Translates host events into console input changes and frontend commands.
@@ -165,6 +186,7 @@ impl Bindings {
                 BindingOutcome::Console(InputChange::LidClosed(true))
             }
             Binding::Command(command) => BindingOutcome::Command(command),
+            Binding::Held(command) => BindingOutcome::Held(command, true),
         })
     }
 
@@ -175,6 +197,7 @@ impl Bindings {
             Binding::Console(ConsoleBinding::Button(button)) => Some(BindingOutcome::Console(
                 InputChange::Button(HoldChange::Release(button)),
             )),
+            Binding::Held(command) => Some(BindingOutcome::Held(command, false)),
             // The lid is absolute state and commands fire on press, so neither
             // has anything to do when the key comes up.
             Binding::Console(_) | Binding::Command(_) => None,
@@ -263,6 +286,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn a_held_command_lasts_until_its_key_comes_up() {
+        let mut bindings = Bindings::new(HashMap::from([(
+            KeyCombination {
+                key_code: Key::Backspace,
+                modifiers: Modifiers::empty(),
+            },
+            Binding::Held(HeldCommand::Rewind),
+        )]));
+
+        assert_eq!(
+            bindings.handle(InputEvent::KeyDown(Key::Backspace)),
+            Some(BindingOutcome::Held(HeldCommand::Rewind, true))
+        );
+        assert_eq!(bindings.handle(InputEvent::KeyDown(Key::Backspace)), None);
+        assert_eq!(
+            bindings.handle(InputEvent::KeyUp(Key::Backspace)),
+            Some(BindingOutcome::Held(HeldCommand::Rewind, false))
+        );
+    }
+
     #[test]
     fn a_key_released_under_different_modifiers_still_releases() {
         let mut bindings = bindings();
@@ -0,0 +1,27 @@
+use crate::melon::nds::Nds;
+
+use super::BoundaryInput;
+
+/// Supplies or changes the input the console sees at each frame boundary,
+/// for bots, automatic resets, or filters over what the player holds.
+///
+/// Providers run in the order they were registered, each handed what the
+/// host's bindings produced, as changed by the providers before it, and
+/// after them comes any Lua script. What they settle on is what a replay in
+/// recording mode records. A replay being played back takes precedence over
+/// all of them: neither providers nor the script are asked for the frames it
+/// has inputs for, so it plays back as it was recorded.
+pub trait InputProvider: Send {
+    /// Changes `input` as needed for the frame about to run, leaving its
+    /// `boundary` alone. `nds` is the console as the last frame left it.
+    fn provide(&mut self, nds: &Nds, input: &mut BoundaryInput);
+}
+
+impl<F> InputProvider for F
+where
+    F: FnMut(&Nds, &mut BoundaryInput) + Send,
+{
+    fn provide(&mut self, nds: &Nds, input: &mut BoundaryInput) {
+        self(nds, input)
+    }
+}
@@ -1,37 +1,58 @@
+#[cfg(feature = "gui")]
 pub mod app;
 pub mod audio;
 pub mod config;
+pub mod debug;
+pub mod encode;
 pub mod events;
 pub mod frontend;
+pub mod gym;
 pub mod input;
 pub mod melon;
 pub mod observe;
 pub mod overlay;
+pub mod pacing;
+pub mod panels;
+pub mod ram;
 pub mod render;
 pub mod replay;
+pub mod rewind;
 pub mod run;
+#[cfg(feature = "lua")]
+pub mod script;
+pub mod session;
+pub mod speed;
 pub mod utils;
 
-pub use input::ConsoleInputState;
+pub use input::{ConsoleInputState, InputProvider};
 pub use observe::{FrameObserver, FrameView};
 pub use overlay::{
     Color, DrawCmd, Text, Line, Overlay, Point, Rect, Screen, TextAlign, DEFAULT_ADVANCE,
     DEFAULT_CELL_HEIGHT, DEFAULT_CELL_WIDTH, DEFAULT_FONT_SIZE,
 };
 pub use render::{RenderContext, RenderHook, RenderStatus, ScreenRect};
-pub use run::{run, RunParams};
+#[cfg(feature = "gui")]
+pub use run::{run, run_multiplayer};
+pub use run::RunParams;
+pub use session::Session;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum EmuState {
     Running,
     Paused,
     Stepping,
     Stopped,
+    /// Stopped by a debugger attached to the GDB stub, which holds the
+    /// emulator thread inside its frame until it lets the console go.
+    Debugging,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EmuStateChange {
     PlayPause,
+    /// Pauses if running, unlike [`EmuStateChange::PlayPause`], so it can be
+    /// sent without knowing the current state.
+    Pause,
     Step,
     Stop,
 }
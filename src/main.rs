@@ -1,18 +1,22 @@
 mod args;
 
+use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
 
-use args::{Args, Commands};
+use args::{Args, Commands, DisasmArgs, RenderArgs, ReplayArgs};
 use chrono::{DateTime, Utc};
 use clap::Parser;
 use melon_rs::{
-    config::{Config, ConfigFile, StartParams},
+    config::{key_map_from_entries, Config, ConfigFile, ConfigKeyMapEntry, StartParams},
+    debug::{Cpu, GdbSettings},
     frontend::ReplayState,
-    replay::{Replay, ReplaySource},
-    run::{RunParams, run},
+    input::{Binding, KeyCombination},
+    replay::{CheckpointSettings, Mismatch, Replay, ReplaySource, SavestateSource},
+    run::{RunParams, run, run_multiplayer},
+    session::Session,
 };
 
-#[ignore = "irrefutable_let_patterns"]
 fn start_params(config: &Config, args: Args) -> StartParams {
     let game_name = args
         .game
@@ -37,40 +41,70 @@ fn start_params(config: &Config, args: Args) -> StartParams {
                     .cloned();
             }
         }
-        Commands::Replay(replay_args) => {
+        Commands::Replay(ReplayArgs { name, .. }) | Commands::Render(RenderArgs { name, .. }) => {
             replay = Some((
-                serde_yaml::from_str(&fs::read_to_string(&replay_args.name).unwrap()).unwrap(),
+                Replay::load(name)
+                    .unwrap_or_else(|err| panic!("Couldn't load replay {}: {err}", name.display())),
                 ReplayState::Playing,
             ));
         }
         Commands::Record(record_args) => {
+            let timestamp = record_args
+                .timestamp
+                .as_ref()
+                .map(|datetime| {
+                    DateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S%.f%z")
+                        .expect("The datetime could not be parsed")
+                })
+                .map(Into::into)
+                .unwrap_or_else(Utc::now);
+
+            let source = match &record_args.savestate {
+                Some(path) if record_args.embed => ReplaySource::Savestate {
+                    state: SavestateSource::Embedded(fs::read(path).unwrap_or_else(|_| {
+                        panic!("Couldn't open savestate with path {}", path.display())
+                    })),
+                    start_frame: 0,
+                },
+                Some(path) => ReplaySource::Savestate {
+                    state: SavestateSource::File(path.clone()),
+                    start_frame: 0,
+                },
+                None if record_args.clean => ReplaySource::None { timestamp },
+                None => ReplaySource::SaveFile {
+                    path: record_args.save.clone(),
+                    timestamp,
+                },
+            };
+
             replay = Some((
                 Replay {
                     name: record_args.name.clone(),
                     author: record_args.author.clone().unwrap_or_default(),
-                    source: ReplaySource::SaveFile {
-                        path: record_args.save.clone(),
-                        timestamp: record_args
-                            .timestamp
-                            .as_ref()
-                            .map(|datetime| {
-                                DateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S%.f%z")
-                                    .expect("The datetime could not be parsed")
-                            })
-                            .map(Into::into)
-                            .unwrap_or_else(Utc::now),
-                    },
+                    source,
+                    identity: None,
+                    checkpoints: vec![],
                     inputs: vec![],
                 },
                 ReplayState::Recording,
             ));
         }
+        Commands::Disasm(disasm_args) => save_name = disasm_args.save.clone(),
     }
 
     if let Some((replay, _)) = &replay {
-        let ReplaySource::SaveFile { path, timestamp } = &replay.source;
-        save_name = path.clone();
-        start_time = *timestamp;
+        match &replay.source {
+            ReplaySource::SaveFile { path, timestamp } => {
+                save_name = path.clone();
+                start_time = *timestamp;
+            }
+            // The savestate brings its own save data and clock.
+            ReplaySource::Savestate { .. } => save_name = None,
+            ReplaySource::None { timestamp } => {
+                save_name = None;
+                start_time = *timestamp;
+            }
+        }
     }
 
     StartParams {
@@ -81,8 +115,100 @@ fn start_params(config: &Config, args: Args) -> StartParams {
     }
 }
 
+/// Plays the replay back as fast as the host allows, encoding every frame,
+/// and stops when it runs out of inputs.
+fn render(params: RunParams) {
+    let mut session = Session::new(params);
+
+    while !session.replay_finished() {
+        session.step();
+
+        if session.frame().is_multiple_of(3600) {
+            println!("rendered {} frames", session.frame());
+        }
+    }
+
+    println!("rendered {} frames in total", session.frame());
+    if let Some(desync) = session.desync() {
+        println!("WARNING: {desync}");
+    }
+}
+
+/// Runs the game for the requested number of frames, then prints the code in
+/// the range.
+fn disassemble(params: RunParams, args: DisasmArgs) {
+    let mut session = Session::new(params);
+    session.run_frames(args.frames);
+
+    let cpu = if args.arm7 { Cpu::Arm7 } else { Cpu::Arm9 };
+    for instruction in session
+        .nds_mut()
+        .disassemble(cpu, args.start, args.count, args.thumb)
+    {
+        println!("{instruction}");
+    }
+}
+
+/// Exits rather than play back a replay made with a different cart or save,
+/// which would only desync.
+fn refuse_mismatched_replay(params: &RunParams) {
+    let mismatches = params.replay_mismatches();
+    if !mismatches.iter().any(Mismatch::is_fatal) {
+        return;
+    }
+
+    for mismatch in &mismatches {
+        println!("WARNING: {mismatch}");
+    }
+    println!("The replay will not play back correctly. Pass --force to play it anyway");
+    std::process::exit(1);
+}
+
+/// Reads a key map given on the command line.
+fn load_key_map(path: &Path) -> HashMap<KeyCombination, Binding> {
+    let yml = fs::read_to_string(path)
+        .unwrap_or_else(|_| panic!("Couldn't open key map with path {}", path.display()));
+    let entries = serde_yaml::from_str::<Vec<ConfigKeyMapEntry>>(&yml)
+        .unwrap_or_else(|err| panic!("Couldn't parse key map {}: {err}", path.display()));
+
+    key_map_from_entries(entries)
+}
+
+/// The config's debugger settings, with any given on the command line taking
+/// precedence. Any of them turns the debugger on.
+fn gdb_settings(config: Option<GdbSettings>, args: &Args) -> Option<GdbSettings> {
+    if config.is_none() && args.gdb_arm9.is_none() && args.gdb_arm7.is_none() && !args.gdb_break {
+        return None;
+    }
+
+    let settings = config.unwrap_or_default();
+    Some(GdbSettings {
+        arm9_port: args.gdb_arm9.unwrap_or(settings.arm9_port),
+        arm7_port: args.gdb_arm7.unwrap_or(settings.arm7_port),
+        break_on_startup: args.gdb_break || settings.break_on_startup,
+    })
+}
+
 fn main() {
     let args = Args::parse();
+    let encode = match &args.command {
+        Commands::Render(render_args) => Some(render_args.output.clone()),
+        _ => args.encode.clone(),
+    };
+    let rendering = matches!(args.command, Commands::Render(_));
+    let disasm = match &args.command {
+        Commands::Disasm(disasm_args) => Some(disasm_args.clone()),
+        _ => None,
+    };
+    let force = match &args.command {
+        Commands::Replay(replay_args) => replay_args.force,
+        Commands::Render(render_args) => render_args.force,
+        _ => false,
+    };
+    let pause_on_desync = matches!(
+        &args.command,
+        Commands::Replay(replay_args) if replay_args.pause_on_desync
+    );
 
     let config: Config = fs::read_to_string("config.yml")
         .ok()
@@ -90,6 +216,19 @@ fn main() {
         .map(Into::into)
         .unwrap_or_default();
 
+    let gdb = gdb_settings(config.gdb, &args);
+    let script = args.script.clone();
+    let players = match &args.command {
+        Commands::Play(play_args) => play_args.players,
+        _ => 1,
+    };
+    let player_key_maps = match &args.command {
+        Commands::Play(play_args) if !play_args.key_maps.is_empty() => {
+            play_args.key_maps.iter().map(|path| load_key_map(path)).collect()
+        }
+        _ => config.player_key_maps.clone(),
+    };
+
     let StartParams {
         replay,
         game_name,
@@ -112,16 +251,67 @@ fn main() {
         })
     });
 
-    run(
-        RunParams {
-            cart,
-            save,
-            start_time,
-            replay,
-            key_map: config.key_map,
-            window_title: String::from("melon-rs"),
+    let params = RunParams {
+        cart,
+        save,
+        start_time,
+        replay,
+        key_map: config.key_map,
+        window_title: String::from("melon-rs"),
+        encode,
+        checkpoints: CheckpointSettings {
+            pause_on_desync: pause_on_desync || config.checkpoints.pause_on_desync,
+            ..config.checkpoints
         },
-        vec![],
-        vec![],
-    );
+        rewind: config.rewind,
+        turbo_speed: config.turbo_speed,
+        pacing: config.pacing,
+        gdb,
+        hooks: vec![],
+        watch: config.watch,
+        trace: config.trace,
+        script,
+        input_providers: vec![],
+        wireless: None,
+    };
+
+    if !force {
+        refuse_mismatched_replay(&params);
+    }
+
+    if let Some(disasm) = disasm {
+        disassemble(params, disasm);
+    } else if rendering {
+        render(params);
+    } else if players > 1 {
+        run_multiplayer(multiplayer(params, players, player_key_maps));
+    } else {
+        run(params, vec![], vec![]);
+    }
+}
+
+/// The params for each player, the first of them being `first`. The others
+/// start blank with the same game and clock, and with the next of `key_maps`,
+/// or the first player's controls once they run out. Keys only reach whichever
+/// window is focused, and [`run_multiplayer`] turns rewind off for them all.
+fn multiplayer(
+    mut first: RunParams,
+    players: usize,
+    key_maps: Vec<HashMap<KeyCombination, Binding>>,
+) -> Vec<RunParams> {
+    first.window_title = String::from("melon-rs (player 1)");
+
+    let mut key_maps = key_maps.into_iter();
+    let others: Vec<RunParams> = (2..=players)
+        .map(|player| RunParams {
+            start_time: first.start_time,
+            key_map: key_maps.next().unwrap_or_else(|| first.key_map.clone()),
+            window_title: format!("melon-rs (player {player})"),
+            turbo_speed: first.turbo_speed,
+            pacing: first.pacing,
+            ..RunParams::new(first.cart.clone())
+        })
+        .collect();
+
+    std::iter::once(first).chain(others).collect()
 }
@@ -2,6 +2,7 @@
 #include <string>
 #include <stdarg.h>
 
+#include "NDS.h"
 #include "Platform.h"
 #include "Util.cpp"
 
@@ -15,15 +16,30 @@ using namespace melonDS;
 
 namespace melonDS::Platform
 {
+    // every console is made with its PlatformImpl::Instance as its userdata
+    static const PlatformImpl::Instance &Instance(void *userdata)
+    {
+        return *static_cast<PlatformImpl::Instance *>(userdata);
+    }
+
+    // these have no userdata, so they go by the console running on this thread
+    static const PlatformImpl::Instance *CurrentInstance()
+    {
+        NDS *nds = NDS::Current;
+        return nds ? static_cast<PlatformImpl::Instance *>(nds->UserData) : nullptr;
+    }
+
     // initialize std::string with ::rust::String
     std::string InstanceFileSuffix()
     {
-        return std::string(PlatformImpl::InstanceFileSuffix());
+        const PlatformImpl::Instance *instance = CurrentInstance();
+        return instance ? std::string(PlatformImpl::InstanceFileSuffix(*instance)) : "";
     }
 
     int InstanceID()
     {
-        return PlatformImpl::InstanceID();
+        const PlatformImpl::Instance *instance = CurrentInstance();
+        return instance ? PlatformImpl::InstanceID(*instance) : 0;
     }
 
     // synchronization primitives
@@ -193,60 +209,60 @@ namespace melonDS::Platform
 
     void WriteNDSSave(const u8 *savedata, u32 savelen, u32 writeoffset, u32 writelen, void* userdata)
     {
-        return PlatformImpl::WriteNDSSave(savedata, savelen, writeoffset, writelen);
+        return PlatformImpl::WriteNDSSave(savedata, savelen, writeoffset, writelen, Instance(userdata));
     }
     bool MP_Init(void* userdata)
     {
-        return PlatformImpl::MP_Init();
+        return PlatformImpl::MP_Init(Instance(userdata));
     }
     void MP_DeInit(void* userdata)
     {
-        return PlatformImpl::MP_DeInit();
+        return PlatformImpl::MP_DeInit(Instance(userdata));
     }
     void MP_Begin(void* userdata)
     {
-        return PlatformImpl::MP_Begin();
+        return PlatformImpl::MP_Begin(Instance(userdata));
     }
     void MP_End(void* userdata)
     {
-        return PlatformImpl::MP_End();
+        return PlatformImpl::MP_End(Instance(userdata));
     }
     int MP_SendPacket(u8 *data, int len, u64 timestamp, void* userdata)
     {
-        return PlatformImpl::MP_SendPacket(data, len, timestamp);
+        return PlatformImpl::MP_SendPacket(data, len, timestamp, Instance(userdata));
     }
     int MP_RecvPacket(u8 *data, u64 *timestamp, void* userdata)
     {
-        return PlatformImpl::MP_RecvPacket(data, timestamp);
+        return PlatformImpl::MP_RecvPacket(data, timestamp, Instance(userdata));
     }
     int MP_SendCmd(u8 *data, int len, u64 timestamp, void* userdata)
     {
-        return PlatformImpl::MP_SendCmd(data, len, timestamp);
+        return PlatformImpl::MP_SendCmd(data, len, timestamp, Instance(userdata));
     }
     int MP_SendReply(u8 *data, int len, u64 timestamp, u16 aid, void* userdata)
     {
-        return PlatformImpl::MP_SendReply(data, len, timestamp, aid);
+        return PlatformImpl::MP_SendReply(data, len, timestamp, aid, Instance(userdata));
     }
     int MP_SendAck(u8 *data, int len, u64 timestamp, void* userdata)
     {
-        return PlatformImpl::MP_SendAck(data, len, timestamp);
+        return PlatformImpl::MP_SendAck(data, len, timestamp, Instance(userdata));
     }
     int MP_RecvHostPacket(u8 *data, u64 *timestamp, void* userdata)
     {
-        return PlatformImpl::MP_RecvHostPacket(data, timestamp);
+        return PlatformImpl::MP_RecvHostPacket(data, timestamp, Instance(userdata));
     }
     u16 MP_RecvReplies(u8 *data, u64 timestamp, u16 aidmask, void* userdata)
     {
-        return PlatformImpl::MP_RecvReplies(data, timestamp, aidmask);
+        return PlatformImpl::MP_RecvReplies(data, timestamp, aidmask, Instance(userdata));
     }
 
     int Net_SendPacket(u8* data, int len, void* userdata)
     {
-        return PlatformImpl::Net_SendPacket(data, len);
+        return PlatformImpl::Net_SendPacket(data, len, Instance(userdata));
     }
     int Net_RecvPacket(u8* data, void* userdata)
     {
-        return PlatformImpl::Net_RecvPacket(data);
+        return PlatformImpl::Net_RecvPacket(data, Instance(userdata));
     }
 
     bool LAN_Init()
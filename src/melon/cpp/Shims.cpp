@@ -1,21 +1,95 @@
 #include "Shims.h"
 
+#include "Args.h"
+#include "ARM.h"
 #include "GPU.h"
+#include "SPI.h"
 #include "NDS.h"
 #include "types.h"
 #include "NDSCart.h"
 
 #include "rust/cxx.h"
+#include "melon-rs/src/melon/sys.rs.h"
 
 using namespace melonDS;
 
 namespace Shims
 {
-    std::unique_ptr<NDS> New_NDS()
+    std::unique_ptr<NDS> New_NDS(PlatformImpl::Instance *instance)
     {
-        auto nds = std::make_unique<NDS>();
-        NDS::Current = nds.get();
+        return std::make_unique<NDS>(NDSArgs {}, instance);
+    }
+
+#ifdef GDBSTUB_ENABLED
+    // the core is patched to call this whenever the stub holds or lets go of
+    // a CPU (see build.rs)
+    template <bool arm7>
+    static void RunStubHook(ARM &cpu, bool stopped)
+    {
+        auto *instance = static_cast<PlatformImpl::Instance *>(cpu.NDS.UserData);
+        PlatformImpl::StubHook(*instance, arm7, stopped);
+    }
+#endif
+
+    std::unique_ptr<NDS> New_NDS_WithGdb(u16 arm9_port, u16 arm7_port, bool break_on_startup, PlatformImpl::Instance *instance)
+    {
+#ifdef GDBSTUB_ENABLED
+        NDSArgs args {};
+        args.GDB.PortARM9 = arm9_port;
+        args.GDB.PortARM7 = arm7_port;
+        args.GDB.ARM9BreakOnStartup = break_on_startup;
+        args.GDB.ARM7BreakOnStartup = break_on_startup;
+
+        auto nds = std::make_unique<NDS>(std::move(args), instance);
+        nds->ARM9.StubHook = RunStubHook<false>;
+        nds->ARM7.StubHook = RunStubHook<true>;
         return nds;
+#else
+        // built without the stub: there is nothing to serve the ports with
+        return New_NDS(instance);
+#endif
+    }
+
+    // NDS::Current, kept per thread, is the only console the callbacks without
+    // userdata can see, so it's pointed at a console for each call into it, and
+    // put back afterwards. Returns what it was
+    NDS *NDS_SetCurrent(NDS *nds)
+    {
+        NDS *previous = NDS::Current;
+        NDS::Current = nds;
+        return previous;
+    }
+
+    // for a console about to be freed, which nothing on this thread may see
+    void NDS_ForgetCurrent(NDS *nds)
+    {
+        if (NDS::Current == nds)
+        {
+            NDS::Current = nullptr;
+        }
+    }
+
+    // lets go of a CPU the stub holds, and stops it taking another debugger.
+    // Takes a pointer, as it's called from another thread than the console's
+    void NDS_DetachGdb(NDS *nds)
+    {
+#ifdef GDBSTUB_ENABLED
+        nds->ARM9.DetachStub();
+        nds->ARM7.DetachStub();
+#endif
+    }
+
+    // takes a pointer, since the hooks reach the console from inside the frame
+    u32 NDS_RunFrame(NDS *nds)
+    {
+        return nds->RunFrame();
+    }
+
+    void Firmware_OffsetMac(NDS &nds, u8 offset)
+    {
+        Firmware *firmware = nds.SPI.GetFirmware();
+        firmware->GetHeader().MacAddr[5] += offset;
+        firmware->UpdateChecksums();
     }
 
     bool Copy_Framebuffers(NDS &nds, u8 *dest, bool index)
@@ -81,6 +155,343 @@ namespace Shims
         return nds.MainRAMMaxSize;
     }
 
+    // where the current mode keeps its SPSR; User and System mode have none
+    static u32 *ARM_SPSR(ARM &cpu)
+    {
+        switch (cpu.CPSR & 0x1F)
+        {
+        case 0x11: return &cpu.R_FIQ[7];
+        case 0x12: return &cpu.R_IRQ[2];
+        case 0x13: return &cpu.R_SVC[2];
+        case 0x17: return &cpu.R_ABT[2];
+        case 0x1B: return &cpu.R_UND[2];
+        default: return nullptr;
+        }
+    }
+
+    // out is R0-R15, then CPSR, then SPSR, then 1 if the mode has an SPSR
+    void ARM_ReadRegisters(const NDS &nds, bool arm7, u32 *out)
+    {
+        ARM &cpu = arm7 ? (ARM &)nds.ARM7 : (ARM &)nds.ARM9;
+
+        memcpy(out, cpu.R, 16 * sizeof(u32));
+        out[16] = cpu.CPSR;
+
+        u32 *spsr = ARM_SPSR(cpu);
+        out[17] = spsr ? *spsr : 0;
+        out[18] = spsr != nullptr;
+    }
+
+    // index is 0-14 for a register, 15 to jump, 16 for CPSR, and 17 for SPSR
+    void ARM_WriteRegister(NDS &nds, bool arm7, u32 index, u32 value)
+    {
+        ARM &cpu = arm7 ? (ARM &)nds.ARM7 : (ARM &)nds.ARM9;
+
+        if (index < 15)
+        {
+            cpu.R[index] = value;
+        }
+        else if (index == 15)
+        {
+            // JumpTo refills the pipeline, and takes the instruction set from
+            // the low bit
+            cpu.JumpTo((value & ~1) | ((cpu.CPSR & 0x20) ? 1 : 0));
+        }
+        else if (index == 16)
+        {
+            u32 old = cpu.CPSR;
+            cpu.CPSR = value;
+            cpu.UpdateMode(old, value);
+        }
+        else if (u32 *spsr = ARM_SPSR(cpu))
+        {
+            *spsr = value;
+        }
+    }
+
+    // the core is patched to call these (see build.rs)
+    template <bool arm7>
+    static void RunInstrHook(ARM &cpu, u32 addr)
+    {
+        auto *instance = static_cast<PlatformImpl::Instance *>(cpu.NDS.UserData);
+        PlatformImpl::InstrHook(*instance, arm7, addr);
+    }
+
+    template <bool arm7>
+    static void RunMemHook(ARM &cpu, u32 addr, u32 width, bool write, u32 value)
+    {
+        auto *instance = static_cast<PlatformImpl::Instance *>(cpu.NDS.UserData);
+        PlatformImpl::MemHook(*instance, arm7, addr, width, write, value);
+    }
+
+    // calls back before the instructions at addrs, or before every one if all
+    // is set; with neither, the CPU runs without checking
+    void ARM_SetInstrHooks(NDS &nds, bool arm7, rust::Slice<const u32> addrs, bool all)
+    {
+        ARM &cpu = arm7 ? (ARM &)nds.ARM7 : (ARM &)nds.ARM9;
+
+        cpu.HookAddrs = std::unordered_set<u32>(addrs.begin(), addrs.end());
+        cpu.HookAll = all;
+        if (all || !addrs.empty())
+        {
+            cpu.InstrHook = arm7 ? RunInstrHook<true> : RunInstrHook<false>;
+        }
+        else
+        {
+            cpu.InstrHook = nullptr;
+        }
+    }
+
+    // pages are numbered by address >> 14; with none, accesses go unchecked
+    void ARM_SetWatchPages(NDS &nds, bool arm7, rust::Slice<const u32> pages)
+    {
+        ARM &cpu = arm7 ? (ARM &)nds.ARM7 : (ARM &)nds.ARM9;
+
+        cpu.WatchPages.assign(pages.empty() ? 0 : 1 << 18, false);
+        for (u32 page : pages)
+        {
+            cpu.WatchPages[page] = true;
+        }
+        if (pages.empty())
+        {
+            cpu.MemHook = nullptr;
+        }
+        else
+        {
+            cpu.MemHook = arm7 ? RunMemHook<true> : RunMemHook<false>;
+        }
+    }
+
+    // domain is an index from MemoryDomain::index on the Rust side
+    u8 *Memory_Domain(const NDS &nds, u32 domain, u32 *size)
+    {
+        NDS &console = const_cast<NDS &>(nds);
+        GPU &gpu = console.GPU;
+
+        u8 *vram[9] = {gpu.VRAM_A, gpu.VRAM_B, gpu.VRAM_C, gpu.VRAM_D, gpu.VRAM_E,
+                       gpu.VRAM_F, gpu.VRAM_G, gpu.VRAM_H, gpu.VRAM_I};
+        u32 vram_size[9] = {sizeof(gpu.VRAM_A), sizeof(gpu.VRAM_B), sizeof(gpu.VRAM_C),
+                            sizeof(gpu.VRAM_D), sizeof(gpu.VRAM_E), sizeof(gpu.VRAM_F),
+                            sizeof(gpu.VRAM_G), sizeof(gpu.VRAM_H), sizeof(gpu.VRAM_I)};
+
+        switch (domain)
+        {
+        case 0:
+            *size = console.MainRAMMaxSize;
+            return console.MainRAM;
+        case 1:
+            *size = sizeof(console.SharedWRAM);
+            return console.SharedWRAM;
+        case 2:
+            *size = sizeof(console.ARM7WRAM);
+            return console.ARM7WRAM;
+        case 3:
+            *size = sizeof(console.ARM9.ITCM);
+            return console.ARM9.ITCM;
+        case 4:
+            *size = sizeof(console.ARM9.DTCM);
+            return console.ARM9.DTCM;
+        case 14:
+            *size = sizeof(gpu.Palette);
+            return gpu.Palette;
+        case 15:
+            *size = sizeof(gpu.OAM);
+            return gpu.OAM;
+        case 16:
+            *size = console.GetNDSSaveLength();
+            return const_cast<u8 *>(console.GetNDSSave());
+        case 17:
+        {
+            const Firmware *firmware = console.SPI.GetFirmware();
+            *size = firmware->Length();
+            return const_cast<u8 *>(firmware->Buffer());
+        }
+        default:
+            if (domain >= 5 && domain < 14)
+            {
+                *size = vram_size[domain - 5];
+                return vram[domain - 5];
+            }
+            *size = 0;
+            return nullptr;
+        }
+    }
+
+    u32 Memory_DTCMBase(const NDS &nds)
+    {
+        return nds.ARM9.DTCMBase;
+    }
+
+    // the ARM9's bus doesn't include its TCMs, which only it can see, so they
+    // are checked first, the way the CPU does
+    static u8 *ARM9_TCM(NDS &nds, u32 addr)
+    {
+        ARMv5 &cpu = nds.ARM9;
+        if (addr < cpu.ITCMSize)
+        {
+            return &cpu.ITCM[addr & (sizeof(cpu.ITCM) - 1)];
+        }
+        if ((addr & cpu.DTCMMask) == cpu.DTCMBase)
+        {
+            return &cpu.DTCM[addr & (sizeof(cpu.DTCM) - 1)];
+        }
+        return nullptr;
+    }
+
+    // the banks VRAMCNT maps to addr, each answering it at once, as a mask of
+    // bank letters from A
+    static u32 VRAM_Banks(const GPU &gpu, bool arm7, u32 addr)
+    {
+        if (arm7)
+        {
+            return gpu.VRAMMap_ARM7[(addr >> 17) & 0x1];
+        }
+
+        switch (addr & 0x00E00000)
+        {
+        case 0x00000000: return gpu.VRAMMap_ABG[(addr >> 14) & 0x1F];
+        case 0x00200000: return gpu.VRAMMap_BBG[(addr >> 14) & 0x7];
+        case 0x00400000: return gpu.VRAMMap_AOBJ[(addr >> 14) & 0xF];
+        case 0x00600000: return gpu.VRAMMap_BOBJ[(addr >> 14) & 0x7];
+        }
+
+        // LCDC gives each bank an address of its own
+        static const u32 lcdc[9] = {0x00000, 0x20000, 0x40000, 0x60000, 0x80000,
+                                    0x90000, 0x94000, 0x98000, 0xA0000};
+        static const u32 lcdc_end = 0xA4000;
+        u32 offset = addr - 0x06800000;
+        for (int bank = 8; bank >= 0; bank--)
+        {
+            if (offset >= lcdc[bank] && offset < lcdc_end)
+            {
+                return gpu.VRAMMap_LCDC & (1 << bank);
+            }
+        }
+        return 0;
+    }
+
+    // the domains addr reaches on the CPU's bus as things are mapped now, as a
+    // mask of indices from MemoryDomain::index, with how far into each in
+    // offsets. Only VRAM can reach more than one
+    u32 Memory_Locate(const NDS &nds, bool arm7, u32 addr, u32 *offsets)
+    {
+        NDS &console = const_cast<NDS &>(nds);
+        const GPU &gpu = console.GPU;
+
+        auto found = [offsets](u32 domain, u32 offset)
+        {
+            offsets[domain] = offset;
+            return 1u << domain;
+        };
+
+        if (!arm7)
+        {
+            if (u8 *tcm = ARM9_TCM(console, addr))
+            {
+                ARMv5 &cpu = console.ARM9;
+                if (tcm >= cpu.ITCM && tcm < cpu.ITCM + sizeof(cpu.ITCM))
+                {
+                    return found(3, tcm - cpu.ITCM);
+                }
+                return found(4, tcm - cpu.DTCM);
+            }
+        }
+
+        switch (addr & 0xFF000000)
+        {
+        case 0x02000000:
+            return found(0, addr & console.MainRAMMask);
+        case 0x03000000:
+        {
+            // the ARM7 sees its own WRAM wherever it hasn't been given any of
+            // the shared
+            const MemRegion &swram = arm7 ? console.SWRAM_ARM7 : console.SWRAM_ARM9;
+            if (arm7 && (addr >= 0x03800000 || !swram.Mem))
+            {
+                return found(2, addr & (sizeof(console.ARM7WRAM) - 1));
+            }
+            if (!swram.Mem)
+            {
+                return 0;
+            }
+            return found(1, (swram.Mem - console.SharedWRAM) + (addr & swram.Mask));
+        }
+        case 0x05000000:
+            return arm7 ? 0 : found(14, addr & (sizeof(gpu.Palette) - 1));
+        case 0x06000000:
+        {
+            u32 banks = VRAM_Banks(gpu, arm7, addr);
+            u32 sizes[9] = {sizeof(gpu.VRAM_A), sizeof(gpu.VRAM_B), sizeof(gpu.VRAM_C),
+                            sizeof(gpu.VRAM_D), sizeof(gpu.VRAM_E), sizeof(gpu.VRAM_F),
+                            sizeof(gpu.VRAM_G), sizeof(gpu.VRAM_H), sizeof(gpu.VRAM_I)};
+            u32 domains = 0;
+            for (int bank = 0; bank < 9; bank++)
+            {
+                // each bank is mirrored across whatever it's mapped to
+                if (banks & (1 << bank))
+                {
+                    domains |= found(5 + bank, addr & (sizes[bank] - 1));
+                }
+            }
+            return domains;
+        }
+        case 0x07000000:
+            return arm7 ? 0 : found(15, addr & (sizeof(gpu.OAM) - 1));
+        default:
+            return 0;
+        }
+    }
+
+    // width is in bytes; addresses are aligned down to it, as the CPUs do
+    u32 Bus_Read(NDS &nds, bool arm7, u32 addr, u32 width)
+    {
+        addr &= ~(width - 1);
+
+        if (!arm7)
+        {
+            if (u8 *tcm = ARM9_TCM(nds, addr))
+            {
+                u32 value = 0;
+                memcpy(&value, tcm, width);
+                return value;
+            }
+        }
+
+        switch (width)
+        {
+        case 1: return arm7 ? nds.ARM7Read8(addr) : nds.ARM9Read8(addr);
+        case 2: return arm7 ? nds.ARM7Read16(addr) : nds.ARM9Read16(addr);
+        default: return arm7 ? nds.ARM7Read32(addr) : nds.ARM9Read32(addr);
+        }
+    }
+
+    void Bus_Write(NDS &nds, bool arm7, u32 addr, u32 width, u32 value)
+    {
+        addr &= ~(width - 1);
+
+        if (!arm7)
+        {
+            if (u8 *tcm = ARM9_TCM(nds, addr))
+            {
+                memcpy(tcm, &value, width);
+                return;
+            }
+        }
+
+        switch (width)
+        {
+        case 1:
+            arm7 ? nds.ARM7Write8(addr, value) : nds.ARM9Write8(addr, value);
+            break;
+        case 2:
+            arm7 ? nds.ARM7Write16(addr, value) : nds.ARM9Write16(addr, value);
+            break;
+        default:
+            arm7 ? nds.ARM7Write32(addr, value) : nds.ARM9Write32(addr, value);
+            break;
+        }
+    }
+
     void NDS_SetupDirectBoot(NDS &nds, rust::string romname)
     {
         nds.SetupDirectBoot(std::string(romname));
@@ -8,9 +8,21 @@
 
 using namespace melonDS;
 
+namespace PlatformImpl
+{
+    // defined in Rust, and handed to each console as its userdata
+    struct Instance;
+}
+
 namespace Shims
 {
-    std::unique_ptr<NDS> New_NDS();
+    std::unique_ptr<NDS> New_NDS(PlatformImpl::Instance *instance);
+    std::unique_ptr<NDS> New_NDS_WithGdb(u16 arm9_port, u16 arm7_port, bool break_on_startup, PlatformImpl::Instance *instance);
+    NDS *NDS_SetCurrent(NDS *nds);
+    void NDS_ForgetCurrent(NDS *nds);
+    void NDS_DetachGdb(NDS *nds);
+    u32 NDS_RunFrame(NDS *nds);
+    void Firmware_OffsetMac(NDS &nds, u8 offset);
 
     bool Copy_Framebuffers(NDS &nds, u8 *dest, bool index);
     s32 SPU_ReadOutput(NDS &nds, s16 *data, s32 samples);
@@ -25,6 +37,21 @@ namespace Shims
     u8 *MainRAMMut(NDS &nds);
     u32 MainRAMMaxSize(const NDS &nds);
 
+    // ARM
+
+    void ARM_ReadRegisters(const NDS &nds, bool arm7, u32 *out);
+    void ARM_WriteRegister(NDS &nds, bool arm7, u32 index, u32 value);
+    void ARM_SetInstrHooks(NDS &nds, bool arm7, rust::Slice<const u32> addrs, bool all);
+    void ARM_SetWatchPages(NDS &nds, bool arm7, rust::Slice<const u32> pages);
+
+    // Memory
+
+    u8 *Memory_Domain(const NDS &nds, u32 domain, u32 *size);
+    u32 Memory_DTCMBase(const NDS &nds);
+    u32 Memory_Locate(const NDS &nds, bool arm7, u32 addr, u32 *offsets);
+    u32 Bus_Read(NDS &nds, bool arm7, u32 addr, u32 width);
+    void Bus_Write(NDS &nds, bool arm7, u32 addr, u32 width, u32 value);
+
     void NDS_SetupDirectBoot(NDS &nds, rust::string romname);
     void NDS_SetNDSCart(NDS &nds, std::unique_ptr<NDSCart::CartCommon> cart);
 
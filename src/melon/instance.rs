@@ -0,0 +1,111 @@
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use crate::debug::Hooks;
+
+use super::nds::Nds;
+use super::wireless::Port;
+
+/// The IDs of the consoles alive in this process.
+static IN_USE: Mutex<BTreeSet<i32>> = Mutex::new(BTreeSet::new());
+
+/// What melonDS's platform callbacks know about the console calling them,
+/// reached through the `userdata` pointer each console is made with.
+///
+/// IDs are the lowest not already taken, as melonDS expects them to stay
+/// small: it derives the console's wifi MAC address from it, among other
+/// things.
+pub struct Instance {
+    id: i32,
+    wireless: Option<Port>,
+    hooks: Option<Arc<Mutex<Hooks>>>,
+    /// The console, while it's running a frame, for the hooks the core calls.
+    running: Cell<*mut Nds>,
+}
+
+impl Instance {
+    pub fn new() -> Box<Self> {
+        let mut in_use = IN_USE.lock().unwrap();
+        let id = (0..).find(|id| !in_use.contains(id)).unwrap();
+        in_use.insert(id);
+
+        Box::new(Instance {
+            id,
+            wireless: None,
+            hooks: None,
+            running: Cell::new(ptr::null_mut()),
+        })
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Appended to the files melonDS keeps for the console, so that two of
+    /// them never write to the same one. The first console keeps the suffix
+    /// every console had before there could be more than one.
+    pub fn file_suffix(&self) -> String {
+        match self.id {
+            0 => String::from(".instance"),
+            id => format!(".instance{}", id + 1),
+        }
+    }
+
+    /// Where the console's wireless packets go, if anywhere.
+    pub(crate) fn wireless(&self) -> Option<&Port> {
+        self.wireless.as_ref()
+    }
+
+    pub(crate) fn join(&mut self, port: Port) {
+        self.wireless = Some(port);
+    }
+
+    pub(crate) fn hooks(&self) -> Option<&Arc<Mutex<Hooks>>> {
+        self.hooks.as_ref()
+    }
+
+    pub(crate) fn attach(&mut self, hooks: Arc<Mutex<Hooks>>) {
+        self.hooks = Some(hooks);
+    }
+
+    pub(crate) fn set_running(&self, nds: *mut Nds) {
+        self.running.set(nds);
+    }
+
+    /// The hooks, and the console to call them with, if the core is calling
+    /// back from inside a frame. The console is only to be used until the
+    /// callback returns.
+    pub(crate) fn running(&self) -> Option<(Arc<Mutex<Hooks>>, *mut Nds)> {
+        let nds = self.running.get();
+        (!nds.is_null())
+            .then(|| self.hooks.clone())
+            .flatten()
+            .map(|hooks| (hooks, nds))
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        IN_USE.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_reused_once_their_console_is_gone() {
+        let first = Instance::new();
+        let second = Instance::new();
+        let third = Instance::new();
+        assert!(first.id() < second.id() && second.id() < third.id());
+        assert_ne!(first.file_suffix(), second.file_suffix());
+
+        let freed = second.id();
+        drop(second);
+        assert_eq!(Instance::new().id(), freed);
+    }
+}
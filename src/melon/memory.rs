@@ -0,0 +1,241 @@
+use std::fmt;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::debug::Cpu;
+
+/// A block of the console's memory that can be viewed on its own, apart from
+/// where either CPU sees it on the bus.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum MemoryDomain {
+    MainRam,
+    /// The 32 KB either CPU can be given, depending on WRAMCNT.
+    SharedWram,
+    Arm7Wram,
+    Itcm,
+    Dtcm,
+    Vram(VramBank),
+    /// Both engines' standard palettes.
+    Palette,
+    /// Both engines' sprite attributes.
+    Oam,
+    /// The cart's save chip. Read-only here, since melonDS tracks writes to it
+    /// itself.
+    CartSave,
+    /// Read-only, for the same reason.
+    Firmware,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum VramBank {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+}
+
+impl VramBank {
+    fn lcdc_address(self) -> u32 {
+        match self {
+            VramBank::A => 0x0680_0000,
+            VramBank::B => 0x0682_0000,
+            VramBank::C => 0x0684_0000,
+            VramBank::D => 0x0686_0000,
+            VramBank::E => 0x0688_0000,
+            VramBank::F => 0x0689_0000,
+            VramBank::G => 0x0689_4000,
+            VramBank::H => 0x0689_8000,
+            VramBank::I => 0x068A_0000,
+        }
+    }
+}
+
+/// How multi-byte values are laid out in a domain.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl MemoryDomain {
+    pub const ALL: [MemoryDomain; 18] = [
+        MemoryDomain::MainRam,
+        MemoryDomain::SharedWram,
+        MemoryDomain::Arm7Wram,
+        MemoryDomain::Itcm,
+        MemoryDomain::Dtcm,
+        MemoryDomain::Vram(VramBank::A),
+        MemoryDomain::Vram(VramBank::B),
+        MemoryDomain::Vram(VramBank::C),
+        MemoryDomain::Vram(VramBank::D),
+        MemoryDomain::Vram(VramBank::E),
+        MemoryDomain::Vram(VramBank::F),
+        MemoryDomain::Vram(VramBank::G),
+        MemoryDomain::Vram(VramBank::H),
+        MemoryDomain::Vram(VramBank::I),
+        MemoryDomain::Palette,
+        MemoryDomain::Oam,
+        MemoryDomain::CartSave,
+        MemoryDomain::Firmware,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            MemoryDomain::MainRam => String::from("Main RAM"),
+            MemoryDomain::SharedWram => String::from("Shared WRAM"),
+            MemoryDomain::Arm7Wram => String::from("ARM7 WRAM"),
+            MemoryDomain::Itcm => String::from("ITCM"),
+            MemoryDomain::Dtcm => String::from("DTCM"),
+            MemoryDomain::Vram(bank) => format!("VRAM {bank:?}"),
+            MemoryDomain::Palette => String::from("Palette"),
+            MemoryDomain::Oam => String::from("OAM"),
+            MemoryDomain::CartSave => String::from("Cart save"),
+            MemoryDomain::Firmware => String::from("Firmware"),
+        }
+    }
+
+    /// Everything on the console is little-endian.
+    pub fn endianness(self) -> Endianness {
+        Endianness::Little
+    }
+
+    pub fn is_writable(self) -> bool {
+        !matches!(self, MemoryDomain::CartSave | MemoryDomain::Firmware)
+    }
+
+    /// Where the domain starts on `cpu`'s bus, for the domains that are always
+    /// in the same place. VRAM banks are given where the ARM9 sees them when
+    /// they are mapped for the CPU to use directly (LCDC).
+    pub fn fixed_bus_address(self, cpu: Cpu) -> Option<u32> {
+        match (self, cpu) {
+            (MemoryDomain::MainRam, _) => Some(0x0200_0000),
+            (MemoryDomain::SharedWram, _) => Some(0x0300_0000),
+            (MemoryDomain::Arm7Wram, Cpu::Arm7) => Some(0x0380_0000),
+            (MemoryDomain::Itcm, Cpu::Arm9) => Some(0x0000_0000),
+            (MemoryDomain::Vram(bank), Cpu::Arm9) => Some(bank.lcdc_address()),
+            (MemoryDomain::Palette, Cpu::Arm9) => Some(0x0500_0000),
+            (MemoryDomain::Oam, Cpu::Arm9) => Some(0x0700_0000),
+            _ => None,
+        }
+    }
+
+    /// Everywhere on `cpu`'s bus the domain can turn up, through any mirror
+    /// or mapping, for the domains that stay inside one region. The DTCM goes
+    /// wherever the game puts it, and cart save and firmware aren't on a bus.
+    pub fn bus_region(self, cpu: Cpu) -> Option<Range<u32>> {
+        match (self, cpu) {
+            (MemoryDomain::MainRam, _) => Some(0x0200_0000..0x0300_0000),
+            (MemoryDomain::SharedWram, Cpu::Arm9) => Some(0x0300_0000..0x0400_0000),
+            (MemoryDomain::SharedWram, Cpu::Arm7) => Some(0x0300_0000..0x0380_0000),
+            (MemoryDomain::Arm7Wram, Cpu::Arm7) => Some(0x0300_0000..0x0400_0000),
+            (MemoryDomain::Itcm, Cpu::Arm9) => Some(0x0000_0000..0x0200_0000),
+            (MemoryDomain::Vram(_), Cpu::Arm9) => Some(0x0600_0000..0x0700_0000),
+            // The ARM7 can only be given banks C and D.
+            (MemoryDomain::Vram(VramBank::C | VramBank::D), Cpu::Arm7) => {
+                Some(0x0600_0000..0x0700_0000)
+            }
+            (MemoryDomain::Palette, Cpu::Arm9) => Some(0x0500_0000..0x0600_0000),
+            (MemoryDomain::Oam, Cpu::Arm9) => Some(0x0700_0000..0x0800_0000),
+            _ => None,
+        }
+    }
+
+    /// Whether the domain repeats across its [`MemoryDomain::bus_region`]
+    /// every domain's length, rather than moving around with how it's mapped.
+    pub fn mirrors_evenly(self) -> bool {
+        matches!(
+            self,
+            MemoryDomain::MainRam | MemoryDomain::Arm7Wram | MemoryDomain::Itcm
+        )
+    }
+
+    /// Whether the domain is behind a serial port rather than on a bus, so
+    /// that the CPU reaches it a byte at a time through I/O registers.
+    pub fn is_serial(self) -> bool {
+        matches!(self, MemoryDomain::CartSave | MemoryDomain::Firmware)
+    }
+
+    /// Whether `cpu` can read or write the domain at all.
+    pub fn is_reachable_by(self, cpu: Cpu) -> bool {
+        match (self, cpu) {
+            (MemoryDomain::Dtcm, Cpu::Arm9) => true,
+            (MemoryDomain::CartSave, _) => true,
+            (MemoryDomain::Firmware, Cpu::Arm7) => true,
+            _ => self.bus_region(cpu).is_some(),
+        }
+    }
+
+    /// The index the shims take.
+    pub(crate) fn index(self) -> u32 {
+        match self {
+            MemoryDomain::MainRam => 0,
+            MemoryDomain::SharedWram => 1,
+            MemoryDomain::Arm7Wram => 2,
+            MemoryDomain::Itcm => 3,
+            MemoryDomain::Dtcm => 4,
+            MemoryDomain::Vram(bank) => 5 + bank as u32,
+            MemoryDomain::Palette => 14,
+            MemoryDomain::Oam => 15,
+            MemoryDomain::CartSave => 16,
+            MemoryDomain::Firmware => 17,
+        }
+    }
+}
+
+impl fmt::Display for MemoryDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What a generic tool needs to know to list a domain.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DomainInfo {
+    pub domain: MemoryDomain,
+    pub name: String,
+    pub size: usize,
+    pub endianness: Endianness,
+    pub writable: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_domain_has_its_own_index() {
+        let mut indices: Vec<u32> = MemoryDomain::ALL
+            .iter()
+            .map(|domain| domain.index())
+            .collect();
+        indices.sort();
+        indices.dedup();
+
+        assert_eq!(indices, (0..18).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn fixed_addresses_are_inside_their_regions() {
+        for domain in MemoryDomain::ALL {
+            for cpu in Cpu::ALL {
+                if let Some(addr) = domain.fixed_bus_address(cpu) {
+                    let region = domain.bus_region(cpu).unwrap();
+                    assert!(region.contains(&addr), "{domain} on {cpu}");
+                }
+            }
+        }
+        assert!(MemoryDomain::Vram(VramBank::C).is_reachable_by(Cpu::Arm7));
+        assert!(!MemoryDomain::Vram(VramBank::A).is_reachable_by(Cpu::Arm7));
+    }
+
+    #[test]
+    fn vram_banks_are_named_by_letter() {
+        assert_eq!(MemoryDomain::Vram(VramBank::H).name(), "VRAM H");
+    }
+}
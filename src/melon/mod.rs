@@ -1,2 +1,8 @@
+mod instance;
+pub mod memory;
 pub mod nds;
 pub mod sys;
+pub mod wireless;
+
+/// The version of melonDS this build is linked against.
+pub const CORE_VERSION: &str = env!("MELONDS_VERSION");
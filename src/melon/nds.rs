@@ -1,11 +1,25 @@
+use std::ptr::{self, NonNull};
+use std::sync::{Arc, Mutex};
+
 use chrono::{DateTime, Datelike, Timelike, Utc};
 use cxx::UniquePtr;
 
+use crate::debug::{self, Cpu, GdbSettings, Hooks, Instruction, Register, Registers};
 use crate::input::ButtonMask;
 
+use super::instance::Instance;
+use super::memory::{DomainInfo, MemoryDomain};
 use super::sys;
+use super::wireless::Wireless;
 
-pub struct Nds(UniquePtr<sys::NDS>);
+/// One console. Any number can run side by side, each on its own thread if
+/// need be, since nothing in one is shared with another.
+///
+/// The instance is what melonDS's callbacks for the console are routed
+/// through, so it has to outlive the console, and is freed after it. It's kept
+/// as a raw pointer, since melonDS holds one to it for as long as the console
+/// lives.
+pub struct Nds(UniquePtr<sys::NDS>, NonNull<Instance>);
 
 impl Nds {
     pub const AUDIO_CHANNELS: u16 = 2;
@@ -13,7 +27,30 @@ impl Nds {
     pub const NATIVE_FRAME_RATE: f64 = 59.826_098_288_080_8;
 
     pub fn new() -> Self {
-        let mut nds = Nds(sys::New_NDS());
+        let instance = NonNull::from(Box::leak(Instance::new()));
+        let nds = unsafe { sys::New_NDS(instance.as_ptr()) };
+        Self::init(nds, instance)
+    }
+
+    /// A console whose CPUs are served to gdb on the ports in `settings`.
+    ///
+    /// Without the `gdbstub` feature the core has no stub, and this is the
+    /// same as [`Nds::new`].
+    pub fn with_gdb(settings: &GdbSettings) -> Self {
+        let instance = NonNull::from(Box::leak(Instance::new()));
+        let nds = unsafe {
+            sys::New_NDS_WithGdb(
+                settings.arm9_port,
+                settings.arm7_port,
+                settings.break_on_startup,
+                instance.as_ptr(),
+            )
+        };
+        Self::init(nds, instance)
+    }
+
+    fn init(nds: UniquePtr<sys::NDS>, instance: NonNull<Instance>) -> Self {
+        let mut nds = Nds(nds, instance);
         nds.reset();
         nds.set_audio_output_skew(60.0 / Self::NATIVE_FRAME_RATE);
         nds
@@ -24,6 +61,39 @@ impl Nds {
     //     sys::nds::SetConsoleType(val);
     // }
 
+    /// Tells this console apart from the others running in the process, as
+    /// the lowest number none of them has.
+    pub fn instance_id(&self) -> i32 {
+        self.instance().id()
+    }
+
+    fn instance(&self) -> &Instance {
+        // Only freed on drop, and only ever written through `&mut self`.
+        unsafe { self.1.as_ref() }
+    }
+
+    /// Points `NDS::Current` at this console until the guard is dropped, for
+    /// the callbacks made during a call into it that have no userdata.
+    fn enter(&mut self) -> Current {
+        Current::enter(self.0.as_mut_ptr())
+    }
+
+    /// Puts the console in range of the others on `wireless`, for local
+    /// multiplayer. Best done before the console boots, as it resets it.
+    pub fn join_wireless(&mut self, wireless: &Wireless) {
+        let Some(port) = wireless.join() else {
+            println!("WARNING: the wireless network is full, so the console can't join it");
+            return;
+        };
+
+        // Consoles tell each other apart by MAC address, and every one made
+        // here starts with the same.
+        let _current = self.enter();
+        sys::Firmware_OffsetMac(self.0.pin_mut(), port.slot() as u8);
+        unsafe { self.1.as_mut() }.join(port);
+        self.reset();
+    }
+
     pub fn cart_inserted(&self) -> bool {
         self.0.CartInserted()
     }
@@ -58,6 +128,7 @@ impl Nds {
                     .unwrap_or_else(std::ptr::null::<u8>),
                 save.map(|data| data.len() as u32).unwrap_or_default(),
             );
+            let _current = self.enter();
             sys::NDS_SetNDSCart(self.0.pin_mut(), cart);
         }
     }
@@ -79,12 +150,28 @@ impl Nds {
     }
 
     pub fn setup_direct_boot(&mut self, rom_name: String) {
+        let _current = self.enter();
         unsafe {
             sys::NDS_SetupDirectBoot(self.0.pin_mut(), rom_name);
         }
     }
 
+    /// Inserts the cart, sets the clock, and starts the console.
+    pub fn boot(&mut self, cart: &[u8], save: Option<&[u8]>, time: DateTime<Utc>) {
+        self.set_nds_cart(cart, save);
+        self.set_time(time);
+
+        println!("Needs direct boot? {:?}", self.needs_direct_boot());
+
+        if self.needs_direct_boot() {
+            self.setup_direct_boot(String::from("TEMP"));
+        }
+
+        self.start();
+    }
+
     pub fn start(&mut self) {
+        let _current = self.enter();
         self.0.pin_mut().Start();
     }
 
@@ -93,12 +180,43 @@ impl Nds {
     // }
 
     pub fn reset(&mut self) {
+        let _current = self.enter();
         self.0.pin_mut().Reset();
     }
 
     /// Emulates a frame. Returns number of scanlines from GPU module
     pub fn run_frame(&mut self) -> u32 {
-        self.0.pin_mut().RunFrame()
+        if let Some(hooks) = self.instance().hooks().cloned() {
+            hooks.lock().unwrap().apply(self);
+        }
+
+        // The hooks are handed the console from inside the frame, so it's
+        // only reached through this pointer until the frame is done.
+        let this: *mut Nds = self;
+        let _current = self.enter();
+        self.instance().set_running(this);
+        let lines = unsafe { sys::NDS_RunFrame(self.0.as_mut_ptr()) };
+        self.instance().set_running(ptr::null_mut());
+        lines
+    }
+
+    /// Has the core call `hooks` from inside each frame, in place of any it
+    /// called before.
+    pub fn attach_hooks(&mut self, hooks: Arc<Mutex<Hooks>>) {
+        hooks.lock().unwrap().set_stub(GdbStub(self.0.as_mut_ptr()));
+        unsafe { self.1.as_mut() }.attach(hooks);
+    }
+
+    /// Has the core call back before `cpu` runs the instructions at `addrs`,
+    /// or before every instruction if `all` is set.
+    pub(crate) fn set_instr_hooks(&mut self, cpu: Cpu, addrs: &[u32], all: bool) {
+        sys::ARM_SetInstrHooks(self.0.pin_mut(), cpu == Cpu::Arm7, addrs, all);
+    }
+
+    /// Has the core call back before each of `cpu`'s data accesses to the 16
+    /// KB pages in `pages`, numbered by address >> 14.
+    pub(crate) fn set_watch_pages(&mut self, cpu: Cpu, pages: &[u32]) {
+        sys::ARM_SetWatchPages(self.0.pin_mut(), cpu == Cpu::Arm7, pages);
     }
 
     pub fn update_framebuffers(&mut self, dest: &mut [u8], bottom: bool) -> bool {
@@ -130,6 +248,22 @@ impl Nds {
             "content bytes: {:X} {:X} {:X} {:X}",
             contents[0], contents[1], contents[2], contents[3]
         );
+        let _current = self.enter();
+        unsafe {
+            sys::ReadSavestate(
+                self.0.pin_mut(),
+                contents.as_mut_ptr(),
+                contents.len() as i32,
+            )
+        }
+    }
+
+    /// Restores a state produced by [`Nds::savestate`].
+    pub fn load_savestate(&mut self, state: &[u8]) -> bool {
+        // melonDS reads and writes savestates through the same signature, so it
+        // wants a buffer it is allowed to write to.
+        let mut contents = state.to_vec();
+        let _current = self.enter();
         unsafe {
             sys::ReadSavestate(
                 self.0.pin_mut(),
@@ -141,12 +275,30 @@ impl Nds {
 
     /// The console's state, for the caller to store however it likes.
     pub fn savestate(&mut self) -> Vec<u8> {
+        let _current = self.enter();
         let state = unsafe { sys::WriteSavestate(self.0.pin_mut()) };
         assert!(!state.is_empty(), "melonDS produced an empty savestate");
 
         state.as_slice().to_vec()
     }
 
+    pub fn registers(&self, cpu: Cpu) -> Registers {
+        let mut raw = [0; 19];
+        unsafe {
+            sys::ARM_ReadRegisters(
+                self.0.as_ref().expect("Couldn't get ref to pin"),
+                cpu == Cpu::Arm7,
+                raw.as_mut_ptr(),
+            );
+        }
+        Registers::from_raw(raw)
+    }
+
+    /// Writing the CPSR switches banks if it changes the mode, as on hardware.
+    pub fn set_register(&mut self, cpu: Cpu, register: Register, value: u32) {
+        sys::ARM_WriteRegister(self.0.pin_mut(), cpu == Cpu::Arm7, register.index(), value);
+    }
+
     pub fn current_frame(&self) -> u32 {
         unsafe { sys::CurrentFrame(self.0.as_ref().expect("Couldn't get ref to pin")) }
     }
@@ -168,6 +320,150 @@ impl Nds {
         }
     }
 
+    /// Every domain, with its name, size, and byte order, for tools that list
+    /// them.
+    pub fn memory_domains(&self) -> Vec<DomainInfo> {
+        MemoryDomain::ALL
+            .into_iter()
+            .map(|domain| DomainInfo {
+                domain,
+                name: domain.name(),
+                size: self.memory(domain).len(),
+                endianness: domain.endianness(),
+                writable: domain.is_writable(),
+            })
+            .collect()
+    }
+
+    pub fn memory(&self, domain: MemoryDomain) -> &[u8] {
+        let mut size = 0;
+        unsafe {
+            let data = sys::Memory_Domain(
+                self.0.as_ref().expect("Couldn't get ref to pin"),
+                domain.index(),
+                &mut size,
+            );
+            if data.is_null() {
+                return &[];
+            }
+            std::slice::from_raw_parts(data, size as usize)
+        }
+    }
+
+    /// Nothing for the domains melonDS doesn't expect written behind its back.
+    pub fn memory_mut(&mut self, domain: MemoryDomain) -> Option<&mut [u8]> {
+        if !domain.is_writable() {
+            return None;
+        }
+
+        let mut size = 0;
+        unsafe {
+            let data = sys::Memory_Domain(
+                self.0.as_ref().expect("Couldn't get ref to pin"),
+                domain.index(),
+                &mut size,
+            );
+            if data.is_null() {
+                return Some(&mut []);
+            }
+            Some(std::slice::from_raw_parts_mut(data, size as usize))
+        }
+    }
+
+    /// Where the domain starts on `cpu`'s bus, if the CPU can see it there.
+    pub fn bus_address(&self, cpu: Cpu, domain: MemoryDomain) -> Option<u32> {
+        match (domain, cpu) {
+            // The game moves the DTCM wherever it likes.
+            (MemoryDomain::Dtcm, Cpu::Arm9) => Some(unsafe {
+                sys::Memory_DTCMBase(self.0.as_ref().expect("Couldn't get ref to pin"))
+            }),
+            _ => domain.fixed_bus_address(cpu),
+        }
+    }
+
+    /// The domain `addr` falls in on `cpu`'s bus, and how far into it.
+    /// Mirrors aren't followed.
+    pub fn locate(&self, cpu: Cpu, addr: u32) -> Option<(MemoryDomain, usize)> {
+        MemoryDomain::ALL.into_iter().find_map(|domain| {
+            let offset = addr.checked_sub(self.bus_address(cpu, domain)?)? as usize;
+            (offset < self.memory(domain).len()).then_some((domain, offset))
+        })
+    }
+
+    /// Every domain `addr` reaches on `cpu`'s bus as things are mapped at the
+    /// moment, through any mirror, and how far into each. Only VRAM banks
+    /// mapped to the same place give more than one.
+    pub fn domains_at(&self, cpu: Cpu, addr: u32) -> Vec<(MemoryDomain, usize)> {
+        let mut offsets = [0; MemoryDomain::ALL.len()];
+        let found = unsafe {
+            sys::Memory_Locate(
+                self.0.as_ref().expect("Couldn't get ref to pin"),
+                cpu == Cpu::Arm7,
+                addr,
+                offsets.as_mut_ptr(),
+            )
+        };
+
+        MemoryDomain::ALL
+            .into_iter()
+            .filter(|domain| found & (1 << domain.index()) != 0)
+            .map(|domain| (domain, offsets[domain.index() as usize] as usize))
+            .collect()
+    }
+
+    // Bus access, through either CPU's address map. Reading an I/O register
+    // has whatever side effect it has on hardware, which is why reads take
+    // `&mut self`.
+
+    pub fn read8(&mut self, cpu: Cpu, addr: u32) -> u8 {
+        self.bus_read(cpu, addr, 1) as u8
+    }
+
+    pub fn read16(&mut self, cpu: Cpu, addr: u32) -> u16 {
+        self.bus_read(cpu, addr, 2) as u16
+    }
+
+    pub fn read32(&mut self, cpu: Cpu, addr: u32) -> u32 {
+        self.bus_read(cpu, addr, 4)
+    }
+
+    pub fn write8(&mut self, cpu: Cpu, addr: u32, value: u8) {
+        self.bus_write(cpu, addr, 1, value as u32);
+    }
+
+    pub fn write16(&mut self, cpu: Cpu, addr: u32, value: u16) {
+        self.bus_write(cpu, addr, 2, value as u32);
+    }
+
+    pub fn write32(&mut self, cpu: Cpu, addr: u32, value: u32) {
+        self.bus_write(cpu, addr, 4, value);
+    }
+
+    fn bus_read(&mut self, cpu: Cpu, addr: u32, width: u32) -> u32 {
+        let _current = self.enter();
+        sys::Bus_Read(self.0.pin_mut(), cpu == Cpu::Arm7, addr, width)
+    }
+
+    fn bus_write(&mut self, cpu: Cpu, addr: u32, width: u32, value: u32) {
+        let _current = self.enter();
+        sys::Bus_Write(self.0.pin_mut(), cpu == Cpu::Arm7, addr, width, value);
+    }
+
+    /// Disassembles `count` instructions from `addr`, reading them the way
+    /// `cpu` sees memory.
+    pub fn disassemble(
+        &mut self,
+        cpu: Cpu,
+        addr: u32,
+        count: usize,
+        thumb: bool,
+    ) -> Vec<Instruction> {
+        debug::disassemble(addr, count, thumb, |addr, width| match width {
+            2 => self.read16(cpu, addr) as u32,
+            _ => self.read32(cpu, addr),
+        })
+    }
+
     pub fn save_data(&self) -> &[u8] {
         unsafe {
             let len = self.0.GetNDSSaveLength();
@@ -177,4 +473,56 @@ impl Nds {
     }
 }
 
+impl Drop for Nds {
+    fn drop(&mut self) {
+        let core = self.0.as_mut_ptr();
+        if let Some(hooks) = self.instance().hooks() {
+            hooks.lock().unwrap().forget_stub();
+        }
+        {
+            // melonDS may still call back while it tears the console down.
+            let _current = Current::enter(core);
+            self.0 = UniquePtr::null();
+        }
+        unsafe {
+            sys::NDS_ForgetCurrent(core);
+            drop(Box::from_raw(self.1.as_ptr()));
+        }
+    }
+}
+
 unsafe impl Send for Nds {}
+
+/// A console's GDB stub, which can be told to let go from any thread, for as
+/// long as the console lives.
+pub(crate) struct GdbStub(*mut sys::NDS);
+
+impl GdbStub {
+    /// Drops the debugger, letting go of a CPU it holds, and takes no other.
+    /// Does nothing without the `gdbstub` feature, or for a console made
+    /// without a stub.
+    pub fn detach(&self) {
+        unsafe { sys::NDS_DetachGdb(self.0) }
+    }
+}
+
+// Detaching only touches the stub's sockets, which is safe from any thread.
+unsafe impl Send for GdbStub {}
+
+/// Keeps `NDS::Current` pointed at one console, and puts back whatever it
+/// pointed at before when dropped.
+struct Current(*mut sys::NDS);
+
+impl Current {
+    fn enter(nds: *mut sys::NDS) -> Self {
+        Current(unsafe { sys::NDS_SetCurrent(nds) })
+    }
+}
+
+impl Drop for Current {
+    fn drop(&mut self) {
+        unsafe {
+            sys::NDS_SetCurrent(self.0);
+        }
+    }
+}
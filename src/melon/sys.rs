@@ -5,13 +5,17 @@
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use std::ptr::drop_in_place;
+use std::ptr::{self, drop_in_place};
 use std::slice;
 use std::sync::{Mutex, MutexGuard, TryLockError};
 use std::thread::{spawn, JoinHandle};
 
+use crate::debug::{self, Access, Cpu};
 use crate::utils::localize_pathbuf;
 
+use super::instance::Instance;
+use super::wireless::{HostGone, Kind, Packet, REPLY_BUFFER_LEN};
+
 #[cxx::bridge]
 mod sys {
     #[namespace = "Util"]
@@ -74,11 +78,29 @@ mod sys {
         #[namespace = "melonDS::Platform"]
         type NdsFileHandle;
 
+        // the console a callback is made for, from its userdata
+        type Instance;
+
         // Instance
         #[cxx_name = "InstanceID"]
-        fn instance_id() -> i32;
+        fn instance_id(instance: &Instance) -> i32;
         #[cxx_name = "InstanceFileSuffix"]
-        fn instance_file_suffix() -> String;
+        fn instance_file_suffix(instance: &Instance) -> String;
+
+        // Hooks, which the core is patched to call from inside a frame
+        #[cxx_name = "InstrHook"]
+        fn instr_hook(instance: &Instance, arm7: bool, addr: u32);
+        #[cxx_name = "MemHook"]
+        fn mem_hook(
+            instance: &Instance,
+            arm7: bool,
+            addr: u32,
+            width: u32,
+            write: bool,
+            value: u32,
+        );
+        #[cxx_name = "StubHook"]
+        fn stub_hook(instance: &Instance, arm7: bool, stopped: bool);
 
         // Camera
         #[cxx_name = "Camera_Start"]
@@ -138,33 +160,53 @@ mod sys {
 
         // multiplayer
         #[cxx_name = "MP_Init"]
-        fn mp_init() -> bool;
+        fn mp_init(instance: &Instance) -> bool;
         #[cxx_name = "MP_DeInit"]
-        fn mp_deinit();
+        fn mp_deinit(instance: &Instance);
         #[cxx_name = "MP_Begin"]
-        fn mp_begin();
+        fn mp_begin(instance: &Instance);
         #[cxx_name = "MP_End"]
-        fn mp_end();
+        fn mp_end(instance: &Instance);
         #[cxx_name = "MP_SendAck"]
-        unsafe fn mp_send_ack(data: *mut u8, len: i32, timestamp: u64) -> i32;
+        unsafe fn mp_send_ack(data: *mut u8, len: i32, timestamp: u64, instance: &Instance) -> i32;
         #[cxx_name = "MP_SendCmd"]
-        unsafe fn mp_send_cmd(data: *mut u8, len: i32, timestamp: u64) -> i32;
+        unsafe fn mp_send_cmd(data: *mut u8, len: i32, timestamp: u64, instance: &Instance) -> i32;
         #[cxx_name = "MP_SendReply"]
-        unsafe fn mp_send_reply(data: *mut u8, len: i32, timestamp: u64, aid: u16) -> i32;
+        unsafe fn mp_send_reply(
+            data: *mut u8,
+            len: i32,
+            timestamp: u64,
+            aid: u16,
+            instance: &Instance,
+        ) -> i32;
         #[cxx_name = "MP_SendPacket"]
-        unsafe fn mp_send_packet(data: *mut u8, len: i32, timestamp: u64) -> i32;
+        unsafe fn mp_send_packet(
+            data: *mut u8,
+            len: i32,
+            timestamp: u64,
+            instance: &Instance,
+        ) -> i32;
         #[cxx_name = "MP_RecvPacket"]
-        unsafe fn mp_recv_packet(data: *mut u8, timestamp: *mut u64) -> i32;
+        unsafe fn mp_recv_packet(data: *mut u8, timestamp: *mut u64, instance: &Instance) -> i32;
         #[cxx_name = "MP_RecvHostPacket"]
-        unsafe fn mp_recv_host_packet(data: *mut u8, timestamp: *mut u64) -> i32;
+        unsafe fn mp_recv_host_packet(
+            data: *mut u8,
+            timestamp: *mut u64,
+            instance: &Instance,
+        ) -> i32;
         #[cxx_name = "MP_RecvReplies"]
-        unsafe fn mp_recv_replies(data: *mut u8, timestamp: u64, aidmask: u16) -> u16;
+        unsafe fn mp_recv_replies(
+            data: *mut u8,
+            timestamp: u64,
+            aidmask: u16,
+            instance: &Instance,
+        ) -> u16;
 
         // net
         #[cxx_name = "Net_SendPacket"]
-        unsafe fn net_send_packet(data: *mut u8, len: i32) -> i32;
+        unsafe fn net_send_packet(data: *mut u8, len: i32, instance: &Instance) -> i32;
         #[cxx_name = "Net_RecvPacket"]
-        unsafe fn net_recv_packet(data: *mut u8) -> i32;
+        unsafe fn net_recv_packet(data: *mut u8, instance: &Instance) -> i32;
 
         #[cxx_name = "WriteNDSSave"]
         unsafe fn write_nds_save(
@@ -172,6 +214,7 @@ mod sys {
             savelen: u32,
             writeoffset: u32,
             writelen: u32,
+            instance: &Instance,
         );
 
         // File interaction
@@ -267,8 +310,6 @@ mod sys {
         fn NeedsDirectBoot(&self) -> bool;
         // fn SetupDirectBoot(self: Pin<&mut NDS>);
 
-        fn RunFrame(self: Pin<&mut NDS>) -> u32;
-
         unsafe fn GetNDSSave(&self) -> *const u8;
         fn GetNDSSaveLength(&self) -> u32;
     }
@@ -279,7 +320,18 @@ mod sys {
     unsafe extern "C++" {
         include!("Shims.h");
 
-        pub fn New_NDS() -> UniquePtr<NDS>;
+        pub unsafe fn New_NDS(instance: *mut Instance) -> UniquePtr<NDS>;
+        pub unsafe fn New_NDS_WithGdb(
+            arm9_port: u16,
+            arm7_port: u16,
+            break_on_startup: bool,
+            instance: *mut Instance,
+        ) -> UniquePtr<NDS>;
+        pub unsafe fn NDS_SetCurrent(nds: *mut NDS) -> *mut NDS;
+        pub unsafe fn NDS_ForgetCurrent(nds: *mut NDS);
+        pub unsafe fn NDS_DetachGdb(nds: *mut NDS);
+        pub unsafe fn NDS_RunFrame(nds: *mut NDS) -> u32;
+        pub fn Firmware_OffsetMac(nds: Pin<&mut NDS>, offset: u8);
 
         pub unsafe fn Copy_Framebuffers(nds: Pin<&mut NDS>, dest: *mut u8, index: bool) -> bool;
         pub unsafe fn SPU_ReadOutput(nds: Pin<&mut NDS>, data: *mut i16, samples: i32) -> i32;
@@ -294,6 +346,17 @@ mod sys {
         pub unsafe fn MainRAMMut(nds: Pin<&mut NDS>) -> *mut u8;
         pub unsafe fn MainRAMMaxSize(nds: &NDS) -> u32;
 
+        pub unsafe fn ARM_ReadRegisters(nds: &NDS, arm7: bool, out: *mut u32);
+        pub fn ARM_WriteRegister(nds: Pin<&mut NDS>, arm7: bool, index: u32, value: u32);
+        pub fn ARM_SetInstrHooks(nds: Pin<&mut NDS>, arm7: bool, addrs: &[u32], all: bool);
+        pub fn ARM_SetWatchPages(nds: Pin<&mut NDS>, arm7: bool, pages: &[u32]);
+
+        pub unsafe fn Memory_Domain(nds: &NDS, domain: u32, size: *mut u32) -> *mut u8;
+        pub unsafe fn Memory_DTCMBase(nds: &NDS) -> u32;
+        pub unsafe fn Memory_Locate(nds: &NDS, arm7: bool, addr: u32, offsets: *mut u32) -> u32;
+        pub fn Bus_Read(nds: Pin<&mut NDS>, arm7: bool, addr: u32, width: u32) -> u32;
+        pub fn Bus_Write(nds: Pin<&mut NDS>, arm7: bool, addr: u32, width: u32, value: u32);
+
         pub unsafe fn NDS_SetupDirectBoot(nds: Pin<&mut NDS>, romname: String);
         pub unsafe fn NDS_SetNDSCart(nds: Pin<&mut NDS>, cart: UniquePtr<CartCommon>);
 
@@ -321,12 +384,47 @@ mod sys {
     impl UniquePtr<NDS> {}
 }
 
-fn instance_id() -> i32 {
-    0
+fn instance_id(instance: &Instance) -> i32 {
+    instance.id()
 }
 
-fn instance_file_suffix() -> String {
-    String::from(".instance")
+fn instance_file_suffix(instance: &Instance) -> String {
+    instance.file_suffix()
+}
+
+fn hooked_cpu(arm7: bool) -> Cpu {
+    if arm7 {
+        Cpu::Arm7
+    } else {
+        Cpu::Arm9
+    }
+}
+
+fn instr_hook(instance: &Instance, arm7: bool, addr: u32) {
+    if let Some((hooks, nds)) = instance.running() {
+        debug::on_instruction(&hooks, unsafe { &mut *nds }, hooked_cpu(arm7), addr);
+    }
+}
+
+fn mem_hook(instance: &Instance, arm7: bool, addr: u32, width: u32, write: bool, value: u32) {
+    if let Some((hooks, nds)) = instance.running() {
+        let access = Access {
+            addr,
+            width,
+            write,
+            value,
+        };
+        debug::on_access(&hooks, unsafe { &mut *nds }, hooked_cpu(arm7), access);
+    }
+}
+
+fn stub_hook(instance: &Instance, arm7: bool, stopped: bool) {
+    if let Some(hooks) = instance.hooks() {
+        hooks
+            .lock()
+            .unwrap()
+            .debugger_stopped(hooked_cpu(arm7), stopped);
+    }
 }
 
 fn camera_start(num: i32) {}
@@ -636,39 +734,88 @@ unsafe fn lan_recv_packet(data: *mut u8) -> i32 {
 
 fn lan_deinit() {}
 
-unsafe fn mp_send_ack(data: *mut u8, len: i32, timestamp: u64) -> i32 {
-    0
+unsafe fn mp_send_ack(data: *mut u8, len: i32, timestamp: u64, instance: &Instance) -> i32 {
+    mp_send(instance, Kind::Ack, data, len, timestamp)
 }
-unsafe fn mp_send_cmd(data: *mut u8, len: i32, timestamp: u64) -> i32 {
-    0
+unsafe fn mp_send_cmd(data: *mut u8, len: i32, timestamp: u64, instance: &Instance) -> i32 {
+    mp_send(instance, Kind::Cmd, data, len, timestamp)
 }
-unsafe fn mp_send_reply(data: *mut u8, len: i32, timestamp: u64, aid: u16) -> i32 {
-    0
+unsafe fn mp_send_reply(
+    data: *mut u8,
+    len: i32,
+    timestamp: u64,
+    aid: u16,
+    instance: &Instance,
+) -> i32 {
+    mp_send(instance, Kind::Reply(aid), data, len, timestamp)
 }
-unsafe fn mp_send_packet(data: *mut u8, len: i32, timestamp: u64) -> i32 {
-    0
+unsafe fn mp_send_packet(data: *mut u8, len: i32, timestamp: u64, instance: &Instance) -> i32 {
+    mp_send(instance, Kind::Data, data, len, timestamp)
 }
-unsafe fn mp_recv_packet(data: *mut u8, timestamp: *mut u64) -> i32 {
-    0
+unsafe fn mp_recv_packet(data: *mut u8, timestamp: *mut u64, instance: &Instance) -> i32 {
+    match instance.wireless().and_then(|port| port.recv(false)) {
+        Some(packet) => mp_deliver(packet, data, timestamp),
+        None => 0,
+    }
 }
-unsafe fn mp_recv_host_packet(data: *mut u8, timestamp: *mut u64) -> i32 {
-    0
+unsafe fn mp_recv_host_packet(data: *mut u8, timestamp: *mut u64, instance: &Instance) -> i32 {
+    match instance.wireless().map(|port| port.recv_host()) {
+        Some(Ok(Some(packet))) => mp_deliver(packet, data, timestamp),
+        Some(Err(HostGone)) => -1,
+        _ => 0,
+    }
 }
-unsafe fn mp_recv_replies(data: *mut u8, timestamp: u64, aidmask: u16) -> u16 {
-    0
+unsafe fn mp_recv_replies(data: *mut u8, timestamp: u64, aidmask: u16, instance: &Instance) -> u16 {
+    let Some(port) = instance.wireless() else {
+        return 0;
+    };
+    let buffer = match data.is_null() {
+        true => &mut [][..],
+        false => slice::from_raw_parts_mut(data, REPLY_BUFFER_LEN),
+    };
+    port.recv_replies(timestamp, aidmask, buffer)
+}
+// nothing is sent for a console that hasn't joined a network, as if it were
+// out of range of everything, nor for a negative length or a missing buffer
+unsafe fn mp_send(instance: &Instance, kind: Kind, data: *mut u8, len: i32, timestamp: u64) -> i32 {
+    let Ok(len) = usize::try_from(len) else {
+        return 0;
+    };
+    if data.is_null() {
+        return 0;
+    }
+    match instance.wireless() {
+        Some(port) => port.send(kind, slice::from_raw_parts(data, len), timestamp) as i32,
+        None => 0,
+    }
 }
-unsafe fn net_send_packet(data: *mut u8, len: i32) -> i32 {
+unsafe fn mp_deliver(packet: Packet, data: *mut u8, timestamp: *mut u64) -> i32 {
+    ptr::copy_nonoverlapping(packet.data.as_ptr(), data, packet.data.len());
+    if !timestamp.is_null() {
+        *timestamp = packet.timestamp;
+    }
+    packet.data.len() as i32
+}
+unsafe fn net_send_packet(data: *mut u8, len: i32, instance: &Instance) -> i32 {
     0
 }
-unsafe fn net_recv_packet(data: *mut u8) -> i32 {
+unsafe fn net_recv_packet(data: *mut u8, instance: &Instance) -> i32 {
     0
 }
-fn mp_init() -> bool {
+fn mp_init(instance: &Instance) -> bool {
     true
 }
-fn mp_deinit() {}
-fn mp_begin() {}
-fn mp_end() {}
+fn mp_deinit(instance: &Instance) {}
+fn mp_begin(instance: &Instance) {
+    if let Some(port) = instance.wireless() {
+        port.begin();
+    }
+}
+fn mp_end(instance: &Instance) {
+    if let Some(port) = instance.wireless() {
+        port.end();
+    }
+}
 
 // use once_cell::sync::Lazy;
 // static SAVE_BUFFER: Lazy<Mutex<Vec<u8>>> = Lazy::new(|| Mutex::new(vec![]));
@@ -694,7 +841,13 @@ fn mp_end() {}
 //     std::fs::write(path, save_contents).unwrap();
 // }
 
-unsafe fn write_nds_save(savedata: *const u8, savelen: u32, writeoffset: u32, writelen: u32) {
+unsafe fn write_nds_save(
+    savedata: *const u8,
+    savelen: u32,
+    writeoffset: u32,
+    writelen: u32,
+    instance: &Instance,
+) {
     // why do this when we can just access the save data straight from the NDS?
 
     // write_save(
@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The most consoles one local wireless network can hold.
+pub const MAX_CONSOLES: usize = 16;
+
+/// The longest frame melonDS sends. Anything longer is dropped.
+const MAX_FRAME_LEN: usize = 0x948;
+
+/// How long a console waits for a packet it can't go on without before
+/// carrying on as if it was lost, as melonDS's own frontend does.
+const RECV_TIMEOUT: Duration = Duration::from_millis(25);
+
+/// Each client's reply goes this far into the buffer the host collects them
+/// in, by its association ID.
+const REPLY_STRIDE: usize = 1024;
+
+/// How big the host's buffer for replies is: one slot per client it can have.
+pub(crate) const REPLY_BUFFER_LEN: usize = 15 * REPLY_STRIDE;
+
+/// What melonDS says a packet is for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Kind {
+    Data,
+    /// From the host, which its clients reply to.
+    Cmd,
+    /// From the client with the association ID, to the host.
+    Reply(u16),
+    /// From the host, once it has the replies.
+    Ack,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct Packet {
+    pub sender: usize,
+    pub kind: Kind,
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+/// The host this console last heard from has left.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct HostGone;
+
+#[derive(Debug, Default)]
+struct State {
+    /// The slots taken by consoles.
+    joined: u16,
+    /// The consoles taking part in a multiplayer session, between melonDS's
+    /// `MP_Begin` and `MP_End`.
+    connected: u16,
+    /// The console that last sent commands.
+    host: Option<usize>,
+    /// What each console has yet to receive, besides replies.
+    packets: [VecDeque<Packet>; MAX_CONSOLES],
+    /// The replies each console has yet to collect as host.
+    replies: [VecDeque<Packet>; MAX_CONSOLES],
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    arrived: Condvar,
+}
+
+/// Local wireless between consoles in this process, standing in for the
+/// air between nearby DSes. Consoles that join the same one can play local
+/// multiplayer games and Download Play together.
+///
+/// Sessions are timed by the host, and a console waiting on another gives up
+/// after a short timeout, so consoles on separate threads should be run at
+/// the same speed.
+#[derive(Debug, Clone, Default)]
+pub struct Wireless(Arc<Shared>);
+
+impl Wireless {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the lowest free slot, if there is one.
+    pub(crate) fn join(&self) -> Option<Port> {
+        let mut state = self.0.state.lock().unwrap();
+        let slot = (0..MAX_CONSOLES).find(|slot| state.joined & 1 << slot == 0)?;
+        state.joined |= 1 << slot;
+
+        Some(Port {
+            shared: self.0.clone(),
+            slot,
+        })
+    }
+}
+
+/// One console's place on a [`Wireless`] network. Leaving it frees the slot.
+#[derive(Debug)]
+pub(crate) struct Port {
+    shared: Arc<Shared>,
+    slot: usize,
+}
+
+impl Port {
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Joins the session, forgetting anything sent before.
+    pub fn begin(&self) {
+        let mut state = self.state();
+        state.connected |= 1 << self.slot;
+        state.packets[self.slot].clear();
+        state.replies[self.slot].clear();
+    }
+
+    pub fn end(&self) {
+        let mut state = self.state();
+        state.connected &= !(1 << self.slot);
+        state.packets[self.slot].clear();
+        state.replies[self.slot].clear();
+        self.shared.arrived.notify_all();
+    }
+
+    /// Sends to everyone else in the session, or a reply to the host alone.
+    /// Returns how much was sent.
+    pub fn send(&self, kind: Kind, data: &[u8], timestamp: u64) -> usize {
+        if data.len() > MAX_FRAME_LEN {
+            return 0;
+        }
+
+        let mut state = self.state();
+        let packet = Packet {
+            sender: self.slot,
+            kind,
+            timestamp,
+            data: data.to_vec(),
+        };
+
+        match kind {
+            Kind::Reply(_) => match state.host {
+                Some(host) => state.replies[host].push_back(packet),
+                None => return 0,
+            },
+            _ => {
+                if kind == Kind::Cmd {
+                    state.host = Some(self.slot);
+                }
+                let connected = state.connected;
+                for slot in (0..MAX_CONSOLES).filter(|&slot| slot != self.slot) {
+                    if connected & 1 << slot != 0 {
+                        state.packets[slot].push_back(packet.clone());
+                    }
+                }
+            }
+        }
+
+        self.shared.arrived.notify_all();
+        data.len()
+    }
+
+    /// The next packet sent to this console, waiting for one if `block` is
+    /// set.
+    pub fn recv(&self, block: bool) -> Option<Packet> {
+        let timeout = if block { RECV_TIMEOUT } else { Duration::ZERO };
+        let mut state = self.wait(timeout, |state| !state.packets[self.slot].is_empty());
+
+        let packet = state.packets[self.slot].pop_front()?;
+        if packet.kind == Kind::Cmd {
+            state.host = Some(packet.sender);
+        }
+        Some(packet)
+    }
+
+    /// Waits for a packet from the host, unless the host has gone. Packets
+    /// from other clients are left for [`Port::recv`]. With no host yet, the
+    /// first command makes its sender the host.
+    pub fn recv_host(&self) -> Result<Option<Packet>, HostGone> {
+        let host_gone =
+            |state: &State| matches!(state.host, Some(host) if state.connected & 1 << host == 0);
+        let mut state = self.wait(RECV_TIMEOUT, |state| {
+            host_gone(state) || self.host_packet(state).is_some()
+        });
+
+        if host_gone(&state) {
+            return Err(HostGone);
+        }
+        let Some(index) = self.host_packet(&state) else {
+            return Ok(None);
+        };
+
+        let packet = state.packets[self.slot].remove(index);
+        if let Some(Packet {
+            kind: Kind::Cmd,
+            sender,
+            ..
+        }) = packet
+        {
+            state.host = Some(sender);
+        }
+        Ok(packet)
+    }
+
+    /// Where the first packet from the host is in this console's queue.
+    fn host_packet(&self, state: &State) -> Option<usize> {
+        state.packets[self.slot]
+            .iter()
+            .position(|packet| match state.host {
+                Some(host) => packet.sender == host,
+                None => packet.kind == Kind::Cmd,
+            })
+    }
+
+    /// Collects replies to the commands sent at `timestamp` into `buffer`,
+    /// until the clients in `aids` have all replied, every console in the
+    /// session has, or the wait times out. Returns the clients that replied,
+    /// as a mask of association IDs.
+    pub fn recv_replies(&self, timestamp: u64, aids: u16, buffer: &mut [u8]) -> u16 {
+        if aids == 0 {
+            return 0;
+        }
+
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        let mut replied = 0;
+        let mut heard = 1 << self.slot;
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let mut state = self.wait(timeout, |state| !state.replies[self.slot].is_empty());
+            let Some(reply) = state.replies[self.slot].pop_front() else {
+                return replied;
+            };
+            // Left over from an earlier exchange.
+            if reply.timestamp < timestamp.saturating_sub(32) {
+                continue;
+            }
+
+            if let Kind::Reply(aid @ 1..=15) = reply.kind {
+                let start = (aid as usize - 1) * REPLY_STRIDE;
+                let len = reply.data.len().min(REPLY_STRIDE);
+                if let Some(slot) = buffer.get_mut(start..start + len) {
+                    slot.copy_from_slice(&reply.data[..len]);
+                }
+                replied |= 1 << aid;
+            }
+            heard |= 1 << reply.sender;
+
+            if heard & state.connected == state.connected || replied & aids == aids {
+                return replied;
+            }
+        }
+    }
+
+    fn wait(&self, timeout: Duration, ready: impl Fn(&State) -> bool) -> MutexGuard<'_, State> {
+        let state = self.state();
+        let (state, _) = self
+            .shared
+            .arrived
+            .wait_timeout_while(state, timeout, |state| !ready(state))
+            .unwrap();
+        state
+    }
+}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        self.end();
+        self.state().joined &= !(1 << self.slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> (Port, Port) {
+        let wireless = Wireless::new();
+        let (host, client) = (wireless.join().unwrap(), wireless.join().unwrap());
+        host.begin();
+        client.begin();
+        (host, client)
+    }
+
+    #[test]
+    fn clients_reply_to_the_host_they_heard_from() {
+        let (host, client) = session();
+
+        assert_eq!(host.send(Kind::Cmd, &[1, 2, 3], 100), 3);
+        let cmd = client.recv(false).unwrap();
+        assert_eq!((cmd.kind, cmd.data), (Kind::Cmd, vec![1, 2, 3]));
+        assert_eq!(host.recv(false), None);
+
+        client.send(Kind::Reply(2), &[9; 4], 110);
+        let mut buffer = vec![0; REPLY_BUFFER_LEN];
+        assert_eq!(host.recv_replies(100, 1 << 2, &mut buffer), 1 << 2);
+        assert_eq!(buffer[REPLY_STRIDE..REPLY_STRIDE + 5], [9, 9, 9, 9, 0]);
+
+        host.send(Kind::Ack, &[7], 120);
+        assert_eq!(client.recv_host().unwrap().unwrap().kind, Kind::Ack);
+    }
+
+    #[test]
+    fn only_consoles_in_the_session_hear_it() {
+        let wireless = Wireless::new();
+        let (first, second) = (wireless.join().unwrap(), wireless.join().unwrap());
+        first.begin();
+
+        first.send(Kind::Data, &[1], 0);
+        second.begin();
+        assert_eq!(second.recv(false), None);
+
+        first.send(Kind::Cmd, &[2], 1);
+        assert_eq!(second.recv(false).unwrap().data, [2]);
+
+        first.end();
+        assert_eq!(second.recv_host(), Err(HostGone));
+    }
+
+    #[test]
+    fn waiting_for_the_host_passes_over_other_clients_packets() {
+        let wireless = Wireless::new();
+        let ports: Vec<Port> = (0..3).map(|_| wireless.join().unwrap()).collect();
+        ports.iter().for_each(Port::begin);
+        let [host, first, second] = &ports[..] else {
+            unreachable!()
+        };
+
+        host.send(Kind::Cmd, &[1], 100);
+        assert_eq!(first.recv_host().unwrap().unwrap().data, [1]);
+        assert_eq!(second.recv_host().unwrap().unwrap().data, [1]);
+
+        second.send(Kind::Data, &[2], 105);
+        first.send(Kind::Data, &[3], 106);
+        assert_eq!(first.recv_host(), Ok(None));
+
+        host.send(Kind::Ack, &[4], 110);
+        let ack = first.recv_host().unwrap().unwrap();
+        assert_eq!(
+            (ack.sender, ack.kind, ack.data),
+            (host.slot(), Kind::Ack, vec![4])
+        );
+        let ack = second.recv_host().unwrap().unwrap();
+        assert_eq!((ack.sender, ack.data), (host.slot(), vec![4]));
+
+        // What the other client sent is still there for an ordinary receive.
+        assert_eq!(first.recv(false).unwrap().data, [2]);
+        assert_eq!(second.recv(false).unwrap().data, [3]);
+    }
+
+    #[test]
+    fn slots_free_up_when_a_console_leaves() {
+        let wireless = Wireless::new();
+        let ports: Vec<Port> = (0..MAX_CONSOLES)
+            .map(|_| wireless.join().unwrap())
+            .collect();
+        assert!(wireless.join().is_none());
+
+        drop(ports);
+        assert_eq!(wireless.join().unwrap().slot(), 0);
+    }
+}
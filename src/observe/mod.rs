@@ -1,11 +1,50 @@
+use std::fmt;
+
+use crate::debug::{Cpu, Registers};
 use crate::input::ConsoleInputState;
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::Nds;
 
 /// Console state after one emulated frame, for analysis hooks.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy)]
 pub struct FrameView<'a> {
     pub frame: u64,
     pub main_ram: &'a [u8],
     pub input: ConsoleInputState,
+    pub arm9: Registers,
+    pub arm7: Registers,
+    /// Read-only, for the memory outside main RAM.
+    pub(crate) nds: &'a Nds,
+}
+
+impl<'a> FrameView<'a> {
+    pub fn registers(&self, cpu: Cpu) -> &Registers {
+        match cpu {
+            Cpu::Arm9 => &self.arm9,
+            Cpu::Arm7 => &self.arm7,
+        }
+    }
+
+    pub fn memory(&self, domain: MemoryDomain) -> &'a [u8] {
+        self.nds.memory(domain)
+    }
+
+    /// See [`Nds::locate`].
+    pub fn locate(&self, cpu: Cpu, addr: u32) -> Option<(MemoryDomain, usize)> {
+        self.nds.locate(cpu, addr)
+    }
+}
+
+impl fmt::Debug for FrameView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameView")
+            .field("frame", &self.frame)
+            .field("main_ram", &self.main_ram.len())
+            .field("input", &self.input)
+            .field("arm9", &self.arm9)
+            .field("arm7", &self.arm7)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Inspects each finished frame without affecting emulation.
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::melon::nds::Nds;
+use crate::speed::Speed;
+
+/// What decides when the next frame runs.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Pacing {
+    /// Exactly 60 frames a second, with the audio stretched slightly to match.
+    #[default]
+    Sixty,
+    /// The console's own rate of about 59.826 frames a second, so a second of
+    /// play lasts as long as it does on hardware.
+    Native,
+    /// The console's own rate, nudged to keep the audio device's backlog
+    /// steady rather than nudging the audio to fit the frames.
+    Audio,
+}
+
+impl Pacing {
+    /// Bounds how far the audio backlog may stretch or squeeze a frame.
+    const MAX_AUDIO_CORRECTION: f64 = 0.05;
+
+    /// How long a frame takes at normal speed.
+    pub fn frame_duration(self) -> Duration {
+        match self {
+            Pacing::Sixty => Duration::from_nanos(16_666_667),
+            Pacing::Native | Pacing::Audio => Duration::from_secs_f64(1.0 / Nds::NATIVE_FRAME_RATE),
+        }
+    }
+
+    /// Frames per second as a fraction, for formats that want one exactly.
+    pub fn frame_rate(self) -> (u32, u32) {
+        match self {
+            Pacing::Sixty => (60, 1),
+            // The ARM7's clock over the cycles in a frame.
+            Pacing::Native | Pacing::Audio => (33_513_982, 560_190),
+        }
+    }
+
+    /// The audio skew under which one frame of audio plays for exactly as long
+    /// as the frame is scheduled for.
+    pub fn calibrated_skew(self) -> f64 {
+        match self {
+            Pacing::Sixty => 60.0 / Nds::NATIVE_FRAME_RATE,
+            Pacing::Native | Pacing::Audio => 1.0,
+        }
+    }
+
+    /// Whether the audio is resampled to keep the device's backlog steady. When
+    /// the audio is clocking the frames instead, the resampling holds still.
+    pub fn adjusts_skew(self) -> bool {
+        self != Pacing::Audio
+    }
+
+    /// How long to wait between frames at `speed`, or nothing if frames
+    /// should run as fast as they can.
+    ///
+    /// `backlog` is how far ahead of the device the audio is, as a fraction of
+    /// the backlog it aims for, when there is audio being played at all.
+    pub fn frame_period(self, speed: Speed, backlog: Option<f64>) -> Option<Duration> {
+        let period = speed.frame_duration(self.frame_duration())?;
+
+        match (self, speed, backlog) {
+            (Pacing::Audio, Speed::Normal, Some(backlog)) => {
+                // A deep backlog means frames are outrunning the device, so the
+                // next one waits a little longer, and a thin one the opposite.
+                let correction = ((backlog - 1.0) / 2.0)
+                    .clamp(-Self::MAX_AUDIO_CORRECTION, Self::MAX_AUDIO_CORRECTION);
+                Some(period.mul_f64(1.0 + correction))
+            }
+            _ => Some(period),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_frames_are_slightly_longer_than_a_sixtieth() {
+        let sixty = Pacing::Sixty.frame_duration();
+        let native = Pacing::Native.frame_duration();
+
+        assert!(native > sixty);
+        assert_eq!(native.as_nanos(), 16_715_113);
+    }
+
+    #[test]
+    fn the_frame_rate_fractions_match_the_frame_durations() {
+        for pacing in [Pacing::Sixty, Pacing::Native, Pacing::Audio] {
+            let (numerator, denominator) = pacing.frame_rate();
+            let duration = Duration::from_secs_f64(denominator as f64 / numerator as f64);
+
+            assert!(duration.abs_diff(pacing.frame_duration()) < Duration::from_nanos(1));
+        }
+    }
+
+    #[test]
+    fn native_pacing_needs_no_skew() {
+        assert_eq!(Pacing::Native.calibrated_skew(), 1.0);
+        assert!(Pacing::Sixty.calibrated_skew() > 1.0);
+    }
+
+    #[test]
+    fn only_audio_pacing_listens_to_the_backlog() {
+        let native = Pacing::Native.frame_duration();
+
+        assert_eq!(
+            Pacing::Native.frame_period(Speed::Normal, Some(2.0)),
+            Some(native)
+        );
+        assert!(
+            Pacing::Audio
+                .frame_period(Speed::Normal, Some(1.5))
+                .unwrap()
+                > native
+        );
+        assert!(
+            Pacing::Audio
+                .frame_period(Speed::Normal, Some(0.5))
+                .unwrap()
+                < native
+        );
+        assert_eq!(
+            Pacing::Audio.frame_period(Speed::Normal, Some(1.0)),
+            Some(native)
+        );
+    }
+
+    #[test]
+    fn audio_pacing_without_audio_keeps_the_native_rate() {
+        assert_eq!(
+            Pacing::Audio.frame_period(Speed::Normal, None),
+            Some(Pacing::Native.frame_duration())
+        );
+    }
+
+    #[test]
+    fn the_audio_correction_is_bounded() {
+        let native = Pacing::Native.frame_duration();
+
+        assert_eq!(
+            Pacing::Audio.frame_period(Speed::Normal, Some(100.0)),
+            Some(native.mul_f64(1.05))
+        );
+        assert_eq!(
+            Pacing::Audio.frame_period(Speed::Normal, Some(0.0)),
+            Some(native.mul_f64(0.95))
+        );
+    }
+
+    #[test]
+    fn speed_scales_the_period() {
+        assert_eq!(
+            Pacing::Native.frame_period(Speed::Quarter, None),
+            Some(Pacing::Native.frame_duration() * 4)
+        );
+        assert_eq!(Pacing::Native.frame_period(Speed::Unlimited, None), None);
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{Color32, Label, RichText, Sense, Ui};
+use tokio::sync::watch;
+
+use crate::debug::{
+    Cpu, DebugHandle, Hooks, Instruction, Registers, LISTING_AFTER, LISTING_BEFORE,
+};
+use crate::EmuStateChange;
+
+use super::Panel;
+
+/// The code around either CPU's PC, with breakpoints set by clicking beside
+/// an instruction.
+///
+/// Breakpoints and stepping by instruction are the console's hooks, which the
+/// core runs itself, so they leave the GDB stub to gdb.
+pub struct DisassemblyPanel {
+    debug: DebugHandle,
+    hooks: Arc<Mutex<Hooks>>,
+    state: watch::Sender<Option<EmuStateChange>>,
+    cpu: Cpu,
+    /// As of the last frame, for a CPU that isn't held.
+    listing: Arc<Mutex<(Registers, Vec<Instruction>)>>,
+}
+
+impl DisassemblyPanel {
+    pub fn new(
+        debug: DebugHandle,
+        hooks: Arc<Mutex<Hooks>>,
+        state: watch::Sender<Option<EmuStateChange>>,
+    ) -> Self {
+        DisassemblyPanel {
+            debug,
+            hooks,
+            state,
+            cpu: Cpu::Arm9,
+            listing: Default::default(),
+        }
+    }
+
+    fn step_instruction(&self, held: bool) {
+        self.hooks.lock().unwrap().step(self.cpu);
+
+        // A paused CPU is only held once a frame starts running it.
+        if !held {
+            let _ = self.state.send(Some(EmuStateChange::Step));
+        }
+    }
+
+    fn step_frame(&self, held: bool) {
+        if held {
+            self.hooks.lock().unwrap().resume(self.cpu);
+        }
+        let _ = self.state.send(Some(EmuStateChange::Step));
+    }
+
+    fn toggle_breakpoint(&self, addr: u32) {
+        let mut hooks = self.hooks.lock().unwrap();
+        let set = !hooks.has_breakpoint(self.cpu, addr);
+        hooks.set_breakpoint(self.cpu, addr, set);
+    }
+}
+
+impl Panel for DisassemblyPanel {
+    fn title(&self) -> &str {
+        "Disassembly"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        let cpu = self.cpu;
+        let halt = self.hooks.lock().unwrap().halt(cpu);
+        let held = halt.is_some();
+
+        // A held CPU keeps the emulator thread inside the frame, where it
+        // can't serve a fetch, so its listing is the one read from the
+        // console as it was held.
+        let (registers, listing) = match halt {
+            Some(halt) => {
+                ui.ctx()
+                    .request_repaint_after(std::time::Duration::from_millis(100));
+                (halt.registers, halt.listing)
+            }
+            None => {
+                self.debug.fetch(&self.listing, move |nds| {
+                    let registers = nds.registers(cpu);
+                    let size = if registers.is_thumb() { 2 } else { 4 };
+                    let start = registers.pc().wrapping_sub(LISTING_BEFORE * size);
+                    let count = (LISTING_BEFORE + LISTING_AFTER) as usize;
+                    let listing = nds.disassemble(cpu, start, count, registers.is_thumb());
+                    (registers, listing)
+                });
+                self.listing.lock().unwrap().clone()
+            }
+        };
+
+        ui.horizontal(|ui| {
+            for cpu in Cpu::ALL {
+                ui.selectable_value(&mut self.cpu, cpu, cpu.to_string());
+            }
+            ui.separator();
+            ui.label(if held { "held" } else { "running" });
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Step instruction").clicked() {
+                self.step_instruction(held);
+            }
+            if ui
+                .add_enabled(held, egui::Button::new("Continue"))
+                .clicked()
+            {
+                self.hooks.lock().unwrap().resume(self.cpu);
+            }
+            if ui.button("Step frame").clicked() {
+                self.step_frame(held);
+            }
+        });
+        ui.separator();
+
+        let pc = registers.pc();
+        egui::Grid::new("disassembly")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for instruction in &listing {
+                    let breakpoint = self
+                        .hooks
+                        .lock()
+                        .unwrap()
+                        .has_breakpoint(cpu, instruction.addr);
+                    let marker = match (breakpoint, instruction.addr == pc) {
+                        (true, _) => RichText::new("●").color(Color32::RED),
+                        (false, true) => RichText::new("▶"),
+                        (false, false) => RichText::new(" "),
+                    };
+                    let gutter = ui
+                        .add(Label::new(marker.monospace()).sense(Sense::click()))
+                        .on_hover_text("Click to toggle a breakpoint");
+                    if gutter.clicked() {
+                        self.toggle_breakpoint(instruction.addr);
+                    }
+
+                    let text = |text: String| {
+                        let text = RichText::new(text).monospace();
+                        if instruction.addr == pc {
+                            text.strong()
+                        } else {
+                            text
+                        }
+                    };
+                    ui.label(text(format!("{:08X}", instruction.addr)));
+                    ui.label(text(match instruction.len {
+                        2 => format!("{:04X}", instruction.opcode),
+                        _ => format!("{:08X}", instruction.opcode),
+                    }));
+                    ui.label(text(instruction.text.clone()));
+                    ui.end_row();
+                }
+            });
+    }
+}
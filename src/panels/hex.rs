@@ -0,0 +1,284 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{Color32, ComboBox, Key, Label, RichText, Sense, TextEdit, Ui};
+
+use crate::debug::{Cpu, DebugHandle};
+use crate::melon::memory::MemoryDomain;
+use crate::ram::{find_pattern, parse_pattern, HexPage, Location};
+
+use super::{parse_hex, Panel};
+
+const COLUMNS: usize = 16;
+const ROWS: usize = 16;
+pub(super) const PAGE_LEN: usize = COLUMNS * ROWS;
+
+/// A page of any memory domain, refreshed every frame, with the bytes the
+/// last frame changed picked out. Bytes are edited by clicking on them.
+pub struct HexPanel {
+    debug: DebugHandle,
+    page: Arc<Mutex<HexPage>>,
+    goto: String,
+    pattern: String,
+    /// The byte last found or edited.
+    cursor: Option<usize>,
+    /// The byte being typed into, and what has been typed so far.
+    editing: Option<(usize, String)>,
+    /// Set from the emulator thread once a search is done.
+    found: Arc<Mutex<Option<Option<usize>>>>,
+    status: String,
+    primed: bool,
+}
+
+impl HexPanel {
+    pub fn new(debug: DebugHandle, page: Arc<Mutex<HexPage>>) -> Self {
+        HexPanel {
+            debug,
+            page,
+            goto: String::new(),
+            pattern: String::new(),
+            cursor: None,
+            editing: None,
+            found: Default::default(),
+            status: String::new(),
+            primed: false,
+        }
+    }
+
+    /// Shows the rows around `offset`, reading them straight away so they
+    /// show even while paused.
+    fn show(&self, domain: MemoryDomain, offset: usize) {
+        let start = offset - offset % COLUMNS;
+        self.page.lock().unwrap().show(domain, start);
+
+        let page = self.page.clone();
+        self.debug.run(move |nds| {
+            let mut page = page.lock().unwrap();
+            let memory = nds.memory(page.domain);
+            page.update(memory, false);
+        });
+    }
+
+    fn search(&self, domain: MemoryDomain) -> Result<(), String> {
+        let pattern = parse_pattern(&self.pattern).ok_or("type bytes in hex, ?? for any")?;
+        let from = self.cursor.map_or(0, |cursor| cursor + 1);
+
+        let found = self.found.clone();
+        self.debug.run(move |nds| {
+            let at = find_pattern(nds.memory(domain), &pattern, from);
+            *found.lock().unwrap() = Some(at);
+        });
+        Ok(())
+    }
+
+    fn write(&self, domain: MemoryDomain, offset: usize, value: u8) {
+        let location = Location::Domain {
+            domain,
+            offset: offset as u32,
+        };
+        let page = self.page.clone();
+        self.debug.run(move |nds| {
+            location.write(nds, &[value]);
+            let mut page = page.lock().unwrap();
+            let memory = nds.memory(page.domain);
+            page.update(memory, false);
+        });
+    }
+
+    /// The address a row is labelled with: where the ARM9, or failing that
+    /// the ARM7, sees it, or the offset into the domain.
+    fn address(domain: MemoryDomain, offset: usize) -> String {
+        let base = domain
+            .fixed_bus_address(Cpu::Arm9)
+            .or_else(|| domain.fixed_bus_address(Cpu::Arm7));
+        match base {
+            Some(base) => format!("{:08X}", base as usize + offset),
+            None => format!("+{offset:07X}"),
+        }
+    }
+
+    fn navigation(&mut self, ui: &mut Ui, page: &HexPage) {
+        ui.horizontal(|ui| {
+            let mut domain = page.domain;
+            ComboBox::from_id_salt("hex_domain")
+                .selected_text(domain.name())
+                .show_ui(ui, |ui| {
+                    for option in MemoryDomain::ALL {
+                        ui.selectable_value(&mut domain, option, option.name());
+                    }
+                });
+            if domain != page.domain {
+                self.cursor = None;
+                self.show(domain, 0);
+            }
+
+            if ui.button("▲").clicked() {
+                self.show(page.domain, page.start.saturating_sub(PAGE_LEN));
+            }
+            let last = page.bytes.len() < page.len;
+            if ui.add_enabled(!last, egui::Button::new("▼")).clicked() {
+                self.show(page.domain, page.start + PAGE_LEN);
+            }
+
+            let goto = ui.add(
+                TextEdit::singleline(&mut self.goto)
+                    .hint_text("go to")
+                    .code_editor()
+                    .desired_width(72.0),
+            );
+            let entered = goto.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if entered {
+                match parse_hex(&self.goto) {
+                    Some(addr) => {
+                        let offset = Self::offset(page.domain, addr);
+                        self.cursor = Some(offset);
+                        self.show(page.domain, offset);
+                    }
+                    None => self.status = String::from("type an address in hex"),
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.pattern)
+                    .hint_text("bytes to find, e.g. DE AD ?? EF")
+                    .code_editor()
+                    .desired_width(200.0),
+            );
+            if ui.button("Find next").clicked() {
+                self.status = match self.search(page.domain) {
+                    Ok(()) => String::from("searching"),
+                    Err(err) => err,
+                };
+            }
+            ui.label(&self.status);
+        });
+    }
+
+    /// Takes a bus address for the domain's rows, or an offset into it.
+    fn offset(domain: MemoryDomain, addr: u32) -> usize {
+        let base = domain
+            .fixed_bus_address(Cpu::Arm9)
+            .or_else(|| domain.fixed_bus_address(Cpu::Arm7));
+        match base {
+            Some(base) if addr >= base => (addr - base) as usize,
+            _ => addr as usize,
+        }
+    }
+
+    fn byte(&mut self, ui: &mut Ui, page: &HexPage, i: usize) {
+        let offset = page.start + i;
+        let byte = page.bytes[i];
+
+        match &mut self.editing {
+            Some((editing, text)) if *editing == offset => {
+                let response = ui.add(TextEdit::singleline(text).code_editor().desired_width(16.0));
+                response.request_focus();
+
+                if response.lost_focus() {
+                    if ui.input(|input| input.key_pressed(Key::Enter)) {
+                        if let Ok(value) = u8::from_str_radix(text.trim(), 16) {
+                            self.write(page.domain, offset, value);
+                            self.cursor = Some(offset);
+                        }
+                    }
+                    self.editing = None;
+                }
+            }
+            _ => {
+                let mut text = RichText::new(format!("{byte:02X}")).monospace();
+                if page.changed[i] {
+                    text = text.color(Color32::from_rgb(255, 140, 60));
+                }
+                if self.cursor == Some(offset) {
+                    text = text.strong().underline();
+                }
+
+                let mut label = ui.add(Label::new(text).sense(Sense::click()));
+                if page.domain.is_writable() {
+                    label = label.on_hover_text("Click to edit");
+                    if label.clicked() {
+                        self.editing = Some((offset, format!("{byte:02X}")));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Panel for HexPanel {
+    fn title(&self) -> &str {
+        "Memory"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        if let Some(found) = self.found.lock().unwrap().take() {
+            match found {
+                Some(offset) => {
+                    let domain = self.page.lock().unwrap().domain;
+                    self.status = format!("found at {}", Self::address(domain, offset));
+                    self.cursor = Some(offset);
+                    self.show(domain, offset);
+                }
+                None => self.status = String::from("not found"),
+            }
+        }
+
+        // Drawn from a copy, so the emulator thread isn't kept waiting.
+        let page = self.page.lock().unwrap().clone();
+        if !self.primed {
+            // Nothing is read until a frame runs otherwise.
+            self.show(page.domain, page.start);
+            self.primed = true;
+        }
+        let last = page.bytes.len() < page.len;
+
+        self.navigation(ui, &page);
+        ui.separator();
+
+        let rows = ui.interact(ui.max_rect(), ui.id().with("hex_scroll"), Sense::hover());
+        if rows.hovered() {
+            let scroll = ui.input(|input| input.smooth_scroll_delta.y);
+            if scroll.abs() >= 1.0 {
+                let lines = (scroll.abs() / 16.0).ceil() as usize * COLUMNS;
+                if scroll > 0.0 {
+                    self.show(page.domain, page.start.saturating_sub(lines));
+                } else if !last {
+                    self.show(page.domain, page.start + lines);
+                }
+            }
+        }
+
+        egui::Grid::new("hex")
+            .num_columns(COLUMNS + 2)
+            .spacing([4.0, 2.0])
+            .show(ui, |ui| {
+                for (row, bytes) in page.bytes.chunks(COLUMNS).enumerate() {
+                    let offset = page.start + row * COLUMNS;
+                    ui.label(
+                        RichText::new(Self::address(page.domain, offset))
+                            .monospace()
+                            .weak(),
+                    );
+
+                    for column in 0..COLUMNS {
+                        if column < bytes.len() {
+                            self.byte(ui, &page, row * COLUMNS + column);
+                        } else {
+                            ui.label("");
+                        }
+                    }
+
+                    let text: String = bytes
+                        .iter()
+                        .map(|&byte| match byte {
+                            0x20..=0x7E => byte as char,
+                            _ => '.',
+                        })
+                        .collect();
+                    ui.label(RichText::new(text).monospace());
+                    ui.end_row();
+                }
+            });
+    }
+}
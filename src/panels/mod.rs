@@ -0,0 +1,89 @@
+mod disassembly;
+mod hex;
+mod ram_search;
+mod ram_watch;
+mod registers;
+mod script;
+
+pub use disassembly::DisassemblyPanel;
+pub use hex::HexPanel;
+pub use ram_search::RamSearchPanel;
+pub use ram_watch::RamWatchPanel;
+pub use registers::RegistersPanel;
+pub use script::ScriptPanel;
+
+use std::sync::{Arc, Mutex};
+
+use egui::Ui;
+use tokio::sync::{mpsc, watch};
+
+use crate::debug::{DebugHandle, Hooks};
+use crate::frontend::Request;
+use crate::melon::memory::MemoryDomain;
+use crate::observe::FrameObserver;
+use crate::ram::{HexObserver, HexPage, RamWatchObserver, WatchList};
+use crate::EmuStateChange;
+
+/// A debugging window, opened from the screens' context menu.
+pub trait Panel: Send {
+    fn title(&self) -> &str;
+
+    /// Draws the panel's contents. Only called while it is open.
+    fn ui(&mut self, ui: &mut Ui);
+}
+
+type Observers = Vec<Box<dyn FrameObserver>>;
+
+/// Every panel the window offers, and the observers that keep them current.
+/// `hooks` are the console's, for the panels that stop and step the CPUs, and
+/// `requests` reach the emulator thread's frontend.
+pub fn all(
+    debug: &DebugHandle,
+    hooks: Arc<Mutex<Hooks>>,
+    state: &watch::Sender<Option<EmuStateChange>>,
+    requests: &mpsc::Sender<Request>,
+) -> (Vec<Box<dyn Panel>>, Observers) {
+    let watches = Arc::new(Mutex::new(WatchList::default()));
+    let page = Arc::new(Mutex::new(HexPage::new(
+        MemoryDomain::MainRam,
+        hex::PAGE_LEN,
+    )));
+
+    let panels: Vec<Box<dyn Panel>> = vec![
+        Box::new(RegistersPanel::new(debug.clone())),
+        Box::new(DisassemblyPanel::new(debug.clone(), hooks, state.clone())),
+        Box::new(RamWatchPanel::new(watches.clone(), debug.clone())),
+        Box::new(RamSearchPanel::new(debug.clone(), watches.clone())),
+        Box::new(HexPanel::new(debug.clone(), page.clone())),
+        Box::new(ScriptPanel::new(requests.clone())),
+    ];
+    let observers: Observers = vec![
+        Box::new(RamWatchObserver::new(watches, debug.clone())),
+        Box::new(HexObserver::new(page)),
+    ];
+
+    (panels, observers)
+}
+
+/// Parses a value typed in hex, with or without a leading `0x`.
+pub(crate) fn parse_hex(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    u32::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_parses_with_or_without_a_prefix() {
+        assert_eq!(parse_hex("0x02000000"), Some(0x0200_0000));
+        assert_eq!(parse_hex(" ff "), Some(0xFF));
+        assert_eq!(parse_hex("g"), None);
+    }
+}
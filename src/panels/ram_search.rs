@@ -0,0 +1,225 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{ComboBox, RichText, ScrollArea, TextEdit, Ui};
+
+use crate::debug::{Cpu, DebugHandle};
+use crate::melon::memory::MemoryDomain;
+use crate::ram::{Candidate, Comparison, Filter, Location, RamWatch, Search, ValueType, WatchList};
+
+use super::{parse_hex, Panel};
+
+/// The most candidates listed at once. The rest are still filtered.
+const MAX_ROWS: usize = 500;
+
+/// Finds where the game keeps a value by snapshotting a memory domain and
+/// filtering the values in it by how they change.
+pub struct RamSearchPanel {
+    debug: DebugHandle,
+    watches: Arc<Mutex<WatchList>>,
+    domain: MemoryDomain,
+    size: usize,
+    signed: bool,
+    comparison: Comparison,
+    value: String,
+    /// Filtered on the emulator thread, which has the memory.
+    search: Arc<Mutex<Option<Search>>>,
+    /// How many candidates are left, and the first of them.
+    rows: Arc<Mutex<(usize, Vec<Candidate>)>>,
+}
+
+impl RamSearchPanel {
+    pub fn new(debug: DebugHandle, watches: Arc<Mutex<WatchList>>) -> Self {
+        RamSearchPanel {
+            debug,
+            watches,
+            domain: MemoryDomain::MainRam,
+            size: 1,
+            signed: false,
+            comparison: Comparison::Equal,
+            value: String::new(),
+            search: Default::default(),
+            rows: Default::default(),
+        }
+    }
+
+    fn start(&self) {
+        let search = self.search.clone();
+        let (domain, kind) = (self.domain, ValueType::integer(self.size, self.signed));
+        self.debug.run(move |nds| {
+            *search.lock().unwrap() = Some(Search::new(domain, kind, nds.memory(domain)));
+        });
+    }
+
+    fn filter(&self, filter: Filter) {
+        let search = self.search.clone();
+        self.debug.run(move |nds| {
+            if let Some(search) = search.lock().unwrap().as_mut() {
+                let memory = nds.memory(search.domain);
+                search.filter(filter, memory);
+            }
+        });
+    }
+
+    fn settings(&mut self, ui: &mut Ui, searching: bool) {
+        ui.add_enabled_ui(!searching, |ui| {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_salt("ram_search_domain")
+                    .selected_text(self.domain.name())
+                    .show_ui(ui, |ui| {
+                        for domain in MemoryDomain::ALL {
+                            ui.selectable_value(&mut self.domain, domain, domain.name());
+                        }
+                    });
+                ComboBox::from_id_salt("ram_search_size")
+                    .selected_text(format!("{}-bit", self.size * 8))
+                    .width(56.0)
+                    .show_ui(ui, |ui| {
+                        for size in [1, 2, 4] {
+                            ui.selectable_value(&mut self.size, size, format!("{}-bit", size * 8));
+                        }
+                    });
+                ui.checkbox(&mut self.signed, "signed");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("New search").clicked() {
+                self.start();
+            }
+            if ui
+                .add_enabled(searching, egui::Button::new("Clear"))
+                .clicked()
+            {
+                let search = self.search.clone();
+                self.debug.run(move |_| *search.lock().unwrap() = None);
+            }
+        });
+    }
+
+    fn filters(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            for (filter, name) in [
+                (Filter::Unchanged, "Unchanged"),
+                (Filter::Changed, "Changed"),
+                (Filter::Increased, "Increased"),
+                (Filter::Decreased, "Decreased"),
+            ] {
+                if ui.button(name).clicked() {
+                    self.filter(filter);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_salt("ram_search_comparison")
+                .selected_text(self.comparison.to_string())
+                .width(32.0)
+                .show_ui(ui, |ui| {
+                    for comparison in Comparison::ALL {
+                        ui.selectable_value(
+                            &mut self.comparison,
+                            comparison,
+                            comparison.to_string(),
+                        );
+                    }
+                });
+            ui.add(
+                TextEdit::singleline(&mut self.value)
+                    .hint_text("value")
+                    .code_editor()
+                    .desired_width(80.0),
+            );
+
+            let value = parse_value(&self.value);
+            if ui
+                .add_enabled(value.is_some(), egui::Button::new("Compare"))
+                .on_hover_text("Decimal, or hex with a leading 0x")
+                .clicked()
+            {
+                self.filter(Filter::Compare(self.comparison, value.unwrap_or_default()));
+            }
+        });
+    }
+
+    fn watch(&self, candidate: &Candidate) {
+        let kind = ValueType::integer(self.size, self.signed);
+        let location = Location::Domain {
+            domain: self.domain,
+            offset: candidate.offset,
+        };
+        let label = format!("search {:X}", candidate.offset);
+        let watch = RamWatch::new(label, location, kind);
+        self.watches.lock().unwrap().watches.push(watch);
+    }
+}
+
+impl Panel for RamSearchPanel {
+    fn title(&self) -> &str {
+        "RAM search"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        let search = self.search.clone();
+        self.debug.fetch(&self.rows, move |nds| {
+            match search.lock().unwrap().as_ref() {
+                Some(search) => {
+                    let memory = nds.memory(search.domain);
+                    (search.len(), search.candidates(memory, MAX_ROWS))
+                }
+                None => (0, Vec::new()),
+            }
+        });
+        let searching = self.search.lock().unwrap().is_some();
+        let (count, rows) = self.rows.lock().unwrap().clone();
+
+        self.settings(ui, searching);
+        ui.add_enabled_ui(searching, |ui| self.filters(ui));
+        ui.separator();
+
+        if !searching {
+            ui.label("Start a search to snapshot the domain");
+            return;
+        }
+        ui.label(match count {
+            1 => String::from("1 candidate"),
+            _ if count > rows.len() => format!("{count} candidates, showing {}", rows.len()),
+            _ => format!("{count} candidates"),
+        });
+
+        let bus = self.domain.fixed_bus_address(Cpu::Arm9);
+        ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+            egui::Grid::new("ram_search")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Address");
+                    ui.label("Previous");
+                    ui.label("Current");
+                    ui.end_row();
+
+                    for candidate in &rows {
+                        let address = match bus {
+                            Some(base) => format!("{:08X}", base + candidate.offset),
+                            None => format!("+{:X}", candidate.offset),
+                        };
+                        ui.label(RichText::new(address).monospace());
+                        ui.label(RichText::new(candidate.previous.to_string()).monospace());
+                        ui.label(RichText::new(candidate.current.to_string()).monospace());
+                        if ui.small_button("Watch").clicked() {
+                            self.watch(candidate);
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+/// Parses a value typed in decimal, or in hex with a leading `0x`.
+fn parse_value(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if text.starts_with("0x") || text.starts_with("0X") {
+        return parse_hex(text).map(i64::from);
+    }
+    text.parse().ok()
+}
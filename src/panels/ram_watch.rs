@@ -0,0 +1,265 @@
+use std::fmt;
+use std::hash::Hash;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use egui::{ComboBox, DragValue, Key, Label, RichText, Sense, TextEdit, Ui};
+
+use crate::debug::{Cpu, DebugHandle};
+use crate::melon::memory::MemoryDomain;
+use crate::ram::{Format, Location, RamWatch, ValueType, WatchList};
+
+use super::{parse_hex, Panel};
+
+/// Where a new watch's address is counted from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Base {
+    Bus(Cpu),
+    Domain(MemoryDomain),
+}
+
+impl Base {
+    fn name(self) -> String {
+        match self {
+            Base::Bus(cpu) => format!("{cpu} bus"),
+            Base::Domain(domain) => domain.name(),
+        }
+    }
+}
+
+/// Values in memory, kept current after every frame, that can be frozen or
+/// changed by clicking on them.
+pub struct RamWatchPanel {
+    list: Arc<Mutex<WatchList>>,
+    debug: DebugHandle,
+    path: String,
+    label: String,
+    base: Base,
+    address: String,
+    kind: ValueType,
+    /// The watch being typed into, and what has been typed so far.
+    editing: Option<(usize, String)>,
+    status: String,
+}
+
+impl RamWatchPanel {
+    pub fn new(list: Arc<Mutex<WatchList>>, debug: DebugHandle) -> Self {
+        RamWatchPanel {
+            list,
+            debug,
+            path: String::from("watches.yml"),
+            label: String::new(),
+            base: Base::Bus(Cpu::Arm9),
+            address: String::new(),
+            kind: ValueType::U32,
+            editing: None,
+            status: String::new(),
+        }
+    }
+
+    fn files(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.add(TextEdit::singleline(&mut self.path).desired_width(160.0));
+
+            if ui.button("Load").clicked() {
+                self.status = match WatchList::load(Path::new(&self.path)) {
+                    Ok(list) => {
+                        *self.list.lock().unwrap() = list;
+                        self.editing = None;
+                        format!("loaded {}", self.path)
+                    }
+                    Err(err) => format!("couldn't load {}: {err}", self.path),
+                };
+            }
+            if ui.button("Save").clicked() {
+                let list = self.list.lock().unwrap().clone();
+                self.status = match list.save(Path::new(&self.path)) {
+                    Ok(()) => format!("saved {}", self.path),
+                    Err(err) => format!("couldn't save {}: {err}", self.path),
+                };
+            }
+            ui.label(&self.status);
+        });
+    }
+
+    fn add_form(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.label)
+                    .hint_text("label")
+                    .desired_width(80.0),
+            );
+
+            ComboBox::from_id_salt("ram_watch_base")
+                .selected_text(self.base.name())
+                .show_ui(ui, |ui| {
+                    for cpu in Cpu::ALL {
+                        ui.selectable_value(&mut self.base, Base::Bus(cpu), Base::Bus(cpu).name());
+                    }
+                    for domain in MemoryDomain::ALL {
+                        let base = Base::Domain(domain);
+                        ui.selectable_value(&mut self.base, base, base.name());
+                    }
+                });
+
+            ui.add(
+                TextEdit::singleline(&mut self.address)
+                    .hint_text("address")
+                    .code_editor()
+                    .desired_width(72.0),
+            );
+            kind_picker(ui, "ram_watch_kind", &mut self.kind);
+
+            let addr = parse_hex(&self.address);
+            if ui
+                .add_enabled(addr.is_some(), egui::Button::new("Add"))
+                .clicked()
+            {
+                let addr = addr.unwrap_or_default();
+                let location = match self.base {
+                    Base::Bus(cpu) => Location::Address { cpu, addr },
+                    Base::Domain(domain) => Location::Domain {
+                        domain,
+                        offset: addr,
+                    },
+                };
+                let label = std::mem::take(&mut self.label);
+                let watch = RamWatch::new(label, location, self.kind);
+                self.list.lock().unwrap().watches.push(watch);
+            }
+        });
+    }
+
+    fn value(&mut self, ui: &mut Ui, index: usize, watch: &RamWatch) {
+        match &mut self.editing {
+            Some((editing, text)) if *editing == index => {
+                let response = ui.add(TextEdit::singleline(text).code_editor().desired_width(96.0));
+                response.request_focus();
+
+                if response.lost_focus() {
+                    if ui.input(|input| input.key_pressed(Key::Enter)) {
+                        if let Some(bytes) = watch.kind.parse(text, watch.format) {
+                            self.write(index, watch, bytes);
+                        }
+                    }
+                    self.editing = None;
+                }
+            }
+            _ => {
+                let text = watch.text();
+                let label = Label::new(RichText::new(&text).monospace()).sense(Sense::click());
+                if ui.add(label).on_hover_text("Click to edit").clicked() {
+                    self.editing = Some((index, text));
+                }
+            }
+        }
+    }
+
+    fn write(&self, index: usize, watch: &RamWatch, bytes: Vec<u8>) {
+        // A frozen value would only be put back, so change what it is frozen
+        // to as well.
+        if let Some(current) = self.list.lock().unwrap().watches.get_mut(index) {
+            if current.frozen.is_some() {
+                current.frozen = Some(bytes.clone());
+            }
+        }
+
+        let location = watch.location;
+        self.debug.run(move |nds| location.write(nds, &bytes));
+    }
+}
+
+impl Panel for RamWatchPanel {
+    fn title(&self) -> &str {
+        "RAM watch"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        self.files(ui);
+        self.add_form(ui);
+        ui.separator();
+
+        // Drawn from a copy, so the emulator thread isn't kept waiting.
+        let watches = self.list.lock().unwrap().watches.clone();
+        let mut removed = None;
+
+        egui::Grid::new("ram_watch")
+            .num_columns(7)
+            .striped(true)
+            .show(ui, |ui| {
+                for (index, watch) in watches.iter().enumerate() {
+                    ui.label(&watch.label);
+                    ui.label(RichText::new(watch.location.to_string()).monospace());
+
+                    let mut kind = watch.kind;
+                    let mut format = watch.format;
+                    kind_picker(ui, ("ram_watch_kind", index), &mut kind);
+                    ComboBox::from_id_salt(("ram_watch_format", index))
+                        .selected_text(format.to_string())
+                        .width(48.0)
+                        .show_ui(ui, |ui| {
+                            for option in [Format::Decimal, Format::Hex, Format::Binary] {
+                                ui.selectable_value(&mut format, option, option.to_string());
+                            }
+                        });
+
+                    self.value(ui, index, watch);
+
+                    let mut frozen = watch.frozen.is_some();
+                    let freeze = ui.checkbox(&mut frozen, "freeze").changed();
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+
+                    let mut list = self.list.lock().unwrap();
+                    let Some(current) = list.watches.get_mut(index) else {
+                        continue;
+                    };
+                    if kind != current.kind {
+                        current.kind = kind;
+                        current.frozen = None;
+                        current.value = None;
+                    }
+                    current.format = format;
+                    if freeze {
+                        current.frozen = if frozen { current.value.clone() } else { None };
+                    }
+                }
+            });
+
+        if let Some(index) = removed {
+            self.list.lock().unwrap().watches.remove(index);
+            self.editing = None;
+        }
+    }
+}
+
+/// A drop-down of every value type, with the length beside it for strings.
+fn kind_picker(ui: &mut Ui, id: impl Hash + fmt::Debug, kind: &mut ValueType) {
+    ui.horizontal(|ui| {
+        ComboBox::from_id_salt(id)
+            .selected_text(match kind {
+                ValueType::Str(_) => String::from("string"),
+                _ => kind.to_string(),
+            })
+            .width(72.0)
+            .show_ui(ui, |ui| {
+                for option in ValueType::NUMBERS {
+                    ui.selectable_value(kind, option, option.to_string());
+                }
+                if ui
+                    .selectable_label(matches!(kind, ValueType::Str(_)), "string")
+                    .clicked()
+                    && !matches!(kind, ValueType::Str(_))
+                {
+                    *kind = ValueType::Str(16);
+                }
+            });
+
+        if let ValueType::Str(len) = kind {
+            ui.add(DragValue::new(len).range(1..=256));
+        }
+    });
+}
@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{Key, Label, RichText, Sense, TextEdit, Ui};
+
+use crate::debug::{Cpu, DebugHandle, Register, Registers};
+
+use super::{parse_hex, Panel};
+
+/// Both CPUs' registers, each editable by clicking on it.
+pub struct RegistersPanel {
+    debug: DebugHandle,
+    registers: Arc<Mutex<[Registers; 2]>>,
+    /// The register being typed into, and what has been typed so far.
+    editing: Option<(Cpu, Register, String)>,
+}
+
+impl RegistersPanel {
+    pub fn new(debug: DebugHandle) -> Self {
+        RegistersPanel {
+            debug,
+            registers: Default::default(),
+            editing: None,
+        }
+    }
+
+    fn rows() -> impl Iterator<Item = Register> {
+        (0..15)
+            .map(Register::Gpr)
+            .chain([Register::Pc, Register::Cpsr, Register::Spsr])
+    }
+
+    fn value(&mut self, ui: &mut Ui, cpu: Cpu, register: Register, value: Option<u32>) {
+        let Some(value) = value else {
+            ui.label("-");
+            return;
+        };
+
+        match &mut self.editing {
+            Some((editing_cpu, editing_register, text))
+                if *editing_cpu == cpu && *editing_register == register =>
+            {
+                let response = ui.add(TextEdit::singleline(text).code_editor().desired_width(64.0));
+                response.request_focus();
+
+                if response.lost_focus() {
+                    if ui.input(|input| input.key_pressed(Key::Enter)) {
+                        if let Some(value) = parse_hex(text) {
+                            self.debug
+                                .run(move |nds| nds.set_register(cpu, register, value));
+                        }
+                    }
+                    self.editing = None;
+                }
+            }
+            _ => {
+                let label = Label::new(RichText::new(format!("{value:08X}")).monospace())
+                    .sense(Sense::click());
+                if ui.add(label).on_hover_text("Click to edit").clicked() {
+                    self.editing = Some((cpu, register, format!("{value:08X}")));
+                }
+            }
+        }
+    }
+}
+
+impl Panel for RegistersPanel {
+    fn title(&self) -> &str {
+        "Registers"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        self.debug.fetch(&self.registers, |nds| {
+            [nds.registers(Cpu::Arm9), nds.registers(Cpu::Arm7)]
+        });
+        let registers = *self.registers.lock().unwrap();
+
+        egui::Grid::new("registers")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                for cpu in Cpu::ALL {
+                    ui.strong(cpu.to_string());
+                }
+                ui.end_row();
+
+                for register in Self::rows() {
+                    ui.label(register.to_string());
+                    for (cpu, registers) in Cpu::ALL.into_iter().zip(&registers) {
+                        self.value(ui, cpu, register, registers.get(register));
+                    }
+                    ui.end_row();
+                }
+
+                ui.label("");
+                for registers in &registers {
+                    ui.monospace(format!(
+                        "{} {} {}",
+                        registers.flags(),
+                        registers.mode(),
+                        if registers.is_thumb() { "thumb" } else { "arm" }
+                    ));
+                }
+                ui.end_row();
+            });
+    }
+}
@@ -0,0 +1,49 @@
+use egui::{TextEdit, Ui};
+use tokio::sync::mpsc;
+
+use crate::frontend::Request;
+
+use super::Panel;
+
+/// Starts and stops the Lua script running alongside the game.
+pub struct ScriptPanel {
+    requests: mpsc::Sender<Request>,
+    path: String,
+}
+
+impl ScriptPanel {
+    pub fn new(requests: mpsc::Sender<Request>) -> Self {
+        ScriptPanel {
+            requests,
+            path: String::from("script.lua"),
+        }
+    }
+}
+
+impl Panel for ScriptPanel {
+    fn title(&self) -> &str {
+        "Script"
+    }
+
+    fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.add(TextEdit::singleline(&mut self.path).desired_width(200.0));
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Run").clicked() {
+                let _ = self
+                    .requests
+                    .try_send(Request::LoadScript(self.path.clone().into()));
+            }
+            if ui.button("Stop").clicked() {
+                let _ = self.requests.try_send(Request::StopScript);
+            }
+        });
+
+        if cfg!(not(feature = "lua")) {
+            ui.label("Scripts need a build with the lua feature");
+        }
+    }
+}
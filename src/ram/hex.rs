@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+
+use crate::melon::memory::MemoryDomain;
+use crate::observe::{FrameObserver, FrameView};
+
+/// The part of a domain a hex view shows, as of the last frame.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HexPage {
+    pub domain: MemoryDomain,
+    pub start: usize,
+    pub len: usize,
+    pub bytes: Vec<u8>,
+    /// Which of `bytes` were different the frame before.
+    pub changed: Vec<bool>,
+}
+
+impl HexPage {
+    pub fn new(domain: MemoryDomain, len: usize) -> Self {
+        HexPage {
+            domain,
+            start: 0,
+            len,
+            bytes: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    /// Moves the page, forgetting what it showed before.
+    pub fn show(&mut self, domain: MemoryDomain, start: usize) {
+        self.domain = domain;
+        self.start = start;
+        self.bytes.clear();
+        self.changed.clear();
+    }
+
+    /// Reads the page from its domain's `memory`. Only bytes read after a
+    /// frame are marked as changed, so an edit made while paused isn't.
+    pub fn update(&mut self, memory: &[u8], after_frame: bool) {
+        let end = (self.start + self.len).min(memory.len());
+        let bytes = memory.get(self.start..end).unwrap_or_default();
+
+        if bytes.len() != self.bytes.len() {
+            self.changed = vec![false; bytes.len()];
+        } else if after_frame {
+            self.changed = self.bytes.iter().zip(bytes).map(|(a, b)| a != b).collect();
+        }
+        self.bytes = bytes.to_vec();
+    }
+}
+
+/// Keeps a hex view's page current after every frame.
+pub struct HexObserver {
+    page: Arc<Mutex<HexPage>>,
+}
+
+impl HexObserver {
+    pub fn new(page: Arc<Mutex<HexPage>>) -> Self {
+        HexObserver { page }
+    }
+}
+
+impl FrameObserver for HexObserver {
+    fn on_frame(&mut self, view: FrameView<'_>) {
+        let mut page = self.page.lock().unwrap();
+        let memory = view.memory(page.domain);
+        page.update(memory, true);
+    }
+}
+
+/// Parses bytes typed in hex, with or without spaces between them, where
+/// `??` matches any byte.
+pub fn parse_pattern(text: &str) -> Option<Vec<Option<u8>>> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| match pair {
+            ['?', '?'] => Some(None),
+            [high, low] => {
+                let byte = high.to_digit(16)? << 4 | low.to_digit(16)?;
+                Some(Some(byte as u8))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where `pattern` next appears in `memory`, from `from` onwards and then
+/// wrapping around to the start.
+pub fn find_pattern(memory: &[u8], pattern: &[Option<u8>], from: usize) -> Option<usize> {
+    let matches = |at: usize| {
+        memory[at..]
+            .iter()
+            .zip(pattern)
+            .all(|(byte, wanted)| wanted.is_none_or(|wanted| *byte == wanted))
+    };
+    let last = memory.len().checked_sub(pattern.len())?;
+    let from = from.min(last + 1);
+
+    (from..=last).chain(0..from).find(|&at| matches(at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_take_hex_bytes_and_wildcards() {
+        assert_eq!(
+            parse_pattern("de AD ?? 0f"),
+            Some(vec![Some(0xDE), Some(0xAD), None, Some(0x0F)])
+        );
+        assert_eq!(parse_pattern("BEEF"), Some(vec![Some(0xBE), Some(0xEF)]));
+        assert_eq!(parse_pattern("ABC"), None);
+        assert_eq!(parse_pattern("G0"), None);
+    }
+
+    #[test]
+    fn searches_wrap_around_to_the_start() {
+        let memory = [1, 2, 3, 9, 1, 2, 4];
+        let pattern = parse_pattern("01 02 ??").unwrap();
+
+        assert_eq!(find_pattern(&memory, &pattern, 0), Some(0));
+        assert_eq!(find_pattern(&memory, &pattern, 1), Some(4));
+        assert_eq!(find_pattern(&memory, &pattern, 5), Some(0));
+        assert_eq!(
+            find_pattern(&memory, &parse_pattern("0505").unwrap(), 0),
+            None
+        );
+    }
+
+    #[test]
+    fn only_bytes_changed_by_a_frame_are_marked() {
+        let mut page = HexPage::new(MemoryDomain::MainRam, 4);
+        page.update(&[0, 0, 0, 0, 0], true);
+        assert_eq!(page.changed, [false; 4]);
+
+        page.update(&[0, 7, 0, 0, 0], false);
+        assert_eq!(page.changed, [false; 4]);
+
+        page.update(&[0, 7, 8, 0, 0], true);
+        assert_eq!(page.changed, [false, false, true, false]);
+
+        page.show(MemoryDomain::MainRam, 2);
+        page.update(&[0, 7, 9, 0, 0], true);
+        assert_eq!(page.bytes, [9, 0, 0]);
+        assert_eq!(page.changed, [false; 3]);
+    }
+}
@@ -0,0 +1,249 @@
+//! Tools for looking at and changing the console's memory while it runs.
+
+mod hex;
+mod search;
+mod watch;
+
+pub use hex::{find_pattern, parse_pattern, HexObserver, HexPage};
+pub use search::{Candidate, Comparison, Filter, Search};
+pub use watch::{Location, RamWatch, RamWatchObserver, WatchList};
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// How the bytes at a location are read as a value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ValueType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    /// Signed 20.12 fixed-point, which is what most games use in place of
+    /// floats, since the console has no FPU.
+    Fixed,
+    F32,
+    /// A string of up to this many bytes, ending at the first zero.
+    Str(u32),
+}
+
+/// How a number is written out.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Format {
+    #[default]
+    Decimal,
+    Hex,
+    Binary,
+}
+
+impl ValueType {
+    pub const NUMBERS: [ValueType; 8] = [
+        ValueType::U8,
+        ValueType::I8,
+        ValueType::U16,
+        ValueType::I16,
+        ValueType::U32,
+        ValueType::I32,
+        ValueType::Fixed,
+        ValueType::F32,
+    ];
+
+    /// The integer type `size` bytes wide, which is 1, 2, or 4.
+    pub fn integer(size: usize, signed: bool) -> ValueType {
+        match (size, signed) {
+            (1, false) => ValueType::U8,
+            (1, true) => ValueType::I8,
+            (2, false) => ValueType::U16,
+            (2, true) => ValueType::I16,
+            (_, false) => ValueType::U32,
+            (_, true) => ValueType::I32,
+        }
+    }
+
+    /// How many bytes the value takes up.
+    pub fn size(self) -> usize {
+        match self {
+            ValueType::U8 | ValueType::I8 => 1,
+            ValueType::U16 | ValueType::I16 => 2,
+            ValueType::U32 | ValueType::I32 | ValueType::Fixed | ValueType::F32 => 4,
+            ValueType::Str(len) => len as usize,
+        }
+    }
+
+    fn is_signed(self) -> bool {
+        matches!(self, ValueType::I8 | ValueType::I16 | ValueType::I32)
+    }
+
+    /// Writes out the value `bytes` hold, which are as many as
+    /// [`ValueType::size`] gives. Fixed-point and float values are only
+    /// written as numbers in decimal; otherwise their raw bits are shown.
+    pub fn format(self, bytes: &[u8], format: Format) -> String {
+        if let ValueType::Str(_) = self {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            return String::from_utf8_lossy(&bytes[..end]).into_owned();
+        }
+
+        let raw = little_endian(bytes);
+        let bits = bytes.len() * 8;
+        match (self, format) {
+            (ValueType::Fixed, Format::Decimal) => {
+                format!("{}", raw as u32 as i32 as f64 / 4096.0)
+            }
+            (ValueType::F32, Format::Decimal) => format!("{}", f32::from_bits(raw as u32)),
+            (_, Format::Decimal) if self.is_signed() => format!("{}", sign_extend(raw, bits)),
+            (_, Format::Decimal) => format!("{raw}"),
+            (_, Format::Hex) => format!("{raw:0width$X}", width = bytes.len() * 2),
+            (_, Format::Binary) => format!("{raw:0bits$b}"),
+        }
+    }
+
+    /// The bytes that hold a value typed in `format`, or nothing if it
+    /// doesn't fit.
+    pub fn parse(self, text: &str, format: Format) -> Option<Vec<u8>> {
+        let text = text.trim();
+        let size = self.size();
+
+        let raw = match (self, format) {
+            (ValueType::Str(len), _) => {
+                if text.len() > len as usize {
+                    return None;
+                }
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.resize(len as usize, 0);
+                return Some(bytes);
+            }
+            (ValueType::Fixed, Format::Decimal) => {
+                let value = (text.parse::<f64>().ok()? * 4096.0).round();
+                if value < i32::MIN as f64 || value > i32::MAX as f64 {
+                    return None;
+                }
+                value as i32 as u32 as u64
+            }
+            (ValueType::F32, Format::Decimal) => text.parse::<f32>().ok()?.to_bits() as u64,
+            (_, Format::Decimal) => {
+                let value = text.parse::<i64>().ok()?;
+                let bits = size as u32 * 8;
+                let fits = if self.is_signed() {
+                    value >= -(1 << (bits - 1)) && value < 1 << (bits - 1)
+                } else {
+                    value >= 0 && value < 1 << bits
+                };
+                if !fits {
+                    return None;
+                }
+                value as u64 & (u64::MAX >> (64 - bits))
+            }
+            (_, Format::Hex) => {
+                let digits = text
+                    .strip_prefix("0x")
+                    .or_else(|| text.strip_prefix("0X"))
+                    .unwrap_or(text);
+                u64::from_str_radix(digits, 16).ok()?
+            }
+            (_, Format::Binary) => {
+                u64::from_str_radix(text.strip_prefix("0b").unwrap_or(text), 2).ok()?
+            }
+        };
+
+        if size < 8 && raw >> (size * 8) != 0 {
+            return None;
+        }
+        Some(raw.to_le_bytes()[..size].to_vec())
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::U8 => write!(f, "u8"),
+            ValueType::I8 => write!(f, "i8"),
+            ValueType::U16 => write!(f, "u16"),
+            ValueType::I16 => write!(f, "i16"),
+            ValueType::U32 => write!(f, "u32"),
+            ValueType::I32 => write!(f, "i32"),
+            ValueType::Fixed => write!(f, "fixed 20.12"),
+            ValueType::F32 => write!(f, "f32"),
+            ValueType::Str(len) => write!(f, "string[{len}]"),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Decimal => write!(f, "dec"),
+            Format::Hex => write!(f, "hex"),
+            Format::Binary => write!(f, "bin"),
+        }
+    }
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_formatted_by_type_and_format() {
+        assert_eq!(ValueType::I16.format(&[0xFE, 0xFF], Format::Decimal), "-2");
+        assert_eq!(
+            ValueType::U16.format(&[0xFE, 0xFF], Format::Decimal),
+            "65534"
+        );
+        assert_eq!(ValueType::U16.format(&[0x34, 0x12], Format::Hex), "1234");
+        assert_eq!(ValueType::U8.format(&[5], Format::Binary), "00000101");
+        assert_eq!(
+            ValueType::Fixed.format(&[0x00, 0x18, 0, 0], Format::Decimal),
+            "1.5"
+        );
+        assert_eq!(
+            ValueType::Str(8).format(b"MARIO\0\0\0", Format::Decimal),
+            "MARIO"
+        );
+    }
+
+    #[test]
+    fn parsing_gives_back_what_formatting_shows() {
+        for (kind, bytes) in [
+            (ValueType::I8, vec![0x80]),
+            (ValueType::U32, vec![0x78, 0x56, 0x34, 0x12]),
+            (ValueType::Fixed, vec![0x00, 0xE8, 0xFF, 0xFF]),
+            (ValueType::F32, 0.25f32.to_le_bytes().to_vec()),
+        ] {
+            for format in [Format::Decimal, Format::Hex, Format::Binary] {
+                let text = kind.format(&bytes, format);
+                assert_eq!(
+                    kind.parse(&text, format),
+                    Some(bytes.clone()),
+                    "{kind} {text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn values_that_dont_fit_are_refused() {
+        assert_eq!(ValueType::U8.parse("256", Format::Decimal), None);
+        assert_eq!(ValueType::I8.parse("-129", Format::Decimal), None);
+        assert_eq!(ValueType::U16.parse("0x10000", Format::Hex), None);
+        assert_eq!(ValueType::Str(2).parse("abc", Format::Decimal), None);
+        assert_eq!(
+            ValueType::Str(4).parse("ab", Format::Decimal),
+            Some(b"ab\0\0".to_vec())
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+
+use crate::melon::memory::MemoryDomain;
+
+use super::{little_endian, sign_extend, ValueType};
+
+/// How a candidate's value now is compared with the one before.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Filter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    /// Against a value typed in, rather than the one before.
+    Compare(Comparison, i64),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 4] = [
+        Comparison::Equal,
+        Comparison::NotEqual,
+        Comparison::Less,
+        Comparison::Greater,
+    ];
+
+    fn test(self, value: i64, against: i64) -> bool {
+        match self {
+            Comparison::Equal => value == against,
+            Comparison::NotEqual => value != against,
+            Comparison::Less => value < against,
+            Comparison::Greater => value > against,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, "="),
+            Comparison::NotEqual => write!(f, "≠"),
+            Comparison::Less => write!(f, "<"),
+            Comparison::Greater => write!(f, ">"),
+        }
+    }
+}
+
+impl Filter {
+    fn keeps(self, previous: i64, current: i64) -> bool {
+        match self {
+            Filter::Unchanged => current == previous,
+            Filter::Changed => current != previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+            Filter::Compare(comparison, value) => comparison.test(current, value),
+        }
+    }
+}
+
+/// An address still in the running, with its value at the last filter and
+/// now.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Candidate {
+    pub offset: u32,
+    pub previous: i64,
+    pub current: i64,
+}
+
+/// Narrows down where in a domain a value the game keeps is, by filtering
+/// every aligned value against how it changes from one snapshot to the next.
+pub struct Search {
+    pub domain: MemoryDomain,
+    /// One of the integer types.
+    pub kind: ValueType,
+    snapshot: Vec<u8>,
+    /// Every aligned offset until the first filter, so a fresh search of
+    /// main RAM doesn't need millions of them written out.
+    candidates: Option<Vec<u32>>,
+}
+
+impl Search {
+    /// Starts from a snapshot of `memory`, with every aligned offset a
+    /// candidate.
+    pub fn new(domain: MemoryDomain, kind: ValueType, memory: &[u8]) -> Self {
+        Search {
+            domain,
+            kind,
+            snapshot: memory.to_vec(),
+            candidates: None,
+        }
+    }
+
+    /// How many candidates are left.
+    pub fn len(&self) -> usize {
+        match &self.candidates {
+            Some(candidates) => candidates.len(),
+            None => self.snapshot.len() / self.kind.size(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keeps the candidates `filter` passes, comparing `memory` with the last
+    /// snapshot, which it then replaces.
+    pub fn filter(&mut self, filter: Filter, memory: &[u8]) {
+        let size = self.kind.size();
+        let offsets: Box<dyn Iterator<Item = u32>> = match self.candidates.take() {
+            Some(candidates) => Box::new(candidates.into_iter()),
+            None => Box::new((0..self.len() as u32).map(|i| i * size as u32)),
+        };
+
+        let candidates = offsets
+            .filter(|&offset| {
+                match (
+                    self.value(&self.snapshot, offset),
+                    self.value(memory, offset),
+                ) {
+                    (Some(previous), Some(current)) => filter.keeps(previous, current),
+                    _ => false,
+                }
+            })
+            .collect();
+
+        self.candidates = Some(candidates);
+        self.snapshot = memory.to_vec();
+    }
+
+    /// Up to `limit` of the candidates, in address order, with their values
+    /// in `memory`.
+    pub fn candidates(&self, memory: &[u8], limit: usize) -> Vec<Candidate> {
+        let size = self.kind.size() as u32;
+        let offsets: Box<dyn Iterator<Item = u32>> = match &self.candidates {
+            Some(candidates) => Box::new(candidates.iter().copied()),
+            None => Box::new((0..self.len() as u32).map(|i| i * size)),
+        };
+
+        offsets
+            .take(limit)
+            .filter_map(|offset| {
+                Some(Candidate {
+                    offset,
+                    previous: self.value(&self.snapshot, offset)?,
+                    current: self.value(memory, offset)?,
+                })
+            })
+            .collect()
+    }
+
+    fn value(&self, memory: &[u8], offset: u32) -> Option<i64> {
+        let offset = offset as usize;
+        let size = self.kind.size();
+        let raw = little_endian(memory.get(offset..offset + size)?);
+
+        Some(match self.kind {
+            ValueType::I8 | ValueType::I16 | ValueType::I32 => sign_extend(raw, size * 8),
+            _ => raw as i64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_narrow_down_to_what_changed_the_right_way() {
+        let before = [1, 0, 5, 0, 9, 0, 7, 0];
+        let after = [2, 0, 5, 0, 3, 0, 7, 0];
+        let mut search = Search::new(MemoryDomain::MainRam, ValueType::U16, &before);
+        assert_eq!(search.len(), 4);
+
+        search.filter(Filter::Changed, &after);
+        assert_eq!(search.len(), 2);
+
+        search.filter(Filter::Unchanged, &after);
+        let offsets: Vec<u32> = search
+            .candidates(&after, 10)
+            .iter()
+            .map(|candidate| candidate.offset)
+            .collect();
+        assert_eq!(offsets, [0, 4]);
+
+        search.filter(Filter::Decreased, &[3, 0, 5, 0, 2, 0, 7, 0]);
+        assert_eq!(
+            search.candidates(&[3, 0, 5, 0, 1, 0, 7, 0], 10),
+            [Candidate {
+                offset: 4,
+                previous: 2,
+                current: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn signedness_decides_what_counts_as_less() {
+        let memory = [0xFF, 0x01];
+
+        let mut signed = Search::new(MemoryDomain::MainRam, ValueType::I8, &memory);
+        signed.filter(Filter::Compare(Comparison::Less, 0), &memory);
+        assert_eq!(signed.len(), 1);
+
+        let mut unsigned = Search::new(MemoryDomain::MainRam, ValueType::U8, &memory);
+        unsigned.filter(Filter::Compare(Comparison::Greater, 100), &memory);
+        assert_eq!(unsigned.candidates(&memory, 10)[0].current, 255);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::debug::{Cpu, DebugHandle};
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::Nds;
+use crate::observe::{FrameObserver, FrameView};
+
+use super::{Format, ValueType};
+
+/// Where a watched value is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Location {
+    /// As a CPU sees it, which only reaches the memory domains.
+    Address {
+        cpu: Cpu,
+        addr: u32,
+    },
+    Domain {
+        domain: MemoryDomain,
+        offset: u32,
+    },
+}
+
+impl Location {
+    /// The `len` bytes here as of `view`.
+    pub fn read<'a>(&self, view: &FrameView<'a>, len: usize) -> Option<&'a [u8]> {
+        let (domain, offset) = match *self {
+            Location::Address { cpu, addr } => view.locate(cpu, addr)?,
+            Location::Domain { domain, offset } => (domain, offset as usize),
+        };
+        view.memory(domain).get(offset..offset.checked_add(len)?)
+    }
+
+    /// Must be called between frames, on the emulator thread.
+    pub fn write(&self, nds: &mut Nds, bytes: &[u8]) {
+        match *self {
+            Location::Address { cpu, addr } => {
+                for (i, &byte) in bytes.iter().enumerate() {
+                    nds.write8(cpu, addr.wrapping_add(i as u32), byte);
+                }
+            }
+            Location::Domain { domain, offset } => {
+                let offset = offset as usize;
+                let Some(memory) = nds.memory_mut(domain) else {
+                    println!("WARNING: {domain} can't be written");
+                    return;
+                };
+                match memory.get_mut(offset..offset + bytes.len()) {
+                    Some(dest) => dest.copy_from_slice(bytes),
+                    None => println!("WARNING: {offset:#x} is past the end of {domain}"),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Address { cpu, addr } => write!(f, "{cpu} {addr:08X}"),
+            Location::Domain { domain, offset } => write!(f, "{domain} +{offset:X}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RamWatch {
+    pub label: String,
+    pub location: Location,
+    pub kind: ValueType,
+    #[serde(default)]
+    pub format: Format,
+    /// Written back after every frame while set.
+    #[serde(default)]
+    pub frozen: Option<Vec<u8>>,
+    /// As of the last frame, or nothing if the location can't be read.
+    #[serde(skip)]
+    pub value: Option<Vec<u8>>,
+}
+
+impl RamWatch {
+    pub fn new(label: String, location: Location, kind: ValueType) -> Self {
+        RamWatch {
+            label,
+            location,
+            kind,
+            format: Format::default(),
+            frozen: None,
+            value: None,
+        }
+    }
+
+    /// The value as of the last frame, written out in the watch's format.
+    pub fn text(&self) -> String {
+        match &self.value {
+            Some(bytes) => self.kind.format(bytes, self.format),
+            None => String::from("-"),
+        }
+    }
+}
+
+/// The watches a RAM watch panel shows, which can be kept in a file.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WatchList {
+    pub watches: Vec<RamWatch>,
+}
+
+impl WatchList {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let yaml = fs::read_to_string(path)?;
+        serde_yaml::from_str(&yaml).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let yaml = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, yaml)
+    }
+}
+
+/// Keeps a watch list's values current, and holds frozen values in place.
+pub struct RamWatchObserver {
+    list: Arc<Mutex<WatchList>>,
+    debug: DebugHandle,
+}
+
+impl RamWatchObserver {
+    pub fn new(list: Arc<Mutex<WatchList>>, debug: DebugHandle) -> Self {
+        RamWatchObserver { list, debug }
+    }
+}
+
+impl FrameObserver for RamWatchObserver {
+    fn on_frame(&mut self, view: FrameView<'_>) {
+        let mut list = self.list.lock().unwrap();
+        let mut writes = Vec::new();
+
+        for watch in &mut list.watches {
+            watch.value = watch
+                .location
+                .read(&view, watch.kind.size())
+                .map(<[u8]>::to_vec);
+
+            if let Some(frozen) = &watch.frozen {
+                if watch.value.as_ref() != Some(frozen) {
+                    writes.push((watch.location, frozen.clone()));
+                }
+            }
+        }
+
+        // The view can't change the console, so frozen values are put back
+        // before the next frame starts instead.
+        if !writes.is_empty() {
+            self.debug.run(move |nds| {
+                for (location, bytes) in writes {
+                    location.write(nds, &bytes);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_lists_keep_everything_but_the_last_value() {
+        let mut watch = RamWatch::new(
+            String::from("lives"),
+            Location::Domain {
+                domain: MemoryDomain::MainRam,
+                offset: 0x1234,
+            },
+            ValueType::U8,
+        );
+        watch.frozen = Some(vec![9]);
+        watch.value = Some(vec![3]);
+        let list = WatchList {
+            watches: vec![watch],
+        };
+
+        let yaml = serde_yaml::to_string(&list).unwrap();
+        let loaded: WatchList = serde_yaml::from_str(&yaml).unwrap();
+
+        assert_eq!(loaded.watches[0].frozen, Some(vec![9]));
+        assert_eq!(loaded.watches[0].value, None);
+        assert_eq!(loaded.watches[0].location, list.watches[0].location);
+    }
+}
@@ -2,7 +2,7 @@ use egui::{
     Align2, FontId, Painter, Pos2, Rect, Stroke, Ui, Vec2,
 };
 
-use crate::app::SCREEN_WIDTH;
+use crate::render::SCREEN_WIDTH;
 use crate::overlay::{Color, DrawCmd, Text, Line, Point, Rect as OverlayRect, TextAlign};
 
 /// Draws console-space overlay commands on top of one screen.
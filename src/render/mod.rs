@@ -2,13 +2,29 @@ mod draw;
 
 pub use draw::draw_screen;
 
+use std::sync::{Arc, OnceLock};
+
+use egui::Context;
+
 use crate::overlay::Overlay;
+use crate::speed::Speed;
+use crate::EmuState;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 192;
+
+/// Handle the emulator uses to wake the UI once a frame is ready.
+///
+/// eframe creates the [`Context`], so the emulator task cannot be handed one
+/// when it is spawned; it takes this and waits for the first repaint to fill it.
+pub type RepaintHandle = Arc<OnceLock<Context>>;
 
 /// Emulation status forwarded to the UI for overlay hooks.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RenderStatus {
     pub frame: u64,
-    pub paused: bool,
+    pub state: EmuState,
+    pub speed: Speed,
 }
 
 /// Where a screen was drawn in window coordinates.
@@ -25,6 +41,7 @@ pub struct ScreenRect {
 pub struct RenderContext {
     pub frame: u64,
     pub paused: bool,
+    pub speed: Speed,
     /// Where the top screen was drawn in window coordinates.
     pub top_screen: ScreenRect,
     /// Where the bottom screen was drawn in window coordinates.
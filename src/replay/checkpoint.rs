@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Replay;
+use crate::frontend::Frames;
+
+/// Hashes of the console's state as a frame finished, taken while recording
+/// and checked again on playback. The first one to differ is where the replay
+/// desynced, which is usually long before it visibly goes wrong.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The frame count once the frame had run.
+    pub frame: u64,
+    /// The CRC32 of main RAM.
+    pub main_ram: u32,
+    /// The CRC32 of both screens, top first.
+    pub framebuffer: u32,
+}
+
+impl Checkpoint {
+    pub fn new(frame: u64, main_ram: &[u8], frames: &Frames) -> Self {
+        let mut framebuffer = crc32fast::Hasher::new();
+        framebuffer.update(&frames.top);
+        framebuffer.update(&frames.bottom);
+
+        Checkpoint {
+            frame,
+            main_ram: crc32fast::hash(main_ram),
+            framebuffer: framebuffer.finalize(),
+        }
+    }
+
+    /// How `actual` differs from this checkpoint, if it does at all.
+    pub fn compare(&self, actual: &Checkpoint) -> Option<Desync> {
+        let desync = Desync {
+            frame: self.frame,
+            main_ram: self.main_ram != actual.main_ram,
+            framebuffer: self.framebuffer != actual.framebuffer,
+        };
+
+        (desync.main_ram || desync.framebuffer).then_some(desync)
+    }
+}
+
+/// The first checkpoint a replay failed to reproduce.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Desync {
+    pub frame: u64,
+    pub main_ram: bool,
+    pub framebuffer: bool,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match (self.main_ram, self.framebuffer) {
+            (true, true) => "main RAM and the screens differ",
+            (true, false) => "main RAM differs",
+            _ => "the screens differ",
+        };
+        write!(f, "the replay desynced by frame {}: {what}", self.frame)
+    }
+}
+
+/// How often checkpoints are taken while recording, and what playback does
+/// when one fails.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointSettings {
+    /// Frames between checkpoints. Zero takes none.
+    pub interval: u32,
+    pub pause_on_desync: bool,
+}
+
+impl Default for CheckpointSettings {
+    fn default() -> Self {
+        CheckpointSettings {
+            interval: 60,
+            pause_on_desync: false,
+        }
+    }
+}
+
+impl CheckpointSettings {
+    pub fn is_due(&self, frame: u64) -> bool {
+        self.interval != 0 && frame.is_multiple_of(self.interval as u64)
+    }
+}
+
+impl Replay {
+    pub fn checkpoint(&self, frame: u64) -> Option<&Checkpoint> {
+        self.checkpoints
+            .binary_search_by_key(&frame, |checkpoint| checkpoint.frame)
+            .ok()
+            .map(|index| &self.checkpoints[index])
+    }
+
+    /// Keeps `checkpoint`, discarding any taken at or after its frame, which
+    /// belonged to inputs that have since been recorded over.
+    pub fn record_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.discard_checkpoints_from(checkpoint.frame);
+        self.checkpoints.push(checkpoint);
+    }
+
+    pub fn discard_checkpoints_from(&mut self, frame: u64) {
+        let kept = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.frame < frame);
+        self.checkpoints.truncate(kept);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::replay::ReplaySource;
+
+    fn replay() -> Replay {
+        Replay {
+            name: PathBuf::from("test.yml"),
+            author: String::new(),
+            source: ReplaySource::None {
+                timestamp: Utc::now(),
+            },
+            identity: None,
+            checkpoints: vec![],
+            inputs: vec![],
+        }
+    }
+
+    fn checkpoint(frame: u64, ram: u8) -> Checkpoint {
+        Checkpoint::new(frame, &[ram; 16], &Frames::blank())
+    }
+
+    #[test]
+    fn the_same_state_matches() {
+        assert_eq!(checkpoint(60, 1).compare(&checkpoint(60, 1)), None);
+    }
+
+    #[test]
+    fn a_difference_says_what_differs() {
+        let mut frames = Frames::blank();
+        frames.bottom[0] = 1;
+        let actual = Checkpoint::new(60, &[1; 16], &frames);
+
+        assert_eq!(
+            checkpoint(60, 2).compare(&actual),
+            Some(Desync {
+                frame: 60,
+                main_ram: true,
+                framebuffer: true,
+            })
+        );
+    }
+
+    #[test]
+    fn recording_over_a_frame_discards_later_checkpoints() {
+        let mut replay = replay();
+        for frame in [60, 120, 180] {
+            replay.record_checkpoint(checkpoint(frame, 0));
+        }
+
+        replay.record_checkpoint(checkpoint(120, 1));
+
+        assert_eq!(
+            replay.checkpoints,
+            vec![checkpoint(60, 0), checkpoint(120, 1)]
+        );
+        assert_eq!(replay.checkpoint(120), Some(&checkpoint(120, 1)));
+        assert_eq!(replay.checkpoint(180), None);
+    }
+
+    #[test]
+    fn a_zero_interval_takes_no_checkpoints() {
+        let settings = CheckpointSettings {
+            interval: 0,
+            pause_on_desync: false,
+        };
+
+        assert!(!settings.is_due(0));
+        assert!(!settings.is_due(60));
+    }
+}
@@ -0,0 +1,802 @@
+//! The on-disk replay format.
+//!
+//! A replay file is a magic number and a format version followed by a series of
+//! chunks, each a four-byte tag, a length, and a payload. Readers skip chunks
+//! they do not recognise, so new kinds of data can be added without breaking
+//! older files or older readers.
+//!
+//! Inputs are run-length encoded: a held input usually stays the same for many
+//! frames in a row, and one run stands in for all of them.
+
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{DateTime, Utc};
+
+use super::{Checkpoint, Replay, ReplayIdentity, ReplaySource, SavestateSource};
+use crate::input::{
+    BoundaryIndex, BoundaryInput, ButtonMask, ConsoleInputState, SystemAction, TouchPoint,
+};
+
+const MAGIC: &[u8; 4] = b"MLRP";
+
+/// The newest format this build writes. Every older version it still reads is
+/// handled in [`Replay::from_bytes`].
+pub const FORMAT_VERSION: u16 = 1;
+
+const HEADER: [u8; 4] = *b"HEAD";
+const INPUTS: [u8; 4] = *b"INPT";
+/// Optional, since replays converted from YAML may not know what they were
+/// recorded against.
+const IDENTITY: [u8; 4] = *b"IDNT";
+const CHECKPOINTS: [u8; 4] = *b"CHKP";
+
+/// Why a replay could not be read.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The file is not a binary replay, and did not parse as a YAML one either.
+    Yaml(serde_yaml::Error),
+    /// The file was written by a newer version of the format.
+    UnsupportedVersion(u16),
+    Malformed(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{err}"),
+            ReplayError::Yaml(err) => write!(f, "not a replay file: {err}"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "the replay uses format version {version}, but only versions up to \
+                {FORMAT_VERSION} are supported"
+            ),
+            ReplayError::Malformed(reason) => write!(f, "the replay is malformed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(value: io::Error) -> Self {
+        ReplayError::Io(value)
+    }
+}
+
+impl From<serde_yaml::Error> for ReplayError {
+    fn from(value: serde_yaml::Error) -> Self {
+        ReplayError::Yaml(value)
+    }
+}
+
+impl Replay {
+    /// Reads a replay from disk, in either the binary format or the YAML one
+    /// that preceded it.
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        Replay::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        if !bytes.starts_with(MAGIC) {
+            let replay: Replay = serde_yaml::from_slice(bytes)?;
+            check_boundaries(&replay)?;
+            return Ok(replay);
+        }
+
+        let mut reader = Cursor::new(&bytes[MAGIC.len()..]);
+        let version = reader.read_u16::<LittleEndian>()?;
+        if version > FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let mut header = None;
+        let mut identity = None;
+        let mut checkpoints = vec![];
+        let mut inputs = None;
+
+        while (reader.position() as usize) < reader.get_ref().len() {
+            let mut tag = [0; 4];
+            reader.read_exact(&mut tag)?;
+            let len = reader.read_u32::<LittleEndian>()? as usize;
+
+            let start = reader.position() as usize;
+            let payload =
+                reader
+                    .get_ref()
+                    .get(start..start + len)
+                    .ok_or(ReplayError::Malformed(
+                        "a chunk runs past the end of the file",
+                    ))?;
+            reader.set_position((start + len) as u64);
+
+            let mut payload = Cursor::new(payload);
+            match tag {
+                HEADER => header = Some(read_header(&mut payload)?),
+                IDENTITY => identity = Some(read_identity(&mut payload)?),
+                CHECKPOINTS => checkpoints = read_checkpoints(&mut payload)?,
+                INPUTS => inputs = Some(read_inputs(&mut payload)?),
+                _ => {}
+            }
+        }
+
+        let (name, author, source) = header.ok_or(ReplayError::Malformed("there is no header"))?;
+        let inputs = inputs.ok_or(ReplayError::Malformed("there are no inputs"))?;
+
+        let replay = Replay {
+            name,
+            author,
+            source,
+            identity,
+            checkpoints,
+            inputs,
+        };
+        check_boundaries(&replay)?;
+
+        Ok(replay)
+    }
+
+    /// The replay in the newest binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.write_u16::<LittleEndian>(FORMAT_VERSION).unwrap();
+
+        let mut header = Vec::new();
+        write_path(&mut header, &self.name);
+        write_str(&mut header, &self.author);
+        write_source(&mut header, &self.source);
+        write_chunk(&mut out, HEADER, &header);
+
+        if let Some(identity) = &self.identity {
+            let mut chunk = Vec::new();
+            write_identity(&mut chunk, identity);
+            write_chunk(&mut out, IDENTITY, &chunk);
+        }
+
+        if !self.checkpoints.is_empty() {
+            let mut chunk = Vec::new();
+            write_checkpoints(&mut chunk, &self.checkpoints);
+            write_chunk(&mut out, CHECKPOINTS, &chunk);
+        }
+
+        let mut inputs = Vec::new();
+        write_inputs(&mut inputs, &self.inputs);
+        write_chunk(&mut out, INPUTS, &inputs);
+
+        out
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, tag: [u8; 4], payload: &[u8]) {
+    out.extend_from_slice(&tag);
+    out.write_u32::<LittleEndian>(payload.len() as u32).unwrap();
+    out.extend_from_slice(payload);
+}
+
+fn read_header(reader: &mut impl Read) -> Result<(PathBuf, String, ReplaySource), ReplayError> {
+    let name = PathBuf::from(read_str(reader)?);
+    let author = read_str(reader)?;
+    let source = read_source(reader)?;
+
+    Ok((name, author, source))
+}
+
+fn write_identity(out: &mut Vec<u8>, identity: &ReplayIdentity) {
+    write_str(out, &identity.game_code);
+    out.write_u16::<LittleEndian>(identity.header_crc).unwrap();
+    out.write_u32::<LittleEndian>(identity.rom_hash).unwrap();
+    match identity.save_hash {
+        Some(hash) => {
+            out.push(1);
+            out.write_u32::<LittleEndian>(hash).unwrap();
+        }
+        None => out.push(0),
+    }
+    write_str(out, &identity.core_version);
+}
+
+fn read_identity(reader: &mut impl Read) -> Result<ReplayIdentity, ReplayError> {
+    Ok(ReplayIdentity {
+        game_code: read_str(reader)?,
+        header_crc: reader.read_u16::<LittleEndian>()?,
+        rom_hash: reader.read_u32::<LittleEndian>()?,
+        save_hash: match reader.read_u8()? {
+            0 => None,
+            _ => Some(reader.read_u32::<LittleEndian>()?),
+        },
+        core_version: read_str(reader)?,
+    })
+}
+
+/// Frames are stored as the distance from the previous checkpoint, which is
+/// almost always the same small number.
+fn write_checkpoints(out: &mut Vec<u8>, checkpoints: &[Checkpoint]) {
+    write_varint(out, checkpoints.len() as u64);
+
+    let mut previous = 0;
+    for checkpoint in checkpoints {
+        write_varint(out, checkpoint.frame - previous);
+        out.write_u32::<LittleEndian>(checkpoint.main_ram).unwrap();
+        out.write_u32::<LittleEndian>(checkpoint.framebuffer)
+            .unwrap();
+        previous = checkpoint.frame;
+    }
+}
+
+fn read_checkpoints(reader: &mut impl Read) -> Result<Vec<Checkpoint>, ReplayError> {
+    let count = read_varint(reader)?;
+
+    let mut frame = 0u64;
+    (0..count)
+        .map(|_| {
+            frame = frame
+                .checked_add(read_varint(reader)?)
+                .ok_or(ReplayError::Malformed("a checkpoint is out of range"))?;
+            Ok(Checkpoint {
+                frame,
+                main_ram: reader.read_u32::<LittleEndian>()?,
+                framebuffer: reader.read_u32::<LittleEndian>()?,
+            })
+        })
+        .collect()
+}
+
+mod source {
+    pub const SAVE_FILE: u8 = 0;
+    pub const SAVESTATE: u8 = 1;
+    pub const NONE: u8 = 2;
+
+    pub const SAVESTATE_FILE: u8 = 0;
+    pub const SAVESTATE_EMBEDDED: u8 = 1;
+}
+
+fn write_source(out: &mut Vec<u8>, source: &ReplaySource) {
+    match source {
+        ReplaySource::SaveFile { path, timestamp } => {
+            out.push(source::SAVE_FILE);
+            match path {
+                Some(path) => {
+                    out.push(1);
+                    write_path(out, path);
+                }
+                None => out.push(0),
+            }
+            write_timestamp(out, timestamp);
+        }
+        ReplaySource::Savestate { state, start_frame } => {
+            out.push(source::SAVESTATE);
+            match state {
+                SavestateSource::File(path) => {
+                    out.push(source::SAVESTATE_FILE);
+                    write_path(out, path);
+                }
+                SavestateSource::Embedded(contents) => {
+                    out.push(source::SAVESTATE_EMBEDDED);
+                    write_bytes(out, contents);
+                }
+            }
+            out.write_u32::<LittleEndian>(*start_frame).unwrap();
+        }
+        ReplaySource::None { timestamp } => {
+            out.push(source::NONE);
+            write_timestamp(out, timestamp);
+        }
+    }
+}
+
+fn read_source(reader: &mut impl Read) -> Result<ReplaySource, ReplayError> {
+    Ok(match reader.read_u8()? {
+        source::SAVE_FILE => {
+            let path = match reader.read_u8()? {
+                0 => None,
+                _ => Some(PathBuf::from(read_str(reader)?)),
+            };
+            ReplaySource::SaveFile {
+                path,
+                timestamp: read_timestamp(reader)?,
+            }
+        }
+        source::SAVESTATE => {
+            let state = match reader.read_u8()? {
+                source::SAVESTATE_FILE => SavestateSource::File(PathBuf::from(read_str(reader)?)),
+                source::SAVESTATE_EMBEDDED => SavestateSource::Embedded(read_bytes(reader)?),
+                _ => return Err(ReplayError::Malformed("unknown savestate source")),
+            };
+            ReplaySource::Savestate {
+                state,
+                start_frame: reader.read_u32::<LittleEndian>()?,
+            }
+        }
+        source::NONE => ReplaySource::None {
+            timestamp: read_timestamp(reader)?,
+        },
+        _ => return Err(ReplayError::Malformed("unknown replay source")),
+    })
+}
+
+/// Inputs are looked up by their position, so a replay must have one for every
+/// boundary from its start frame on, with none missing.
+fn check_boundaries(replay: &Replay) -> Result<(), ReplayError> {
+    let start = replay.source.start_frame() as u64;
+    let in_place = replay
+        .inputs
+        .iter()
+        .zip(start..)
+        .all(|(input, boundary)| input.boundary.0 == boundary);
+
+    match in_place {
+        true => Ok(()),
+        false => Err(ReplayError::Malformed(
+            "the inputs skip a boundary, or begin before or after the start frame",
+        )),
+    }
+}
+
+mod record {
+    /// Consecutive boundaries sharing one held state and no actions.
+    pub const RUN: u8 = 0;
+    /// A single boundary that carries actions.
+    pub const ACTIONS: u8 = 1;
+    /// The next boundary does not follow on from the previous one.
+    pub const SEEK: u8 = 2;
+}
+
+fn write_inputs(out: &mut Vec<u8>, inputs: &[BoundaryInput]) {
+    let first = inputs.first().map(|input| input.boundary.0).unwrap_or(0);
+    write_varint(out, first);
+
+    let mut next = first;
+    let mut index = 0;
+    while let Some(input) = inputs.get(index) {
+        if input.boundary.0 != next {
+            out.push(record::SEEK);
+            write_varint(out, input.boundary.0);
+        }
+
+        if input.actions.is_empty() {
+            let run = inputs[index..]
+                .iter()
+                .zip(input.boundary.0..)
+                .take_while(|(other, boundary)| {
+                    other.boundary.0 == *boundary
+                        && other.actions.is_empty()
+                        && other.state == input.state
+                })
+                .count();
+
+            out.push(record::RUN);
+            write_varint(out, run as u64);
+            write_state(out, &input.state);
+
+            index += run;
+            next = input.boundary.0 + run as u64;
+        } else {
+            out.push(record::ACTIONS);
+            write_state(out, &input.state);
+            write_varint(out, input.actions.len() as u64);
+            out.extend(input.actions.iter().map(|action| action_code(*action)));
+
+            index += 1;
+            next = input.boundary.0 + 1;
+        }
+    }
+}
+
+/// More boundaries than any replay holds: a day of frames. A run is a handful
+/// of bytes however long it says it is, so without a limit a few bytes could
+/// ask for any amount of memory.
+const MAX_INPUTS: u64 = 24 * 60 * 60 * 60;
+
+fn read_inputs(reader: &mut Cursor<&[u8]>) -> Result<Vec<BoundaryInput>, ReplayError> {
+    let mut inputs = Vec::new();
+    let mut next = read_varint(reader)?;
+
+    while (reader.position() as usize) < reader.get_ref().len() {
+        match reader.read_u8()? {
+            record::RUN => {
+                let run = read_varint(reader)?;
+                let state = read_state(reader)?;
+
+                if run > MAX_INPUTS - inputs.len() as u64 {
+                    return Err(ReplayError::Malformed("there are too many inputs"));
+                }
+                let end = next
+                    .checked_add(run)
+                    .ok_or(ReplayError::Malformed("a boundary is out of range"))?;
+
+                inputs.extend((next..end).map(|boundary| BoundaryInput {
+                    boundary: BoundaryIndex(boundary),
+                    state,
+                    actions: vec![],
+                }));
+                next = end;
+            }
+            record::ACTIONS => {
+                let state = read_state(reader)?;
+                let count = read_varint(reader)?;
+                let actions = (0..count)
+                    .map(|_| action_from_code(reader.read_u8()?))
+                    .collect::<Result<_, ReplayError>>()?;
+
+                if inputs.len() as u64 >= MAX_INPUTS {
+                    return Err(ReplayError::Malformed("there are too many inputs"));
+                }
+                inputs.push(BoundaryInput {
+                    boundary: BoundaryIndex(next),
+                    state,
+                    actions,
+                });
+                next = next
+                    .checked_add(1)
+                    .ok_or(ReplayError::Malformed("a boundary is out of range"))?;
+            }
+            record::SEEK => next = read_varint(reader)?,
+            _ => return Err(ReplayError::Malformed("unknown input record")),
+        }
+    }
+
+    Ok(inputs)
+}
+
+mod state_flags {
+    pub const TOUCH: u8 = 1 << 0;
+    pub const LID_CLOSED: u8 = 1 << 1;
+}
+
+fn write_state(out: &mut Vec<u8>, state: &ConsoleInputState) {
+    out.write_u16::<LittleEndian>(state.buttons.bits()).unwrap();
+
+    let mut flags = 0;
+    if state.touch.is_some() {
+        flags |= state_flags::TOUCH;
+    }
+    if state.lid_closed {
+        flags |= state_flags::LID_CLOSED;
+    }
+    out.push(flags);
+
+    if let Some(point) = state.touch {
+        out.extend_from_slice(&[point.x, point.y]);
+    }
+}
+
+fn read_state(reader: &mut impl Read) -> Result<ConsoleInputState, ReplayError> {
+    let buttons = ButtonMask::from_bits(reader.read_u16::<LittleEndian>()?)
+        .ok_or(ReplayError::Malformed("unknown buttons are held"))?;
+    let flags = reader.read_u8()?;
+
+    let touch = if flags & state_flags::TOUCH != 0 {
+        let x = reader.read_u8()?;
+        let y = reader.read_u8()?;
+        Some(TouchPoint::new(x, y).ok_or(ReplayError::Malformed("a touch is off the screen"))?)
+    } else {
+        None
+    };
+
+    Ok(ConsoleInputState {
+        buttons,
+        touch,
+        lid_closed: flags & state_flags::LID_CLOSED != 0,
+    })
+}
+
+fn action_code(action: SystemAction) -> u8 {
+    match action {
+        SystemAction::Reset => 0,
+        SystemAction::PowerCycle => 1,
+        SystemAction::InsertCartridge => 2,
+        SystemAction::EjectCartridge => 3,
+    }
+}
+
+fn action_from_code(code: u8) -> Result<SystemAction, ReplayError> {
+    Ok(match code {
+        0 => SystemAction::Reset,
+        1 => SystemAction::PowerCycle,
+        2 => SystemAction::InsertCartridge,
+        3 => SystemAction::EjectCartridge,
+        _ => return Err(ReplayError::Malformed("unknown system action")),
+    })
+}
+
+fn write_timestamp(out: &mut Vec<u8>, timestamp: &DateTime<Utc>) {
+    out.write_i64::<LittleEndian>(timestamp.timestamp())
+        .unwrap();
+    out.write_u32::<LittleEndian>(timestamp.timestamp_subsec_nanos())
+        .unwrap();
+}
+
+fn read_timestamp(reader: &mut impl Read) -> Result<DateTime<Utc>, ReplayError> {
+    let seconds = reader.read_i64::<LittleEndian>()?;
+    let nanos = reader.read_u32::<LittleEndian>()?;
+
+    DateTime::from_timestamp(seconds, nanos)
+        .ok_or(ReplayError::Malformed("the timestamp is out of range"))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, ReplayError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8()?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(ReplayError::Malformed("a number is too long"))
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.write_all(bytes).unwrap();
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, ReplayError> {
+    let len = read_varint(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(bytes)
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_bytes(out, value.as_bytes());
+}
+
+fn read_str(reader: &mut impl Read) -> Result<String, ReplayError> {
+    String::from_utf8(read_bytes(reader)?)
+        .map_err(|_| ReplayError::Malformed("a string is not UTF-8"))
+}
+
+/// Paths are stored as UTF-8, which is lossy only for paths no replay should
+/// be using anyway.
+fn write_path(out: &mut Vec<u8>, path: &Path) {
+    write_str(out, &path.to_string_lossy());
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn input(boundary: u64, buttons: ButtonMask) -> BoundaryInput {
+        BoundaryInput {
+            boundary: BoundaryIndex(boundary),
+            state: ConsoleInputState {
+                buttons,
+                ..Default::default()
+            },
+            actions: vec![],
+        }
+    }
+
+    fn replay(source: ReplaySource, inputs: Vec<BoundaryInput>) -> Replay {
+        Replay {
+            name: PathBuf::from("run.rpl"),
+            author: String::from("someone"),
+            source,
+            identity: None,
+            checkpoints: vec![],
+            inputs,
+        }
+    }
+
+    fn save_file() -> ReplaySource {
+        ReplaySource::SaveFile {
+            path: Some(PathBuf::from("save.bin")),
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+        }
+    }
+
+    fn round_trip(replay: &Replay) -> Replay {
+        Replay::from_bytes(&replay.to_bytes()).unwrap()
+    }
+
+    #[test]
+    fn every_source_survives_a_round_trip() {
+        let timestamp = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+        let sources = [
+            save_file(),
+            ReplaySource::SaveFile {
+                path: None,
+                timestamp,
+            },
+            ReplaySource::Savestate {
+                state: SavestateSource::File(PathBuf::from("start.state")),
+                start_frame: 42,
+            },
+            ReplaySource::Savestate {
+                state: SavestateSource::Embedded(vec![0, 1, 2, 255]),
+                start_frame: 42,
+            },
+            ReplaySource::None { timestamp },
+        ];
+
+        for source in sources {
+            let first = source.start_frame() as u64;
+            let replay = replay(source, vec![input(first, ButtonMask::A)]);
+            assert_eq!(round_trip(&replay), replay);
+        }
+    }
+
+    #[test]
+    fn the_identity_survives_a_round_trip() {
+        for save in [None, Some(&[1, 2, 3][..])] {
+            let mut replay = replay(save_file(), vec![input(0, ButtonMask::A)]);
+            replay.identity = Some(ReplayIdentity::new(&[0; 0x200], save));
+
+            assert_eq!(round_trip(&replay), replay);
+        }
+    }
+
+    #[test]
+    fn checkpoints_survive_a_round_trip() {
+        let mut replay = replay(save_file(), vec![input(0, ButtonMask::A)]);
+        replay.checkpoints = vec![
+            Checkpoint {
+                frame: 60,
+                main_ram: 0xDEADBEEF,
+                framebuffer: 1,
+            },
+            Checkpoint {
+                frame: 120,
+                main_ram: 2,
+                framebuffer: 0xFFFFFFFF,
+            },
+        ];
+
+        assert_eq!(round_trip(&replay), replay);
+    }
+
+    #[test]
+    fn every_kind_of_input_survives_a_round_trip() {
+        let mut touched = input(3, ButtonMask::empty());
+        touched.state.touch = TouchPoint::new(255, 191);
+        let mut closed = input(4, ButtonMask::empty());
+        closed.state.lid_closed = true;
+        let mut reset = input(5, ButtonMask::START);
+        reset.actions = vec![SystemAction::Reset, SystemAction::EjectCartridge];
+
+        let replay = replay(
+            save_file(),
+            vec![
+                input(0, ButtonMask::A),
+                input(1, ButtonMask::A),
+                input(2, ButtonMask::A | ButtonMask::Y),
+                touched,
+                closed,
+                reset,
+                input(6, ButtonMask::START),
+            ],
+        );
+
+        assert_eq!(round_trip(&replay), replay);
+    }
+
+    #[test]
+    fn gaps_between_boundaries_are_refused() {
+        let gap = replay(
+            save_file(),
+            vec![
+                input(0, ButtonMask::A),
+                input(1, ButtonMask::A),
+                input(10, ButtonMask::A),
+            ],
+        );
+        let late = replay(save_file(), vec![input(10, ButtonMask::A)]);
+
+        for replay in [gap, late] {
+            assert!(matches!(
+                Replay::from_bytes(&replay.to_bytes()),
+                Err(ReplayError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn an_empty_replay_survives_a_round_trip() {
+        let replay = replay(save_file(), vec![]);
+
+        assert_eq!(round_trip(&replay), replay);
+    }
+
+    #[test]
+    fn a_held_input_costs_one_run_however_long_it_lasts() {
+        let short = replay(
+            save_file(),
+            (0..10).map(|i| input(i, ButtonMask::B)).collect(),
+        );
+        let long = replay(
+            save_file(),
+            (0..216_000).map(|i| input(i, ButtonMask::B)).collect(),
+        );
+
+        assert!(long.to_bytes().len() <= short.to_bytes().len() + 2);
+    }
+
+    #[test]
+    fn yaml_replays_still_load() {
+        let replay = replay(save_file(), vec![input(0, ButtonMask::A)]);
+        let yaml = serde_yaml::to_string(&replay).unwrap();
+
+        assert_eq!(Replay::from_bytes(yaml.as_bytes()).unwrap(), replay);
+    }
+
+    #[test]
+    fn unknown_chunks_are_skipped() {
+        let replay = replay(save_file(), vec![input(0, ButtonMask::A)]);
+        let mut bytes = replay.to_bytes();
+        write_chunk(&mut bytes, *b"NEW!", &[1, 2, 3]);
+
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    }
+
+    #[test]
+    fn a_newer_version_is_refused() {
+        let mut bytes = replay(save_file(), vec![]).to_bytes();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn a_truncated_replay_is_an_error_rather_than_a_panic() {
+        let bytes = replay(save_file(), vec![input(0, ButtonMask::A)]).to_bytes();
+
+        for len in MAGIC.len()..bytes.len() {
+            assert!(Replay::from_bytes(&bytes[..len]).is_err(), "at {len} bytes");
+        }
+    }
+
+    #[test]
+    fn runs_past_the_limit_are_an_error_rather_than_a_panic() {
+        for (first, run) in [(0, u64::MAX), (0, MAX_INPUTS + 1), (u64::MAX - 1, 2)] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, first);
+            bytes.push(record::RUN);
+            write_varint(&mut bytes, run);
+            write_state(&mut bytes, &ConsoleInputState::default());
+
+            assert!(
+                matches!(
+                    read_inputs(&mut Cursor::new(&bytes[..])),
+                    Err(ReplayError::Malformed(_))
+                ),
+                "a run of {run} from {first}"
+            );
+        }
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+
+            assert_eq!(read_varint(&mut Cursor::new(&out)).unwrap(), value);
+        }
+    }
+}
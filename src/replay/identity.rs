@@ -0,0 +1,231 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::melon::CORE_VERSION;
+
+/// What a replay was recorded against. A replay played back against a
+/// different dump, a different save, or a different build of the core will
+/// usually desync without any other sign that something is wrong.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ReplayIdentity {
+    /// The four-character code in the cartridge header, such as `ADAE`.
+    pub game_code: String,
+    /// The CRC16 of the cartridge header, as stored in the header itself.
+    pub header_crc: u16,
+    /// The CRC32 of the whole ROM file.
+    pub rom_hash: u32,
+    /// The CRC32 of the save file the replay starts from, if it starts from one.
+    pub save_hash: Option<u32>,
+    /// The version of melonDS the replay was recorded with.
+    pub core_version: String,
+}
+
+impl ReplayIdentity {
+    const GAME_CODE: std::ops::Range<usize> = 0x0C..0x10;
+    const HEADER_CRC: usize = 0x15E;
+
+    pub fn new(rom: &[u8], save: Option<&[u8]>) -> Self {
+        let game_code = rom
+            .get(Self::GAME_CODE)
+            .map(|code| String::from_utf8_lossy(code).into_owned())
+            .unwrap_or_default();
+        let header_crc = rom
+            .get(Self::HEADER_CRC..Self::HEADER_CRC + 2)
+            .map(|crc| u16::from_le_bytes([crc[0], crc[1]]))
+            .unwrap_or_default();
+
+        ReplayIdentity {
+            game_code,
+            header_crc,
+            rom_hash: crc32fast::hash(rom),
+            save_hash: save.map(crc32fast::hash),
+            core_version: CORE_VERSION.to_owned(),
+        }
+    }
+
+    /// Everything about `actual` that differs from what the replay expects.
+    pub fn mismatches(&self, actual: &ReplayIdentity) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+
+        if self.game_code != actual.game_code {
+            mismatches.push(Mismatch::GameCode {
+                expected: self.game_code.clone(),
+                actual: actual.game_code.clone(),
+            });
+        }
+        // A different game will have a different hash too, which is not worth
+        // saying twice.
+        else if self.header_crc != actual.header_crc || self.rom_hash != actual.rom_hash {
+            mismatches.push(Mismatch::Rom {
+                expected: self.rom_hash,
+                actual: actual.rom_hash,
+            });
+        }
+        if self.save_hash != actual.save_hash {
+            mismatches.push(Mismatch::Save {
+                expected: self.save_hash,
+                actual: actual.save_hash,
+            });
+        }
+        if self.core_version != actual.core_version {
+            mismatches.push(Mismatch::CoreVersion {
+                expected: self.core_version.clone(),
+                actual: actual.core_version.clone(),
+            });
+        }
+
+        mismatches
+    }
+}
+
+/// One way in which the loaded cart, save, or core differ from a replay's.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mismatch {
+    GameCode {
+        expected: String,
+        actual: String,
+    },
+    /// The same game, but a different dump or revision of it.
+    Rom {
+        expected: u32,
+        actual: u32,
+    },
+    Save {
+        expected: Option<u32>,
+        actual: Option<u32>,
+    },
+    CoreVersion {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Mismatch {
+    /// Whether playback is all but certain to desync. A different core
+    /// version often plays a replay back just fine, so it only merits a
+    /// warning.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, Mismatch::CoreVersion { .. })
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::GameCode { expected, actual } => write!(
+                f,
+                "the replay was recorded on game {expected}, but game {actual} is loaded"
+            ),
+            Mismatch::Rom { expected, actual } => write!(
+                f,
+                "the replay was recorded on a ROM with CRC32 {expected:08X}, but the loaded \
+                ROM's is {actual:08X}"
+            ),
+            Mismatch::Save { expected, actual } => {
+                let describe = |hash: &Option<u32>| match hash {
+                    Some(hash) => format!("a save with CRC32 {hash:08X}"),
+                    None => String::from("no save"),
+                };
+                write!(
+                    f,
+                    "the replay was recorded with {}, but {} is loaded",
+                    describe(expected),
+                    describe(actual)
+                )
+            }
+            Mismatch::CoreVersion { expected, actual } => write!(
+                f,
+                "the replay was recorded with melonDS {expected}, but this is melonDS {actual}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(game_code: &[u8; 4], crc: u16, fill: u8) -> Vec<u8> {
+        let mut rom = vec![fill; 0x200];
+        rom[0x0C..0x10].copy_from_slice(game_code);
+        rom[0x15E..0x160].copy_from_slice(&crc.to_le_bytes());
+        rom
+    }
+
+    #[test]
+    fn the_header_fields_are_read_from_the_rom() {
+        let identity = ReplayIdentity::new(&rom(b"ADAE", 0xBEEF, 0), None);
+
+        assert_eq!(identity.game_code, "ADAE");
+        assert_eq!(identity.header_crc, 0xBEEF);
+        assert_eq!(identity.save_hash, None);
+        assert_eq!(identity.core_version, CORE_VERSION);
+    }
+
+    #[test]
+    fn a_short_rom_does_not_panic() {
+        let identity = ReplayIdentity::new(&[1, 2, 3], None);
+
+        assert_eq!(identity.game_code, "");
+        assert_eq!(identity.header_crc, 0);
+    }
+
+    #[test]
+    fn the_same_inputs_match() {
+        let rom = rom(b"ADAE", 0xBEEF, 0);
+        let expected = ReplayIdentity::new(&rom, Some(&[1, 2, 3]));
+        let actual = ReplayIdentity::new(&rom, Some(&[1, 2, 3]));
+
+        assert_eq!(expected.mismatches(&actual), vec![]);
+    }
+
+    #[test]
+    fn a_different_game_is_reported_once() {
+        let expected = ReplayIdentity::new(&rom(b"ADAE", 0xBEEF, 0), None);
+        let actual = ReplayIdentity::new(&rom(b"APAE", 0x1234, 0), None);
+
+        assert_eq!(
+            expected.mismatches(&actual),
+            vec![Mismatch::GameCode {
+                expected: String::from("ADAE"),
+                actual: String::from("APAE"),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_different_dump_of_the_same_game_is_fatal() {
+        let expected = ReplayIdentity::new(&rom(b"ADAE", 0xBEEF, 0), None);
+        let actual = ReplayIdentity::new(&rom(b"ADAE", 0xBEEF, 1), None);
+
+        let mismatches = expected.mismatches(&actual);
+        assert!(matches!(mismatches[..], [Mismatch::Rom { .. }]));
+        assert!(mismatches[0].is_fatal());
+    }
+
+    #[test]
+    fn a_missing_save_is_fatal() {
+        let rom = rom(b"ADAE", 0xBEEF, 0);
+        let expected = ReplayIdentity::new(&rom, Some(&[1, 2, 3]));
+        let actual = ReplayIdentity::new(&rom, None);
+
+        let mismatches = expected.mismatches(&actual);
+        assert!(matches!(
+            mismatches[..],
+            [Mismatch::Save { actual: None, .. }]
+        ));
+        assert!(mismatches[0].is_fatal());
+    }
+
+    #[test]
+    fn a_different_core_is_only_a_warning() {
+        let rom = rom(b"ADAE", 0xBEEF, 0);
+        let mut expected = ReplayIdentity::new(&rom, None);
+        expected.core_version = String::from("0.9.5");
+
+        let mismatches = expected.mismatches(&ReplayIdentity::new(&rom, None));
+        assert!(matches!(mismatches[..], [Mismatch::CoreVersion { .. }]));
+        assert!(!mismatches[0].is_fatal());
+    }
+}
@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::input::BoundaryInput;
+
+mod checkpoint;
+mod file;
+mod identity;
+
+pub use checkpoint::{Checkpoint, CheckpointSettings, Desync};
+pub use file::{ReplayError, FORMAT_VERSION};
+pub use identity::{Mismatch, ReplayIdentity};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub name: PathBuf,
+    pub author: String,
+    pub source: ReplaySource,
+    /// The cart, save, and core the replay was recorded against. Replays from
+    /// before this was kept have none, and are played back unchecked.
+    #[serde(default)]
+    pub identity: Option<ReplayIdentity>,
+    /// Hashes taken every so often while recording, in frame order.
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+    /// One sampled input per boundary, indexed by boundary order and starting
+    /// at the source's [`ReplaySource::start_frame`].
+    pub inputs: Vec<BoundaryInput>,
+}
+
+impl Replay {
+    /// Where `boundary`'s input sits in [`Replay::inputs`], if the replay
+    /// started early enough to have one.
+    pub fn index_of(&self, boundary: u64) -> Option<usize> {
+        boundary
+            .checked_sub(self.source.start_frame() as u64)
+            .map(|index| index as usize)
+    }
+
+    pub fn input(&self, boundary: u64) -> Option<&BoundaryInput> {
+        self.inputs.get(self.index_of(boundary)?)
+    }
+
+    /// The first boundary the replay has no input for.
+    pub fn end(&self) -> u64 {
+        self.source.start_frame() as u64 + self.inputs.len() as u64
+    }
+}
+
+/// Replays could realistically be played back in 3 ways:
+/// from the emulator startup using a consistent save file;
+/// from a savestate at any particular frame;
+/// or from the emulator startup with no backing state.
+/// Using a save file is preferred. Starting a replay from a savestate
+/// makes it not possible to prove if game memory was tampered with,
+/// while having no consistent source is likely to cause desyncs.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum ReplaySource {
+    SaveFile {
+        path: Option<PathBuf>,
+        timestamp: DateTime<Utc>,
+    },
+    /// Starts wherever the savestate left the console. The savestate carries
+    /// the clock and the save data along with it.
+    Savestate {
+        state: SavestateSource,
+        /// The frame the savestate was taken on, which the first input belongs to.
+        start_frame: u32,
+    },
+    None {
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl ReplaySource {
+    /// The boundary the replay's first input belongs to.
+    pub fn start_frame(&self) -> u32 {
+        match self {
+            ReplaySource::Savestate { start_frame, .. } => *start_frame,
+            ReplaySource::SaveFile { .. } | ReplaySource::None { .. } => 0,
+        }
+    }
+}
+
+/// Where a replay's starting savestate is kept.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum SavestateSource {
+    /// A savestate file kept alongside the replay.
+    File(PathBuf),
+    /// The savestate itself, so the replay can be shared on its own.
+    Embedded(Vec<u8>),
+}
+
+impl SavestateSource {
+    pub fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            SavestateSource::File(path) => std::fs::read(path).map(Cow::Owned),
+            SavestateSource::Embedded(contents) => Ok(Cow::Borrowed(contents)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SavestateContext {
+    pub replay: Option<SavestateContextReplay>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SavestateContextReplay {
+    pub name: PathBuf,
+    pub inputs: Vec<BoundaryInput>,
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::input::{BoundaryIndex, ConsoleInputState};
+
+    fn replay(source: ReplaySource, inputs: u64) -> Replay {
+        let start = source.start_frame() as u64;
+        Replay {
+            name: PathBuf::from("test.yml"),
+            author: String::new(),
+            source,
+            identity: None,
+            checkpoints: vec![],
+            inputs: (start..start + inputs)
+                .map(|boundary| BoundaryInput {
+                    boundary: BoundaryIndex(boundary),
+                    state: ConsoleInputState::default(),
+                    actions: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn timestamp() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    #[test]
+    fn every_source_survives_a_yaml_round_trip() {
+        let sources = [
+            ReplaySource::SaveFile {
+                path: Some(PathBuf::from("save.bin")),
+                timestamp: timestamp(),
+            },
+            ReplaySource::Savestate {
+                state: SavestateSource::File(PathBuf::from("start.state")),
+                start_frame: 120,
+            },
+            ReplaySource::Savestate {
+                state: SavestateSource::Embedded(vec![1, 2, 3]),
+                start_frame: 120,
+            },
+            ReplaySource::None {
+                timestamp: timestamp(),
+            },
+        ];
+
+        for source in sources {
+            let replay = replay(source, 2);
+            let yaml = serde_yaml::to_string(&replay).unwrap();
+
+            assert_eq!(serde_yaml::from_str::<Replay>(&yaml).unwrap(), replay);
+        }
+    }
+
+    #[test]
+    fn inputs_are_indexed_from_the_savestates_frame() {
+        let replay = replay(
+            ReplaySource::Savestate {
+                state: SavestateSource::Embedded(vec![]),
+                start_frame: 100,
+            },
+            3,
+        );
+
+        assert_eq!(replay.input(99), None);
+        assert_eq!(replay.input(100).unwrap().boundary, BoundaryIndex(100));
+        assert_eq!(replay.input(102).unwrap().boundary, BoundaryIndex(102));
+        assert_eq!(replay.input(103), None);
+        assert_eq!(replay.end(), 103);
+    }
+
+    #[test]
+    fn a_boot_replay_is_indexed_from_the_first_frame() {
+        let replay = replay(
+            ReplaySource::None {
+                timestamp: timestamp(),
+            },
+            3,
+        );
+
+        assert_eq!(replay.index_of(0), Some(0));
+        assert_eq!(replay.end(), 3);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// How often the rewind buffer takes a savestate, and how much memory it may
+/// keep them in.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewindSettings {
+    pub enabled: bool,
+    /// Frames between savestates. Rewinding steps back this many frames at a
+    /// time.
+    pub interval: u32,
+    /// The most the buffer may hold, in megabytes. The oldest savestates are
+    /// dropped to stay under it.
+    pub memory_budget: usize,
+}
+
+impl Default for RewindSettings {
+    fn default() -> Self {
+        RewindSettings {
+            enabled: true,
+            interval: 4,
+            memory_budget: 256,
+        }
+    }
+}
+
+/// A bounded history of recent savestates, newest last.
+///
+/// Consecutive savestates differ in only a small part of the console's memory,
+/// so only the newest is kept whole. Each older one is kept as the bytes that
+/// changed between it and the one after it, which is enough to walk back from
+/// the newest one at a time. Dropping the oldest never needs anything rebuilt.
+#[derive(Debug)]
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    newest: Option<(u64, Vec<u8>)>,
+    older: VecDeque<Delta>,
+    /// Bytes held by `older`.
+    used: usize,
+}
+
+/// What turns a savestate back into the one taken before it.
+#[derive(Debug)]
+struct Delta {
+    frame: u64,
+    len: usize,
+    /// The two savestates XORed together, then run-length encoded.
+    changes: Vec<u8>,
+}
+
+impl Rewind {
+    pub fn new(settings: RewindSettings) -> Self {
+        Rewind {
+            interval: settings.interval.max(1) as u64,
+            budget: settings.memory_budget * 1024 * 1024,
+            newest: None,
+            older: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn is_due(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    /// The number of savestates held.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.older.clear();
+        self.used = 0;
+    }
+
+    /// Keeps the savestate taken on `frame`, which must come after every one
+    /// already held.
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some((previous_frame, previous)) = self.newest.take() {
+            let changes = encode_changes(&previous, &state);
+            self.used += changes.len();
+            self.older.push_back(Delta {
+                frame: previous_frame,
+                len: previous.len(),
+                changes,
+            });
+        }
+        self.newest = Some((frame, state));
+
+        let newest_len = self.newest.as_ref().map_or(0, |(_, state)| state.len());
+        while self.used + newest_len > self.budget {
+            let Some(oldest) = self.older.pop_front() else {
+                break;
+            };
+            self.used -= oldest.changes.len();
+        }
+    }
+
+    /// Drops every savestate taken on or after `frame`, and returns the newest
+    /// one left, as long as it was taken no earlier than `earliest`.
+    ///
+    /// The savestate returned stays in the buffer, so the console can go back
+    /// to it again until something newer is pushed.
+    pub fn rewind_before(&mut self, frame: u64, earliest: u64) -> Option<(u64, &[u8])> {
+        while self.newest.as_ref()?.0 >= frame {
+            self.pop();
+        }
+
+        let (frame, state) = self.newest.as_ref()?;
+        (*frame >= earliest).then_some((*frame, state.as_slice()))
+    }
+
+    fn pop(&mut self) {
+        let Some((_, mut state)) = self.newest.take() else {
+            return;
+        };
+        let Some(delta) = self.older.pop_back() else {
+            return;
+        };
+
+        self.used -= delta.changes.len();
+        apply_changes(&mut state, &delta.changes, delta.len);
+        self.newest = Some((delta.frame, state));
+    }
+}
+
+/// Runs alternate between unchanged bytes, stored as a count, and changed
+/// bytes, stored as a count followed by the XOR of each.
+fn encode_changes(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let len = old.len().max(new.len());
+    let xor =
+        |index: usize| old.get(index).copied().unwrap_or(0) ^ new.get(index).copied().unwrap_or(0);
+
+    let mut out = Vec::new();
+    let mut index = 0;
+    while index < len {
+        let unchanged = (index..len).take_while(|&i| xor(i) == 0).count();
+        index += unchanged;
+        let changed = (index..len).take_while(|&i| xor(i) != 0).count();
+
+        write_varint(&mut out, unchanged);
+        write_varint(&mut out, changed);
+        out.extend((index..index + changed).map(xor));
+        index += changed;
+    }
+
+    out
+}
+
+/// Undoes [`encode_changes`], leaving `state` as the older savestate, `len`
+/// bytes long.
+fn apply_changes(state: &mut Vec<u8>, changes: &[u8], len: usize) {
+    state.resize(state.len().max(len), 0);
+
+    let mut changes = changes.iter().copied();
+    let mut index = 0;
+    while let Some(unchanged) = read_varint(&mut changes) {
+        index += unchanged;
+        let changed = read_varint(&mut changes).unwrap_or(0);
+        for byte in &mut state[index..index + changed] {
+            *byte ^= changes.next().unwrap_or(0);
+        }
+        index += changed;
+    }
+
+    state.truncate(len);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(interval: u32, memory_budget: usize) -> RewindSettings {
+        RewindSettings {
+            enabled: true,
+            interval,
+            memory_budget,
+        }
+    }
+
+    /// A savestate that differs from its neighbours in a few bytes, the way
+    /// real ones do.
+    fn state(frame: u64) -> Vec<u8> {
+        let mut state = vec![0xAA; 4096];
+        state[0..8].copy_from_slice(&frame.to_le_bytes());
+        state[2048] = frame as u8;
+        state
+    }
+
+    #[test]
+    fn changes_round_trip() {
+        let cases: [(&[u8], &[u8]); 4] = [
+            (&[1, 2, 3, 4], &[1, 2, 3, 4]),
+            (&[1, 2, 3, 4], &[1, 9, 9, 4]),
+            (&[1, 2, 3], &[1, 2, 3, 4, 5]),
+            (&[1, 2, 3, 4, 5], &[7]),
+        ];
+
+        for (old, new) in cases {
+            let mut state = new.to_vec();
+            apply_changes(&mut state, &encode_changes(old, new), old.len());
+
+            assert_eq!(state, old);
+        }
+    }
+
+    #[test]
+    fn small_changes_are_small() {
+        assert!(encode_changes(&state(1), &state(2)).len() < 32);
+    }
+
+    #[test]
+    fn rewinding_walks_back_one_savestate_at_a_time() {
+        let mut rewind = Rewind::new(settings(4, 16));
+        for frame in (0..=40).step_by(4) {
+            rewind.push(frame, state(frame));
+        }
+
+        assert_eq!(rewind.rewind_before(41, 0), Some((40, &state(40)[..])));
+        assert_eq!(rewind.rewind_before(40, 0), Some((36, &state(36)[..])));
+        assert_eq!(rewind.rewind_before(36, 0), Some((32, &state(32)[..])));
+        assert_eq!(rewind.len(), 9);
+    }
+
+    #[test]
+    fn the_savestate_returned_can_be_returned_to_again() {
+        let mut rewind = Rewind::new(settings(4, 16));
+        for frame in [0, 4, 8] {
+            rewind.push(frame, state(frame));
+        }
+
+        assert_eq!(rewind.rewind_before(8, 0).map(|(frame, _)| frame), Some(4));
+        assert_eq!(rewind.rewind_before(5, 0).map(|(frame, _)| frame), Some(4));
+    }
+
+    #[test]
+    fn nothing_before_the_earliest_frame_is_returned() {
+        let mut rewind = Rewind::new(settings(4, 16));
+        for frame in [0, 4, 8] {
+            rewind.push(frame, state(frame));
+        }
+
+        assert_eq!(rewind.rewind_before(8, 5), None);
+    }
+
+    #[test]
+    fn the_oldest_savestates_are_dropped_to_stay_in_budget() {
+        let mut rewind = Rewind::new(settings(1, 0));
+        for frame in 0..10 {
+            rewind.push(frame, state(frame));
+        }
+
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.rewind_before(10, 0), Some((9, &state(9)[..])));
+        assert_eq!(rewind.rewind_before(9, 0), None);
+    }
+}
@@ -1,20 +1,27 @@
 use std::collections::HashMap;
-use std::sync::{Arc, OnceLock};
-use std::thread;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 use chrono::{DateTime, Utc};
-use tokio::sync::{mpsc, watch};
 
-use crate::app::{App, RepaintHandle, native_options};
-use crate::audio::Playback;
 use crate::config::Config;
-use crate::frontend::{Frames, Frontend, ReplayState, Request, Save};
-use crate::input::{Binding, InputBridge, InputEvent, KeyCombination};
-use crate::observe::FrameObserver;
-use crate::render::{RenderHook, RenderStatus};
-use crate::replay::Replay;
-use crate::{EmuState, EmuStateChange};
+use crate::debug::{
+    ExecutionHook, GdbSettings, Hooks, TraceSettings, Tracer, WatchKind, WatchLog, WatchSettings,
+};
+use crate::frontend::ReplayState;
+use crate::input::{Binding, InputProvider, KeyCombination};
+use crate::melon::nds::Nds;
+use crate::melon::wireless::Wireless;
+use crate::pacing::Pacing;
+use crate::replay::{CheckpointSettings, Mismatch, Replay, ReplayIdentity};
+use crate::rewind::RewindSettings;
+use crate::speed::Speed;
+
+#[cfg(feature = "gui")]
+mod window;
+
+#[cfg(feature = "gui")]
+pub use window::{run, run_multiplayer};
 
 /// Everything needed to start the emulator after ROM and save bytes are loaded.
 pub struct RunParams {
@@ -24,6 +31,28 @@ pub struct RunParams {
     pub replay: Option<(Replay, ReplayState)>,
     pub key_map: HashMap<KeyCombination, Binding>,
     pub window_title: String,
+    /// Encode video and audio to files with this stem from the first frame.
+    pub encode: Option<PathBuf>,
+    pub checkpoints: CheckpointSettings,
+    pub rewind: RewindSettings,
+    /// The speed the turbo bindings switch to.
+    pub turbo_speed: Speed,
+    pub pacing: Pacing,
+    /// Serve the CPUs to gdb. Needs the `gdbstub` feature.
+    pub gdb: Option<GdbSettings>,
+    /// Closures to call when a CPU reaches an address.
+    pub hooks: Vec<ExecutionHook>,
+    /// Memory to report accesses to.
+    pub watch: WatchSettings,
+    /// How traces toggled by [`crate::input::FrontendCommand::ToggleTrace`]
+    /// run. None can be taken if unset.
+    pub trace: Option<TraceSettings>,
+    /// A Lua script to run from the first frame. Needs the `lua` feature.
+    pub script: Option<PathBuf>,
+    /// Sources of input besides the host's bindings, asked in order.
+    pub input_providers: Vec<Box<dyn InputProvider>>,
+    /// Consoles booted onto the same network can play local multiplayer.
+    pub wireless: Option<Wireless>,
 }
 
 impl RunParams {
@@ -35,256 +64,181 @@ impl RunParams {
             replay: None,
             key_map: Config::default().key_map,
             window_title: String::from("melon-rs"),
+            encode: None,
+            checkpoints: CheckpointSettings::default(),
+            rewind: RewindSettings::default(),
+            turbo_speed: Config::default().turbo_speed,
+            pacing: Pacing::default(),
+            gdb: None,
+            hooks: Vec::new(),
+            watch: WatchSettings::default(),
+            trace: None,
+            script: None,
+            input_providers: Vec::new(),
+            wireless: None,
         }
     }
-}
-
-/// Opens the window, runs emulation until it closes, then shuts down cleanly.
-pub fn run(
-    params: RunParams,
-    observers: impl IntoIterator<Item = Box<dyn FrameObserver>>,
-    render_hooks: impl IntoIterator<Item = Box<dyn RenderHook>>,
-) {
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_io()
-        .enable_time()
-        .build()
-        .expect("failed to build the main runtime");
-
-    runtime.block_on(async move {
-        println!("start_time = {}", params.start_time);
-
-        let (_playback, audio) = Playback::start();
-
-        let (input_tx, input_rx) = mpsc::channel::<InputEvent>(128);
-        let (input_bridge, input_wake_rx) = InputBridge::new(input_tx);
-        let (request_tx, request_rx) = mpsc::channel::<Request>(16);
-        let (state_tx, state_rx) = watch::channel(None);
-        let (save_tx, save_rx) = mpsc::channel::<Save>(8);
-        let (frames_tx, frames_rx) = watch::channel(Arc::new(Frames::blank()));
-        let (status_tx, status_rx) =
-            watch::channel(RenderStatus {
-                frame: 0,
-                paused: true,
-            });
-
-        thread::Builder::new()
-            .name("file-saver".to_owned())
-            .spawn(move || write_saves(save_rx))
-            .expect("failed to spawn the save writer thread");
-
-        let repaint: RepaintHandle = Arc::new(OnceLock::new());
-        let render_hooks: Vec<Box<dyn RenderHook>> = render_hooks.into_iter().collect();
-
-        let emulator = Emulator {
-            frontend: Frontend::new(
-                params.cart,
-                params.save,
-                params.start_time,
-                audio,
-                params.key_map,
-                params.replay,
-                frames_tx,
-            )
-            .with_observers(observers),
-            state: EmuState::Paused,
-            status_tx,
-            state_tx: state_tx.clone(),
-            state_rx,
-            request_tx,
-            request_rx,
-            input_rx,
-            input_wake: input_wake_rx,
-            saves: save_tx,
-            repaint: repaint.clone(),
-        };
-
-        let thread = thread::Builder::new()
-            .name("emulator".to_owned())
-            .spawn(move || emulator.run())
-            .expect("failed to spawn the emulator thread");
-
-        let window_state_tx = state_tx.clone();
-        let window_title = params.window_title;
-
-        eframe::run_native(
-            &window_title,
-            native_options(),
-            Box::new(move |cc| {
-                Ok(Box::new(App::new(
-                    cc,
-                    frames_rx,
-                    status_rx,
-                    render_hooks,
-                    input_bridge,
-                    window_state_tx,
-                    &repaint,
-                )))
-            }),
-        )
-        .expect("failed to open the window");
-
-        let _ = state_tx.send(Some(EmuStateChange::Stop));
-        let _ = thread.join();
-    });
-}
 
-struct Emulator {
-    frontend: Frontend,
-    state: EmuState,
-    status_tx: watch::Sender<RenderStatus>,
-    state_tx: watch::Sender<Option<EmuStateChange>>,
-    state_rx: watch::Receiver<Option<EmuStateChange>>,
-    request_tx: mpsc::Sender<Request>,
-    request_rx: mpsc::Receiver<Request>,
-    input_rx: mpsc::Receiver<InputEvent>,
-    input_wake: watch::Receiver<u64>,
-    saves: mpsc::Sender<Save>,
-    repaint: RepaintHandle,
-}
-
-impl Emulator {
-    const FRAME: Duration = Duration::from_nanos(16_666_667);
-
-    fn run(mut self) {
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_time()
-            .build()
-            .expect("failed to build the emulator runtime");
-
-        runtime.block_on(async move {
-            let mut timer = tokio::time::interval(Self::FRAME);
-            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-
-            loop {
-                tokio::select! {
-                    _ = self.input_wake.changed() => {
-                        let _ = self.input_wake.borrow_and_update();
-                        self.drain_input();
-                        self.apply_state_change();
-                    }
-
-                    _ = timer.tick() => {
-                        self.apply_state_change();
-                        self.serve_requests();
-                        self.drain_input();
-
-                        match self.state {
-                            EmuState::Running => self.tick(),
-                            EmuState::Paused => {}
-                            EmuState::Stepping => {
-                                self.tick();
-                                self.state = EmuState::Paused;
-                            }
-                            EmuState::Stopped => {}
-                        }
-                    }
+    /// How the cart, save, and core differ from those a replay being played
+    /// back was made with. A replay is played whatever this says, so callers
+    /// that would rather not play one with a [`Mismatch::is_fatal`] difference
+    /// check first.
+    pub fn replay_mismatches(&self) -> Vec<Mismatch> {
+        match &self.replay {
+            Some((replay, ReplayState::Playing)) => match &replay.identity {
+                Some(expected) => {
+                    expected.mismatches(&ReplayIdentity::new(&self.cart, self.save.as_deref()))
                 }
-
-                if self.state == EmuState::Stopped {
-                    break;
-                }
-            }
-        });
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
     }
 
-    fn apply_state_change(&mut self) {
-        // Stop is sent from the UI thread on window close; do not rely on
-        // has_changed alone, in case we were busy when it arrived.
-        if *self.state_rx.borrow() == Some(EmuStateChange::Stop) {
-            self.state = EmuState::Stopped;
-            let _ = self.state_rx.borrow_and_update();
-            self.publish_status();
+    /// Stamps a new recording with the cart, save, and core it is recorded
+    /// against, and warns of any way a replay being played back is not
+    /// getting the same.
+    pub fn check_replay(&mut self) {
+        let Some((replay, state)) = &mut self.replay else {
             return;
-        }
+        };
 
-        if !self.state_rx.has_changed().unwrap_or(false) {
+        if let ReplayState::Recording = state {
+            replay.identity = Some(ReplayIdentity::new(&self.cart, self.save.as_deref()));
             return;
         }
+        if replay.identity.is_none() {
+            println!(
+                "WARNING: the replay doesn't record what it was made with, so it can't be checked"
+            );
+        }
 
-        let change = *self.state_rx.borrow_and_update();
-
-        self.state = match (change, self.state) {
-            (Some(EmuStateChange::Stop), _) => EmuState::Stopped,
-            (Some(EmuStateChange::PlayPause), EmuState::Running) => EmuState::Paused,
-            (Some(EmuStateChange::PlayPause), EmuState::Paused | EmuState::Stepping) => {
-                EmuState::Running
-            }
-            (Some(EmuStateChange::Step), EmuState::Running | EmuState::Paused) => {
-                EmuState::Stepping
-            }
-            (_, state) => state,
-        };
-
-        self.publish_status();
+        for mismatch in self.replay_mismatches() {
+            println!("WARNING: {mismatch}");
+        }
     }
 
-    fn publish_status(&self) {
-        let _ = self.status_tx.send(RenderStatus {
-            frame: self.frontend.nds.current_frame() as u64,
-            paused: !matches!(self.state, EmuState::Running),
+    /// Starts running `hooks` and `watch` against `nds`, booted from these
+    /// params. `on_pause` is called whenever one asks for a pause.
+    pub fn attach_hooks(
+        &mut self,
+        nds: &mut Nds,
+        on_pause: impl Fn() + Send + Sync + 'static,
+    ) -> Arc<Mutex<Hooks>> {
+        let mut hooks = Hooks::new(on_pause);
+        for hook in self.hooks.drain(..) {
+            hooks.add(hook);
+        }
+
+        let log = self.watch.log.as_ref().and_then(|path| {
+            WatchLog::create(path)
+                .map_err(|err| println!("WARNING: couldn't create {}: {err}", path.display()))
+                .ok()
+                .map(|log| Arc::new(Mutex::new(log)))
         });
-    }
+        for point in &self.watch.points {
+            let Some(watchpoint) = point.resolve(nds, self.watch.pause) else {
+                println!(
+                    "WARNING: {} can't reach {} bytes at {:#x} in {}, so they can't be watched",
+                    point.cpu,
+                    point.len,
+                    point.offset,
+                    point.domain.name()
+                );
+                continue;
+            };
+            if point.domain.is_serial() && point.kind != WatchKind::Write {
+                println!(
+                    "WARNING: reads of {} can't be caught, only changes to it",
+                    point.domain.name()
+                );
+            }
 
-    fn drain_input(&mut self) {
-        while let Ok(event) = self.input_rx.try_recv() {
-            self.frontend
-                .handle_input_event(event, &self.state_tx, &self.request_tx);
+            let log = log.clone();
+            hooks.add_watchpoint(watchpoint, move |hit| match &log {
+                Some(log) => log.lock().unwrap().record(hit),
+                None => println!("{hit}"),
+            });
         }
+
+        let hooks = Arc::new(Mutex::new(hooks));
+        nds.attach_hooks(hooks.clone());
+        hooks
     }
 
-    fn serve_requests(&mut self) {
-        while let Ok(request) = self.request_rx.try_recv() {
-            let saves = match request {
-                Request::WriteRam(path) => vec![Save {
-                    path,
-                    contents: self.frontend.nds.main_ram().to_vec(),
-                }],
-                Request::WriteSavedata(path) => vec![Save {
-                    path,
-                    contents: self.frontend.nds.save_data().to_vec(),
-                }],
-                Request::WriteSavestate(path) => {
-                    self.frontend.savestate(path.to_string_lossy().into_owned())
-                }
-                Request::WriteReplay => self.frontend.replay_save().into_iter().collect(),
-                Request::ReadSavestate(path) => {
-                    self.frontend
-                        .read_savestate(path.to_string_lossy().into_owned());
-                    continue;
-                }
-            };
+    /// Takes traces through `hooks`, if these params allow them.
+    pub fn tracer(&self, hooks: &Arc<Mutex<Hooks>>) -> Option<Tracer> {
+        let settings = self.trace.clone()?;
+        Some(Tracer::new(hooks.clone(), settings))
+    }
 
-            for save in saves {
-                if let Err(err) = self.saves.try_send(save) {
-                    println!("WARNING: a file was not written: {err}");
-                }
+    /// A console with the cart and save inserted, started at the start time.
+    pub fn boot(&self) -> Nds {
+        let mut nds = match &self.gdb {
+            Some(settings) if cfg!(feature = "gdbstub") => {
+                println!(
+                    "gdb stub listening: ARM9 on port {}, ARM7 on port {}",
+                    settings.arm9_port, settings.arm7_port
+                );
+                Nds::with_gdb(settings)
             }
+            Some(_) => {
+                println!("WARNING: built without the gdbstub feature, so gdb can't attach");
+                Nds::new()
+            }
+            None => Nds::new(),
+        };
+        if let Some(wireless) = &self.wireless {
+            nds.join_wireless(wireless);
         }
+
+        nds.boot(&self.cart, self.save.as_deref(), self.start_time);
+        nds
     }
+}
 
-    fn tick(&mut self) {
-        self.frontend.run_frame();
-        self.publish_status();
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::replay::ReplaySource;
+
+    fn replay_of(cart: &[u8], state: ReplayState) -> (Replay, ReplayState) {
+        let replay = Replay {
+            name: PathBuf::from("run.rpl"),
+            author: String::new(),
+            source: ReplaySource::None {
+                timestamp: Utc::now(),
+            },
+            identity: Some(ReplayIdentity::new(cart, None)),
+            checkpoints: vec![],
+            inputs: vec![],
+        };
 
-        if let Some(ctx) = self.repaint.get() {
-            ctx.request_repaint();
-        }
+        (replay, state)
     }
-}
 
-fn write_saves(mut saves: mpsc::Receiver<Save>) {
-    while let Some(save) = saves.blocking_recv() {
-        let result = save
-            .path
-            .parent()
-            .map(std::fs::create_dir_all)
-            .transpose()
-            .and_then(|_| std::fs::write(&save.path, &save.contents));
+    #[test]
+    fn a_replay_of_another_game_is_reported_rather_than_refused() {
+        let mut params = RunParams::new(vec![1; 0x200]);
+        params.replay = Some(replay_of(&[2; 0x200], ReplayState::Playing));
 
-        match result {
-            Ok(()) => println!("wrote {}", save.path.display()),
-            Err(err) => println!("WARNING: couldn't write {}: {err}", save.path.display()),
-        }
+        assert!(params.replay_mismatches().iter().any(Mismatch::is_fatal));
+        params.check_replay();
+    }
+
+    #[test]
+    fn a_recording_is_stamped_with_what_it_is_made_with() {
+        let mut params = RunParams::new(vec![1; 0x200]);
+        params.replay = Some(replay_of(&[2; 0x200], ReplayState::Recording));
+        params.check_replay();
+
+        let (replay, _) = params.replay.as_ref().unwrap();
+        assert_eq!(
+            replay.identity,
+            Some(ReplayIdentity::new(&[1; 0x200], None))
+        );
+        assert!(params.replay_mismatches().is_empty());
     }
 }
@@ -0,0 +1,569 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+use egui::Context;
+use tokio::sync::{mpsc, watch};
+
+use crate::app::{native_options, App};
+use crate::audio::{Audio, Playback, SharedBacklog};
+use crate::debug::{Cpu, DebugCommand, DebugHandle, Hooks};
+use crate::frontend::{Frames, Frontend, Request, Save};
+use crate::input::{InputBridge, InputEvent};
+use crate::melon::wireless::Wireless;
+use crate::observe::FrameObserver;
+use crate::pacing::Pacing;
+use crate::panels::{self, Panel};
+use crate::render::{RenderHook, RenderStatus, RepaintHandle};
+use crate::speed::{Speed, SpeedControl};
+use crate::{EmuState, EmuStateChange};
+
+use super::RunParams;
+
+/// Opens the window, runs emulation until it closes, then shuts down cleanly.
+pub fn run(
+    params: RunParams,
+    observers: impl IntoIterator<Item = Box<dyn FrameObserver>>,
+    render_hooks: impl IntoIterator<Item = Box<dyn RenderHook>>,
+) {
+    run_consoles(vec![(
+        params,
+        observers.into_iter().collect(),
+        render_hooks.into_iter().collect(),
+    )]);
+}
+
+/// Like [`run`], with a console and a window for each player, all on the same
+/// wireless network unless their params say otherwise. Only the first
+/// player's console is heard, and the others keep pace with it. Pausing,
+/// stepping, or changing the speed of any console does the same to them all,
+/// and closing any window closes them all. Rewind is turned off, as a console
+/// that stepped back alone would leave the others' link behind.
+pub fn run_multiplayer(players: Vec<RunParams>) {
+    let wireless = Wireless::new();
+    let consoles = players
+        .into_iter()
+        .map(|mut params| {
+            params.wireless.get_or_insert_with(|| wireless.clone());
+            params.rewind.enabled = false;
+            (params, Vec::new(), Vec::new())
+        })
+        .collect();
+
+    run_consoles(consoles);
+}
+
+type ConsoleParams = (
+    RunParams,
+    Vec<Box<dyn FrameObserver>>,
+    Vec<Box<dyn RenderHook>>,
+);
+
+fn run_consoles(params: Vec<ConsoleParams>) {
+    if params.is_empty() {
+        return;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .expect("failed to build the main runtime");
+
+    runtime.block_on(async move {
+        let (_playback, audio) = Playback::start();
+        let mut audio = Some(audio);
+        let link = Link::new();
+
+        let (consoles, windows): (Vec<Console>, Vec<Window>) = params
+            .into_iter()
+            .map(|(params, observers, render_hooks)| {
+                Console::start(params, audio.take(), &link, observers, render_hooks)
+            })
+            .unzip();
+
+        let window_title = windows[0].title.clone();
+
+        eframe::run_native(
+            &window_title,
+            native_options(),
+            Box::new(move |cc| {
+                let mut windows = windows.into_iter();
+                let main = windows.next().unwrap().open(&cc.egui_ctx);
+                let players =
+                    windows.map(|window| (window.title.clone(), window.open(&cc.egui_ctx)));
+
+                Ok(Box::new(main.with_players(players)))
+            }),
+        )
+        .expect("failed to open the window");
+
+        for console in &consoles {
+            let _ = console.state_tx.send(Some(EmuStateChange::Stop));
+        }
+        for console in consoles {
+            console.join();
+        }
+    });
+}
+
+/// What the consoles run together share, so that they pause, step, and change
+/// speed together, and keep pace with the one that is heard.
+struct Link {
+    state_tx: watch::Sender<Option<EmuStateChange>>,
+    speed_tx: watch::Sender<SpeedControl>,
+    backlog: SharedBacklog,
+}
+
+impl Link {
+    fn new() -> Self {
+        Link {
+            state_tx: watch::channel(None).0,
+            speed_tx: watch::channel(SpeedControl::default()).0,
+            backlog: SharedBacklog::default(),
+        }
+    }
+}
+
+/// A console's emulator thread, and what's needed to stop it.
+struct Console {
+    state_tx: watch::Sender<Option<EmuStateChange>>,
+    thread: thread::JoinHandle<()>,
+    hooks: Arc<Mutex<Hooks>>,
+}
+
+/// What a console's window is made from.
+struct Window {
+    title: String,
+    frames_rx: watch::Receiver<Arc<Frames>>,
+    status_rx: watch::Receiver<RenderStatus>,
+    render_hooks: Vec<Box<dyn RenderHook>>,
+    input_bridge: InputBridge,
+    state_tx: watch::Sender<Option<EmuStateChange>>,
+    panels: Vec<Box<dyn Panel>>,
+    repaint: RepaintHandle,
+}
+
+impl Console {
+    /// Boots the console in `params` and starts emulating it, playing its
+    /// sound through `audio` if given, and pacing it by `link`'s backlog if not.
+    fn start(
+        mut params: RunParams,
+        audio: Option<Audio>,
+        link: &Link,
+        observers: Vec<Box<dyn FrameObserver>>,
+        render_hooks: Vec<Box<dyn RenderHook>>,
+    ) -> (Console, Window) {
+        println!("start_time = {}", params.start_time);
+        params.check_replay();
+
+        let (input_tx, input_rx) = mpsc::channel::<InputEvent>(128);
+        let (input_bridge, input_wake_rx) = InputBridge::new(input_tx);
+        let (request_tx, request_rx) = mpsc::channel::<Request>(16);
+        let state_tx = link.state_tx.clone();
+        let state_rx = state_tx.subscribe();
+        let (save_tx, save_rx) = mpsc::channel::<Save>(8);
+        let (frames_tx, frames_rx) = watch::channel(Arc::new(Frames::blank()));
+        let (status_tx, status_rx) = watch::channel(RenderStatus {
+            frame: 0,
+            state: EmuState::Paused,
+            speed: Speed::Normal,
+        });
+
+        thread::Builder::new()
+            .name("file-saver".to_owned())
+            .spawn(move || write_saves(save_rx))
+            .expect("failed to spawn the save writer thread");
+
+        let repaint: RepaintHandle = Arc::new(OnceLock::new());
+        let (debug, debug_rx) = DebugHandle::new(repaint.clone());
+
+        let mut nds = params.boot();
+        let hook_state_tx = state_tx.clone();
+        let hooks = params.attach_hooks(&mut nds, move || {
+            let _ = hook_state_tx.send(Some(EmuStateChange::Pause));
+        });
+        hooks
+            .lock()
+            .unwrap()
+            .set_on_debugger(show_debugger(status_tx.clone(), repaint.clone()));
+        let tracer = params.tracer(&hooks);
+        let (panels, panel_observers) = panels::all(&debug, hooks.clone(), &state_tx, &request_tx);
+
+        let heard = audio.is_some();
+        let mut frontend = Frontend::new(nds, audio, params.key_map, params.replay, frames_tx)
+            .with_observers(observers.into_iter().chain(panel_observers))
+            .with_input_providers(params.input_providers)
+            .with_checkpoints(params.checkpoints)
+            .with_rewind(params.rewind)
+            .with_turbo_speed(params.turbo_speed)
+            .with_pacing(params.pacing)
+            .with_tracer(tracer);
+
+        if let Some(stem) = &params.encode {
+            frontend.start_encoding(stem);
+        }
+        #[cfg(feature = "lua")]
+        let script_overlay: Option<Box<dyn RenderHook>> = Some(Box::new(frontend.script_overlay()));
+        #[cfg(not(feature = "lua"))]
+        let script_overlay: Option<Box<dyn RenderHook>> = None;
+        let render_hooks: Vec<_> = render_hooks.into_iter().chain(script_overlay).collect();
+        if let Some(script) = &params.script {
+            frontend.load_script(script);
+        }
+
+        let speed_seen = frontend.speed_control();
+        let emulator = Emulator {
+            frontend,
+            state: EmuState::Paused,
+            status_tx,
+            state_tx: state_tx.clone(),
+            state_rx,
+            request_tx,
+            request_rx,
+            input_rx,
+            input_wake: input_wake_rx,
+            saves: save_tx,
+            repaint: repaint.clone(),
+            pause_on_desync: params.checkpoints.pause_on_desync,
+            pacing: params.pacing,
+            heard,
+            backlog: link.backlog.clone(),
+            speed_seen,
+            speed_tx: link.speed_tx.clone(),
+            speed_rx: link.speed_tx.subscribe(),
+            debug: debug_rx,
+        };
+
+        let thread = thread::Builder::new()
+            .name("emulator".to_owned())
+            .spawn(move || emulator.run())
+            .expect("failed to spawn the emulator thread");
+
+        let window = Window {
+            title: params.window_title,
+            frames_rx,
+            status_rx,
+            render_hooks,
+            input_bridge,
+            state_tx: state_tx.clone(),
+            panels,
+            repaint,
+        };
+        let console = Console {
+            state_tx,
+            thread,
+            hooks,
+        };
+
+        (console, window)
+    }
+
+    /// Waits for the emulator thread to finish after being told to stop.
+    fn join(self) {
+        // A CPU held at a breakpoint or in the debugger holds the frame with
+        // it, so it's let go first.
+        self.hooks.lock().unwrap().detach();
+        let _ = self.thread.join();
+    }
+}
+
+impl Window {
+    fn open(self, ctx: &Context) -> App {
+        App::for_context(
+            ctx,
+            self.frames_rx,
+            self.status_rx,
+            self.render_hooks,
+            self.input_bridge,
+            self.state_tx,
+            &self.repaint,
+        )
+        .with_panels(self.panels)
+    }
+}
+
+struct Emulator {
+    frontend: Frontend,
+    state: EmuState,
+    status_tx: watch::Sender<RenderStatus>,
+    state_tx: watch::Sender<Option<EmuStateChange>>,
+    state_rx: watch::Receiver<Option<EmuStateChange>>,
+    request_tx: mpsc::Sender<Request>,
+    request_rx: mpsc::Receiver<Request>,
+    input_rx: mpsc::Receiver<InputEvent>,
+    input_wake: watch::Receiver<u64>,
+    saves: mpsc::Sender<Save>,
+    repaint: RepaintHandle,
+    pause_on_desync: bool,
+    pacing: Pacing,
+    /// Whether this console's audio is played, and so sets the pace for the
+    /// others through `backlog`.
+    heard: bool,
+    backlog: SharedBacklog,
+    /// The speed control as last shared with the other consoles.
+    speed_seen: SpeedControl,
+    speed_tx: watch::Sender<SpeedControl>,
+    speed_rx: watch::Receiver<SpeedControl>,
+    debug: mpsc::Receiver<DebugCommand>,
+}
+
+impl Emulator {
+    fn run(mut self) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("failed to build the emulator runtime");
+
+        runtime.block_on(async move {
+            let mut speed = self.frontend.speed();
+            let mut deadline = Instant::now();
+
+            loop {
+                tokio::select! {
+                    _ = self.input_wake.changed() => {
+                        let _ = self.input_wake.borrow_and_update();
+                        self.drain_input();
+                        self.apply_state_change();
+                    }
+
+                    _ = tokio::time::sleep_until(deadline.into()) => {
+                        deadline = self.next_deadline(deadline);
+
+                        self.apply_state_change();
+                        self.serve_requests();
+                        self.serve_debug();
+                        self.drain_input();
+
+                        match self.state {
+                            EmuState::Running => self.run_for_tick(speed),
+                            EmuState::Paused => {}
+                            EmuState::Stepping => {
+                                self.tick();
+                                self.state = EmuState::Paused;
+                            }
+                            EmuState::Stopped | EmuState::Debugging => {}
+                        }
+                    }
+                }
+
+                if self.state == EmuState::Stopped {
+                    break;
+                }
+
+                self.share_speed();
+                if self.frontend.speed() != speed {
+                    speed = self.frontend.speed();
+                    self.publish_status();
+                }
+            }
+        });
+    }
+
+    /// Schedules the tick after the one due at `deadline`.
+    ///
+    /// Unlimited speed still ticks at the normal rate, so input and the window
+    /// are served as often as ever, and [`Emulator::run_for_tick`] fills each
+    /// tick with as many frames as fit. A tick more than a frame late is not
+    /// made up for, so a stall cannot turn into a burst of frames afterwards.
+    fn next_deadline(&self, deadline: Instant) -> Instant {
+        let backlog = if self.heard {
+            let backlog = self.frontend.audio_backlog();
+            self.backlog.set(backlog);
+            backlog
+        } else {
+            self.backlog.get()
+        };
+        let period = self
+            .pacing
+            .frame_period(self.frontend.speed(), backlog)
+            .unwrap_or(self.pacing.frame_duration());
+
+        let now = Instant::now();
+        let next = deadline + period;
+        if next + period < now {
+            now
+        } else {
+            next
+        }
+    }
+
+    /// Hands a speed change made by this console's bindings to the others, or
+    /// takes up one made by theirs.
+    fn share_speed(&mut self) {
+        let control = self.frontend.speed_control();
+
+        if control != self.speed_seen {
+            self.speed_seen = control;
+            self.speed_tx.send_replace(control);
+            self.speed_rx.borrow_and_update();
+        } else if self.speed_rx.has_changed().unwrap_or(false) {
+            self.speed_seen = *self.speed_rx.borrow_and_update();
+            self.frontend.set_speed_control(self.speed_seen);
+        }
+    }
+
+    fn run_for_tick(&mut self, speed: Speed) {
+        if speed.multiplier().is_some() {
+            self.tick();
+            return;
+        }
+
+        let start = Instant::now();
+        while self.state == EmuState::Running && start.elapsed() < self.pacing.frame_duration() {
+            self.tick();
+        }
+    }
+
+    fn apply_state_change(&mut self) {
+        // Stop is sent from the UI thread on window close; do not rely on
+        // has_changed alone, in case we were busy when it arrived.
+        if *self.state_rx.borrow() == Some(EmuStateChange::Stop) {
+            self.state = EmuState::Stopped;
+            let _ = self.state_rx.borrow_and_update();
+            self.publish_status();
+            return;
+        }
+
+        if !self.state_rx.has_changed().unwrap_or(false) {
+            return;
+        }
+
+        let change = *self.state_rx.borrow_and_update();
+
+        self.state = match (change, self.state) {
+            (Some(EmuStateChange::Stop), _) => EmuState::Stopped,
+            (Some(EmuStateChange::PlayPause), EmuState::Running) => EmuState::Paused,
+            (Some(EmuStateChange::PlayPause), EmuState::Paused | EmuState::Stepping) => {
+                EmuState::Running
+            }
+            (Some(EmuStateChange::Pause), EmuState::Running | EmuState::Stepping) => {
+                EmuState::Paused
+            }
+            (Some(EmuStateChange::Step), EmuState::Running | EmuState::Paused) => {
+                EmuState::Stepping
+            }
+            (_, state) => state,
+        };
+
+        self.publish_status();
+    }
+
+    fn publish_status(&self) {
+        let _ = self.status_tx.send(RenderStatus {
+            frame: self.frontend.nds.current_frame() as u64,
+            state: self.state,
+            speed: self.frontend.speed(),
+        });
+    }
+
+    fn drain_input(&mut self) {
+        while let Ok(event) = self.input_rx.try_recv() {
+            self.frontend
+                .handle_input_event(event, &self.state_tx, &self.request_tx);
+        }
+    }
+
+    fn serve_requests(&mut self) {
+        while let Ok(request) = self.request_rx.try_recv() {
+            let saves = match request {
+                Request::WriteRam(path) => vec![Save {
+                    path,
+                    contents: self.frontend.nds.main_ram().to_vec(),
+                }],
+                Request::WriteSavedata(path) => vec![Save {
+                    path,
+                    contents: self.frontend.nds.save_data().to_vec(),
+                }],
+                Request::WriteSavestate(path) => {
+                    self.frontend.savestate(path.to_string_lossy().into_owned())
+                }
+                Request::WriteReplay => self.frontend.replay_save().into_iter().collect(),
+                Request::ReadSavestate(path) => {
+                    self.frontend
+                        .read_savestate(path.to_string_lossy().into_owned());
+                    continue;
+                }
+                Request::LoadScript(path) => {
+                    self.frontend.load_script(&path);
+                    continue;
+                }
+                Request::StopScript => {
+                    self.frontend.stop_script();
+                    continue;
+                }
+            };
+
+            for save in saves {
+                if let Err(err) = self.saves.try_send(save) {
+                    println!("WARNING: a file was not written: {err}");
+                }
+            }
+        }
+    }
+
+    fn serve_debug(&mut self) {
+        while let Ok(command) = self.debug.try_recv() {
+            command(&mut self.frontend.nds);
+        }
+    }
+
+    fn tick(&mut self) {
+        let desynced = self.frontend.desync().is_some();
+        self.frontend.run_frame();
+
+        if self.pause_on_desync && !desynced && self.frontend.desync().is_some() {
+            self.state = EmuState::Paused;
+        }
+        self.publish_status();
+
+        if let Some(ctx) = self.repaint.get() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// Shows the console as [`EmuState::Debugging`] while a debugger holds one of
+/// its CPUs, and as it was before once the debugger lets go.
+///
+/// The emulator thread can't publish this itself, as it's the thread the
+/// debugger is holding, so the stub's callback does it from there.
+fn show_debugger(
+    status: watch::Sender<RenderStatus>,
+    repaint: RepaintHandle,
+) -> impl Fn(Cpu, bool) + Send + Sync + 'static {
+    let before = Mutex::new(None);
+
+    move |cpu, stopped| {
+        if stopped {
+            println!("{cpu} stopped in the debugger");
+            let previous = status.send_replace(RenderStatus {
+                state: EmuState::Debugging,
+                ..*status.borrow()
+            });
+            *before.lock().unwrap() = Some(previous.state);
+        } else if let Some(state) = before.lock().unwrap().take() {
+            status.send_modify(|status| status.state = state);
+        }
+
+        if let Some(ctx) = repaint.get() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+fn write_saves(mut saves: mpsc::Receiver<Save>) {
+    while let Some(save) = saves.blocking_recv() {
+        let result = save
+            .path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .and_then(|_| std::fs::write(&save.path, &save.contents));
+
+        match result {
+            Ok(()) => println!("wrote {}", save.path.display()),
+            Err(err) => println!("WARNING: couldn't write {}: {err}", save.path.display()),
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use mlua::{Error, Function, Lua, Result, Scope, Table, Value};
+
+use crate::debug::Cpu;
+use crate::input::{BoundaryInput, ButtonMask, SystemAction, TouchPoint};
+use crate::melon::memory::MemoryDomain;
+use crate::melon::nds::Nds;
+use crate::overlay::{Color, DrawCmd, Line, Overlay, Point, Rect, Screen, Text};
+use crate::ram::ValueType;
+
+pub(super) const ON_FRAME: &str = "melon_on_frame";
+pub(super) const ON_INPUT: &str = "melon_on_input";
+
+const BUTTONS: [(&str, ButtonMask); 12] = [
+    ("a", ButtonMask::A),
+    ("b", ButtonMask::B),
+    ("select", ButtonMask::SELECT),
+    ("start", ButtonMask::START),
+    ("right", ButtonMask::RIGHT),
+    ("left", ButtonMask::LEFT),
+    ("up", ButtonMask::UP),
+    ("down", ButtonMask::DOWN),
+    ("r", ButtonMask::R),
+    ("l", ButtonMask::L),
+    ("x", ButtonMask::X),
+    ("y", ButtonMask::Y),
+];
+
+/// Sets up the parts of the API that don't need the console: registering
+/// callbacks, drawing into `drawing`, and asking for a savestate to be loaded
+/// through `state_to_load`.
+pub(super) fn install(
+    lua: &Lua,
+    drawing: Arc<Mutex<Overlay>>,
+    state_to_load: Arc<Mutex<Option<Vec<u8>>>>,
+) -> Result<()> {
+    let globals = lua.globals();
+
+    let emu = lua.create_table()?;
+    emu.set(
+        "on_frame",
+        lua.create_function(|lua, callback: Option<Function>| {
+            lua.set_named_registry_value(ON_FRAME, callback)
+        })?,
+    )?;
+    emu.set(
+        "on_input",
+        lua.create_function(|lua, callback: Option<Function>| {
+            lua.set_named_registry_value(ON_INPUT, callback)
+        })?,
+    )?;
+    globals.set("emu", emu)?;
+
+    let memory = lua.create_table()?;
+    memory.set(
+        "domains",
+        lua.create_function(|_, ()| Ok(MemoryDomain::ALL.map(|domain| domain.name()).to_vec()))?,
+    )?;
+    globals.set("memory", memory)?;
+    globals.set("bus", lua.create_table()?)?;
+
+    // The frontend loads it once the script returns, as loading one has to
+    // square it with the replay running, and with the rewind history.
+    let savestate = lua.create_table()?;
+    savestate.set(
+        "load",
+        lua.create_function(move |_, state: mlua::String| {
+            *state_to_load.lock().unwrap() = Some(state.as_bytes().to_vec());
+            Ok(())
+        })?,
+    )?;
+    globals.set("savestate", savestate)?;
+
+    let gui = lua.create_table()?;
+    let draw = drawing.clone();
+    gui.set(
+        "text",
+        lua.create_function(
+            move |_, (screen, x, y, text, color): (String, f32, f32, String, Option<u32>)| {
+                let text = Text::new(Point::new(x, y), text, color_or_white(color));
+                draw.lock()
+                    .unwrap()
+                    .push(screen_named(&screen)?, DrawCmd::EguiText(text));
+                Ok(())
+            },
+        )?,
+    )?;
+    let draw = drawing.clone();
+    gui.set(
+        "rect",
+        lua.create_function(
+            move |_,
+                  (screen, x, y, width, height, color, fill): (
+                String,
+                f32,
+                f32,
+                f32,
+                f32,
+                Option<u32>,
+                Option<u32>,
+            )| {
+                let mut rect = Rect::stroke(Point::new(x, y), width, height, color_or_white(color));
+                rect.fill = fill.map(Color::rgba_u32);
+                draw.lock()
+                    .unwrap()
+                    .push(screen_named(&screen)?, DrawCmd::Rect(rect));
+                Ok(())
+            },
+        )?,
+    )?;
+    gui.set(
+        "line",
+        lua.create_function(
+            move |_, (screen, x1, y1, x2, y2, color): (String, f32, f32, f32, f32, Option<u32>)| {
+                let line = Line::new(
+                    Point::new(x1, y1),
+                    Point::new(x2, y2),
+                    color_or_white(color),
+                );
+                drawing
+                    .lock()
+                    .unwrap()
+                    .push(screen_named(&screen)?, DrawCmd::Line(line));
+                Ok(())
+            },
+        )?,
+    )?;
+    globals.set("gui", gui)?;
+
+    Ok(())
+}
+
+/// Adds the parts of the API that reach the console, for as long as `scope`
+/// lasts. Outside a callback they raise an error instead.
+pub(super) fn install_scoped<'lua, 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    nds: &'scope RefCell<&mut Nds>,
+) -> Result<()> {
+    let globals = lua.globals();
+
+    let emu: Table = globals.get("emu")?;
+    emu.set(
+        "frame",
+        scope.create_function(|_, ()| Ok(nds.borrow().current_frame()))?,
+    )?;
+
+    let memory: Table = globals.get("memory")?;
+    memory.set(
+        "read",
+        scope.create_function(
+            |lua, (domain, offset, kind): (String, usize, Option<String>)| {
+                let (domain, kind) = (domain_named(&domain)?, kind_named(kind)?);
+                let nds = nds.borrow();
+                let bytes = span(offset, kind.size())
+                    .and_then(|span| nds.memory(domain).get(span))
+                    .ok_or_else(|| out_of_range(domain, offset))?;
+                decode(lua, kind, bytes)
+            },
+        )?,
+    )?;
+    memory.set(
+        "write",
+        scope.create_function(
+            |_, (domain, offset, value, kind): (String, usize, Value, Option<String>)| {
+                let (domain, kind) = (domain_named(&domain)?, kind_named(kind)?);
+                let bytes = encode(kind, value)?;
+                let mut nds = nds.borrow_mut();
+                let memory = nds
+                    .memory_mut(domain)
+                    .ok_or_else(|| Error::RuntimeError(format!("{domain} can't be written")))?;
+                span(offset, bytes.len())
+                    .and_then(|span| memory.get_mut(span))
+                    .ok_or_else(|| out_of_range(domain, offset))?
+                    .copy_from_slice(&bytes);
+                Ok(())
+            },
+        )?,
+    )?;
+    memory.set(
+        "read_bytes",
+        scope.create_function(|lua, (domain, offset, len): (String, usize, usize)| {
+            let domain = domain_named(&domain)?;
+            let nds = nds.borrow();
+            let bytes = span(offset, len)
+                .and_then(|span| nds.memory(domain).get(span))
+                .ok_or_else(|| out_of_range(domain, offset))?;
+            lua.create_string(bytes)
+        })?,
+    )?;
+
+    let bus: Table = globals.get("bus")?;
+    bus.set(
+        "read",
+        scope.create_function(|lua, (cpu, addr, kind): (String, u32, Option<String>)| {
+            let (cpu, kind) = (cpu_named(&cpu)?, kind_named(kind)?);
+            let mut nds = nds.borrow_mut();
+            let bytes: Vec<u8> = (0..kind.size() as u32)
+                .map(|i| nds.read8(cpu, addr.wrapping_add(i)))
+                .collect();
+            decode(lua, kind, &bytes)
+        })?,
+    )?;
+    bus.set(
+        "write",
+        scope.create_function(
+            |_, (cpu, addr, value, kind): (String, u32, Value, Option<String>)| {
+                let (cpu, kind) = (cpu_named(&cpu)?, kind_named(kind)?);
+                let bytes = encode(kind, value)?;
+                let mut nds = nds.borrow_mut();
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    nds.write8(cpu, addr.wrapping_add(i as u32), byte);
+                }
+                Ok(())
+            },
+        )?,
+    )?;
+
+    let savestate: Table = globals.get("savestate")?;
+    savestate.set(
+        "save",
+        scope.create_function(|lua, ()| lua.create_string(nds.borrow_mut().savestate()))?,
+    )?;
+
+    Ok(())
+}
+
+/// The table an input callback is given, and can hand back changed.
+pub(super) fn input_table<'lua>(lua: &'lua Lua, input: &BoundaryInput) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+
+    let buttons = lua.create_table()?;
+    for (name, mask) in BUTTONS {
+        buttons.set(name, input.state.buttons.contains(mask))?;
+    }
+    table.set("buttons", buttons)?;
+
+    if let Some(point) = input.state.touch {
+        let touch = lua.create_table()?;
+        touch.set("x", point.x)?;
+        touch.set("y", point.y)?;
+        table.set("touch", touch)?;
+    }
+    table.set("lid_closed", input.state.lid_closed)?;
+    table.set("reset", input.actions.contains(&SystemAction::Reset))?;
+
+    Ok(table)
+}
+
+/// Replaces `input` with what `table` holds.
+pub(super) fn read_input_table(table: &Table, input: &mut BoundaryInput) -> Result<()> {
+    let mut buttons = ButtonMask::empty();
+    if let Some(pressed) = table.get::<_, Option<Table>>("buttons")? {
+        for (name, mask) in BUTTONS {
+            if pressed.get::<_, Option<bool>>(name)?.unwrap_or(false) {
+                buttons |= mask;
+            }
+        }
+    }
+    input.state.buttons = buttons;
+
+    input.state.touch = match table.get::<_, Option<Table>>("touch")? {
+        Some(touch) => TouchPoint::new(touch.get("x")?, touch.get("y")?),
+        None => None,
+    };
+    input.state.lid_closed = table.get::<_, Option<bool>>("lid_closed")?.unwrap_or(false);
+
+    let reset = table.get::<_, Option<bool>>("reset")?.unwrap_or(false);
+    input
+        .actions
+        .retain(|action| *action != SystemAction::Reset);
+    if reset {
+        input.actions.push(SystemAction::Reset);
+    }
+
+    Ok(())
+}
+
+fn decode<'lua>(lua: &'lua Lua, kind: ValueType, bytes: &[u8]) -> Result<Value<'lua>> {
+    let mut raw = [0; 8];
+    raw[..bytes.len()].copy_from_slice(bytes);
+    let raw = u64::from_le_bytes(raw);
+
+    Ok(match kind {
+        ValueType::I8 => Value::Integer(raw as u8 as i8 as i64),
+        ValueType::I16 => Value::Integer(raw as u16 as i16 as i64),
+        ValueType::I32 => Value::Integer(raw as u32 as i32 as i64),
+        ValueType::Fixed => Value::Number(raw as u32 as i32 as f64 / 4096.0),
+        ValueType::F32 => Value::Number(f32::from_bits(raw as u32) as f64),
+        ValueType::Str(_) => Value::String(lua.create_string(bytes)?),
+        _ => Value::Integer(raw as i64),
+    })
+}
+
+fn encode(kind: ValueType, value: Value) -> Result<Vec<u8>> {
+    let number = match value {
+        Value::Integer(value) => value as f64,
+        Value::Number(value) => value,
+        _ => return Err(Error::RuntimeError(String::from("expected a number"))),
+    };
+
+    let raw = match kind {
+        ValueType::Fixed => (number * 4096.0).round() as i32 as u32 as u64,
+        ValueType::F32 => (number as f32).to_bits() as u64,
+        _ => match value {
+            Value::Integer(value) => value as u64,
+            _ => number as i64 as u64,
+        },
+    };
+    Ok(raw.to_le_bytes()[..kind.size()].to_vec())
+}
+
+fn kind_named(name: Option<String>) -> Result<ValueType> {
+    let Some(name) = name else {
+        return Ok(ValueType::U8);
+    };
+
+    match name.as_str() {
+        "u8" => Ok(ValueType::U8),
+        "i8" => Ok(ValueType::I8),
+        "u16" => Ok(ValueType::U16),
+        "i16" => Ok(ValueType::I16),
+        "u32" => Ok(ValueType::U32),
+        "i32" => Ok(ValueType::I32),
+        "fixed" => Ok(ValueType::Fixed),
+        "f32" => Ok(ValueType::F32),
+        _ => Err(Error::RuntimeError(format!(
+            "there is no type called {name}"
+        ))),
+    }
+}
+
+fn domain_named(name: &str) -> Result<MemoryDomain> {
+    MemoryDomain::ALL
+        .into_iter()
+        .find(|domain| domain.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::RuntimeError(format!("there is no memory domain called {name}")))
+}
+
+fn cpu_named(name: &str) -> Result<Cpu> {
+    Cpu::ALL
+        .into_iter()
+        .find(|cpu| cpu.to_string().eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::RuntimeError(format!("there is no CPU called {name}")))
+}
+
+fn screen_named(name: &str) -> Result<Screen> {
+    match name {
+        "top" => Ok(Screen::Top),
+        "bottom" => Ok(Screen::Bottom),
+        _ => Err(Error::RuntimeError(format!(
+            "there is no screen called {name}"
+        ))),
+    }
+}
+
+fn color_or_white(color: Option<u32>) -> Color {
+    color.map_or(Color::rgb(255, 255, 255), Color::rgba_u32)
+}
+
+/// The `len` bytes from `offset`, unless they'd run past the end of memory.
+fn span(offset: usize, len: usize) -> Option<Range<usize>> {
+    Some(offset..offset.checked_add(len)?)
+}
+
+fn out_of_range(domain: MemoryDomain, offset: usize) -> Error {
+    Error::RuntimeError(format!("{offset:#x} is past the end of {domain}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{BoundaryIndex, ConsoleInputState};
+
+    #[test]
+    fn input_tables_round_trip() {
+        let lua = Lua::new();
+        let input = BoundaryInput {
+            boundary: BoundaryIndex(7),
+            state: ConsoleInputState {
+                buttons: ButtonMask::A | ButtonMask::LEFT,
+                touch: TouchPoint::new(10, 20),
+                lid_closed: false,
+            },
+            actions: vec![SystemAction::Reset],
+        };
+
+        let table = input_table(&lua, &input).unwrap();
+        let mut read = BoundaryInput {
+            boundary: BoundaryIndex(7),
+            state: ConsoleInputState::default(),
+            actions: Vec::new(),
+        };
+        read_input_table(&table, &mut read).unwrap();
+
+        assert_eq!(read, input);
+    }
+
+    #[test]
+    fn scripts_can_change_the_input() {
+        let lua = Lua::new();
+        let change: Function = lua
+            .load("return function(input) input.buttons.b = true; input.touch = nil; return input end")
+            .eval()
+            .unwrap();
+        let mut input = BoundaryInput {
+            boundary: BoundaryIndex(0),
+            state: ConsoleInputState {
+                buttons: ButtonMask::A,
+                touch: TouchPoint::new(1, 1),
+                lid_closed: false,
+            },
+            actions: Vec::new(),
+        };
+
+        let table: Table = change.call(input_table(&lua, &input).unwrap()).unwrap();
+        read_input_table(&table, &mut input).unwrap();
+
+        assert_eq!(input.state.buttons, ButtonMask::A | ButtonMask::B);
+        assert_eq!(input.state.touch, None);
+    }
+
+    #[test]
+    fn spans_past_the_end_of_memory_are_refused() {
+        assert_eq!(span(0x10, 4), Some(0x10..0x14));
+        assert_eq!(span(usize::MAX - 1, 4), None);
+    }
+
+    #[test]
+    fn values_are_encoded_by_type() {
+        let lua = Lua::new();
+
+        let bytes = encode(ValueType::Fixed, Value::Number(-1.5)).unwrap();
+        assert_eq!(bytes, [0x00, 0xE8, 0xFF, 0xFF]);
+        assert!(matches!(
+            decode(&lua, ValueType::Fixed, &bytes).unwrap(),
+            Value::Number(n) if n == -1.5
+        ));
+
+        let bytes = encode(ValueType::I16, Value::Integer(-2)).unwrap();
+        assert_eq!(bytes, [0xFE, 0xFF]);
+        assert!(matches!(
+            decode(&lua, ValueType::I16, &bytes).unwrap(),
+            Value::Integer(-2)
+        ));
+        assert!(matches!(
+            decode(&lua, ValueType::U16, &bytes).unwrap(),
+            Value::Integer(65534)
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+//! Lua scripts run alongside the game, from the `--script` argument or the
+//! script panel.
+//!
+//! A script registers callbacks with `emu.on_input(function(input) ... end)`,
+//! which can change the input the coming frame will see by returning it, and
+//! `emu.on_frame(function() ... end)`, called after each frame. `on_input`
+//! isn't called for the frames a replay plays back. Inside them,
+//! and while the script first runs, it can use:
+//!
+//! - `emu.frame()`
+//! - `memory.read(domain, offset, type)`, `memory.write(domain, offset, value,
+//!   type)`, `memory.read_bytes(domain, offset, len)` and `memory.domains()`,
+//!   with domains named as in the memory panels and types `u8` (the default),
+//!   `i8`, `u16`, `i16`, `u32`, `i32`, `fixed` (20.12) and `f32`
+//! - `bus.read(cpu, addr, type)` and `bus.write(cpu, addr, value, type)`, with
+//!   `cpu` `"arm9"` or `"arm7"`
+//! - `savestate.save()`, which gives back the state as a string, and
+//!   `savestate.load(state)`, which loads it before the next frame's input
+//!   is sampled. As the state carries no replay, a replay being played back
+//!   refuses it, and a recording carries on from where it lands
+//! - `gui.text(screen, x, y, text, color)`, `gui.rect(screen, x, y, w, h,
+//!   color, fill)` and `gui.line(screen, x1, y1, x2, y2, color)`, with
+//!   `screen` `"top"` or `"bottom"` and colors as `0xRRGGBBAA`. What is drawn
+//!   during a frame stays up until the next one.
+
+mod api;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use mlua::{Function, Lua, Table};
+
+use crate::input::BoundaryInput;
+use crate::melon::nds::Nds;
+use crate::overlay::Overlay;
+use crate::render::{RenderContext, RenderHook};
+
+/// One loaded script.
+struct Script {
+    lua: Lua,
+    path: PathBuf,
+    /// What the script has drawn during the frame being run.
+    drawing: Arc<Mutex<Overlay>>,
+}
+
+impl Script {
+    fn load(
+        path: &Path,
+        nds: &mut Nds,
+        state_to_load: Arc<Mutex<Option<Vec<u8>>>>,
+    ) -> mlua::Result<Self> {
+        let source = fs::read_to_string(path).map_err(mlua::Error::external)?;
+        let lua = Lua::new();
+        let drawing = Arc::new(Mutex::new(Overlay::default()));
+        api::install(&lua, drawing.clone(), state_to_load)?;
+
+        let script = Script {
+            lua,
+            path: path.to_owned(),
+            drawing,
+        };
+        script.with_console(nds, |lua| {
+            lua.load(&source).set_name(path.to_string_lossy()).exec()
+        })?;
+
+        Ok(script)
+    }
+
+    /// Runs `f` with the parts of the API that need the console.
+    fn with_console<R>(
+        &self,
+        nds: &mut Nds,
+        f: impl FnOnce(&Lua) -> mlua::Result<R>,
+    ) -> mlua::Result<R> {
+        let nds = RefCell::new(nds);
+        self.lua.scope(|scope| {
+            api::install_scoped(&self.lua, scope, &nds)?;
+            f(&self.lua)
+        })
+    }
+
+    fn on_input(&self, nds: &mut Nds, input: &mut BoundaryInput) -> mlua::Result<()> {
+        let Some(callback) = self
+            .lua
+            .named_registry_value::<Option<Function>>(api::ON_INPUT)?
+        else {
+            return Ok(());
+        };
+
+        self.with_console(nds, |lua| {
+            let changed: Option<Table> = callback.call(api::input_table(lua, input)?)?;
+            match changed {
+                Some(table) => api::read_input_table(&table, input),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn on_frame(&self, nds: &mut Nds) -> mlua::Result<()> {
+        let Some(callback) = self
+            .lua
+            .named_registry_value::<Option<Function>>(api::ON_FRAME)?
+        else {
+            return Ok(());
+        };
+
+        self.drawing.lock().unwrap().clear();
+        self.with_console(nds, |_| callback.call(()))
+    }
+}
+
+/// The script the console is running, if any, and what it last drew.
+///
+/// A script that raises an error is stopped, so it doesn't print the same one
+/// every frame.
+pub struct Scripting {
+    script: Option<Script>,
+    overlay: Arc<Mutex<Overlay>>,
+    /// A savestate the script asked for, for the frontend to load.
+    state_to_load: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl Scripting {
+    pub fn new() -> Self {
+        Scripting {
+            script: None,
+            overlay: Default::default(),
+            state_to_load: Default::default(),
+        }
+    }
+
+    /// Draws what scripts draw, in the window.
+    pub fn render_hook(&self) -> ScriptOverlay {
+        ScriptOverlay(self.overlay.clone())
+    }
+
+    /// Runs the script at `path`, in place of any already running.
+    pub fn load(&mut self, path: &Path, nds: &mut Nds) {
+        self.stop();
+
+        match Script::load(path, nds, self.state_to_load.clone()) {
+            Ok(script) => {
+                println!("running {}", path.display());
+                self.script = Some(script);
+                self.publish();
+            }
+            Err(err) => println!("WARNING: couldn't run {}: {err}", path.display()),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(script) = self.script.take() {
+            println!("stopped {}", script.path.display());
+        }
+        self.overlay.lock().unwrap().clear();
+    }
+
+    /// Lets the script see, and change, the input for the coming frame.
+    pub fn before_frame(&mut self, nds: &mut Nds, mut input: BoundaryInput) -> BoundaryInput {
+        if let Some(script) = &self.script {
+            let result = script.on_input(nds, &mut input);
+            self.check(result);
+        }
+        input
+    }
+
+    /// The savestate the script last asked to load, if it hasn't been yet.
+    pub fn take_savestate(&mut self) -> Option<Vec<u8>> {
+        self.state_to_load.lock().unwrap().take()
+    }
+
+    pub fn after_frame(&mut self, nds: &mut Nds) {
+        if let Some(script) = &self.script {
+            let result = script.on_frame(nds);
+            self.check(result);
+            self.publish();
+        }
+    }
+
+    fn check(&mut self, result: mlua::Result<()>) {
+        if let Err(err) = result {
+            println!("WARNING: script error: {err}");
+            self.stop();
+        }
+    }
+
+    fn publish(&self) {
+        if let Some(script) = &self.script {
+            *self.overlay.lock().unwrap() = script.drawing.lock().unwrap().clone();
+        }
+    }
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The window's view of what scripts draw.
+pub struct ScriptOverlay(Arc<Mutex<Overlay>>);
+
+impl RenderHook for ScriptOverlay {
+    fn on_render(&mut self, _ctx: RenderContext) -> Overlay {
+        self.0.lock().unwrap().clone()
+    }
+}
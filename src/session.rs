@@ -0,0 +1,177 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+use crate::debug::{ExecutionHook, HookId, Hooks};
+use crate::frontend::{Frames, Frontend, ReplayState};
+use crate::input::InputChange;
+use crate::melon::nds::Nds;
+use crate::observe::FrameObserver;
+use crate::replay::{Desync, Replay};
+use crate::run::RunParams;
+
+/// Emulation driven by the caller, one frame at a time, with no window and no
+/// audio device.
+///
+/// Nothing here keeps time: a frame runs when [`Session::step`] is called and
+/// not otherwise, so a session runs as fast as the host allows and behaves the
+/// same on a machine with no display or sound card.
+pub struct Session {
+    frontend: Frontend,
+    frames: watch::Receiver<Arc<Frames>>,
+    hooks: Arc<Mutex<Hooks>>,
+    /// Set when a hook asks for a pause, which only the caller can act on.
+    pause_requested: Arc<AtomicBool>,
+}
+
+impl Session {
+    pub fn new(mut params: RunParams) -> Self {
+        let (frames_tx, frames) = watch::channel(Arc::new(Frames::blank()));
+
+        params.check_replay();
+        let mut nds = params.boot();
+        let pause_requested = Arc::new(AtomicBool::new(false));
+        let hooks = params.attach_hooks(&mut nds, {
+            let pause_requested = pause_requested.clone();
+            move || pause_requested.store(true, Ordering::Relaxed)
+        });
+        let tracer = params.tracer(&hooks);
+
+        let mut frontend = Frontend::new(nds, None, params.key_map, params.replay, frames_tx)
+            .with_checkpoints(params.checkpoints)
+            .with_input_providers(params.input_providers)
+            .with_pacing(params.pacing)
+            .with_tracer(tracer);
+
+        if let Some(stem) = &params.encode {
+            frontend.start_encoding(stem);
+        }
+        if let Some(script) = &params.script {
+            frontend.load_script(script);
+        }
+
+        Session {
+            frontend,
+            frames,
+            hooks,
+            pause_requested,
+        }
+    }
+
+    pub fn with_observers(
+        mut self,
+        observers: impl IntoIterator<Item = Box<dyn FrameObserver>>,
+    ) -> Self {
+        self.frontend = self.frontend.with_observers(observers);
+        self
+    }
+
+    /// Emulates one frame.
+    pub fn step(&mut self) {
+        self.frontend.run_frame();
+    }
+
+    pub fn run_frames(&mut self, count: u64) {
+        for _ in 0..count {
+            self.step();
+        }
+    }
+
+    /// The number of frames emulated so far.
+    pub fn frame(&self) -> u64 {
+        self.frontend.nds.current_frame() as u64
+    }
+
+    /// Both screens as of the last frame.
+    pub fn frames(&self) -> Arc<Frames> {
+        self.frames.borrow().clone()
+    }
+
+    /// The audio produced by the last frame, as interleaved stereo pairs at
+    /// [`Nds::AUDIO_SAMPLE_RATE`].
+    pub fn audio(&self) -> &[[i16; 2]] {
+        self.frontend.samples()
+    }
+
+    pub fn main_ram(&self) -> &[u8] {
+        self.frontend.nds.main_ram()
+    }
+
+    pub fn main_ram_mut(&mut self) -> &mut [u8] {
+        self.frontend.nds.main_ram_mut()
+    }
+
+    /// Queues an input change for the next frame to sample.
+    pub fn input(&mut self, change: InputChange) {
+        self.frontend.queue_input(change);
+    }
+
+    pub fn replay(&self) -> Option<&Replay> {
+        self.frontend.replay().map(|(replay, _)| replay)
+    }
+
+    /// Whether a replay being played back has no inputs left to give.
+    pub fn replay_finished(&self) -> bool {
+        match self.frontend.replay() {
+            Some((replay, ReplayState::Playing)) => self.frame() >= replay.end(),
+            _ => false,
+        }
+    }
+
+    /// Where the replay being played back first stopped matching its
+    /// checkpoints, if it has.
+    pub fn desync(&self) -> Option<Desync> {
+        self.frontend.desync()
+    }
+
+    /// The console's state, to hand back to [`Session::load_savestate`] later.
+    pub fn savestate(&mut self) -> Vec<u8> {
+        self.frontend.nds.savestate()
+    }
+
+    /// Goes back to a state from [`Session::savestate`], as the window does
+    /// with a savestate file. See [`Frontend::load_savestate`].
+    pub fn load_savestate(&mut self, state: &[u8]) -> bool {
+        self.frontend.load_savestate(state)
+    }
+
+    /// Encodes every frame from the next one on to `<stem>.y4m` and `<stem>.wav`.
+    pub fn start_encoding(&mut self, stem: &Path) {
+        self.frontend.start_encoding(stem);
+    }
+
+    /// Finishes writing whatever has been encoded so far.
+    pub fn stop_encoding(&mut self) {
+        self.frontend.stop_encoding();
+    }
+
+    /// Starts calling `hook` from the next frame on.
+    pub fn add_hook(&mut self, hook: ExecutionHook) -> HookId {
+        self.hooks.lock().unwrap().add(hook)
+    }
+
+    pub fn remove_hook(&mut self, id: HookId) {
+        self.hooks.lock().unwrap().remove(id);
+    }
+
+    /// Starts tracing to `path`, or stops the trace running. Needs
+    /// [`RunParams::trace`] set.
+    pub fn toggle_trace(&mut self, path: &Path) {
+        self.frontend.toggle_trace(path);
+    }
+
+    /// Whether a hook has asked for a pause since the last call.
+    pub fn take_pause_request(&mut self) -> bool {
+        self.pause_requested.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn nds(&self) -> &Nds {
+        &self.frontend.nds
+    }
+
+    pub fn nds_mut(&mut self) -> &mut Nds {
+        &mut self.frontend.nds
+    }
+}
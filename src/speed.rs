@@ -0,0 +1,112 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How fast emulation runs, relative to the console.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Speed {
+    Quarter,
+    Half,
+    #[default]
+    Normal,
+    Double,
+    Quadruple,
+    /// As fast as the host can manage.
+    Unlimited,
+}
+
+impl Speed {
+    /// How many emulated frames pass per frame of real time, if the speed is
+    /// capped at all.
+    pub fn multiplier(self) -> Option<f64> {
+        match self {
+            Speed::Quarter => Some(0.25),
+            Speed::Half => Some(0.5),
+            Speed::Normal => Some(1.0),
+            Speed::Double => Some(2.0),
+            Speed::Quadruple => Some(4.0),
+            Speed::Unlimited => None,
+        }
+    }
+
+    /// How long each emulated frame should take, for frames that take `frame`
+    /// at normal speed.
+    pub fn frame_duration(self, frame: Duration) -> Option<Duration> {
+        self.multiplier()
+            .map(|multiplier| frame.div_f64(multiplier))
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.multiplier() {
+            Some(multiplier) => write!(f, "{multiplier}x"),
+            None => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// Decides the speed from the one selected and the state of the turbo
+/// bindings.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SpeedControl {
+    pub selected: Speed,
+    /// The speed either turbo binding switches to.
+    pub turbo: Speed,
+    pub turbo_held: bool,
+    pub turbo_toggled: bool,
+}
+
+impl SpeedControl {
+    pub fn new(turbo: Speed) -> Self {
+        SpeedControl {
+            turbo,
+            ..Default::default()
+        }
+    }
+
+    pub fn current(&self) -> Speed {
+        if self.turbo_held || self.turbo_toggled {
+            self.turbo
+        } else {
+            self.selected
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn faster_speeds_take_less_time_per_frame() {
+        assert_eq!(Speed::Quarter.frame_duration(FRAME), Some(FRAME * 4));
+        assert_eq!(Speed::Normal.frame_duration(FRAME), Some(FRAME));
+        assert_eq!(Speed::Quadruple.frame_duration(FRAME), Some(FRAME / 4));
+        assert_eq!(Speed::Unlimited.frame_duration(FRAME), None);
+    }
+
+    #[test]
+    fn speeds_read_as_multipliers() {
+        assert_eq!(Speed::Quarter.to_string(), "0.25x");
+        assert_eq!(Speed::Double.to_string(), "2x");
+        assert_eq!(Speed::Unlimited.to_string(), "unlimited");
+    }
+
+    #[test]
+    fn either_turbo_binding_overrides_the_selected_speed() {
+        let mut control = SpeedControl::new(Speed::Unlimited);
+        control.selected = Speed::Half;
+        assert_eq!(control.current(), Speed::Half);
+
+        control.turbo_held = true;
+        assert_eq!(control.current(), Speed::Unlimited);
+
+        control.turbo_held = false;
+        control.turbo_toggled = true;
+        assert_eq!(control.current(), Speed::Unlimited);
+    }
+}